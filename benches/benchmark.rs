@@ -2,7 +2,7 @@ use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use hash_db::Hasher;
 use keccak_hasher::KeccakHasher;
 use pchain_types::cryptography::{PublicAddress, Sha256Hash};
-use pchain_world_state::{db::KeyInstrumentedDB, Mpt, MptError, WorldState, V1, V2};
+use pchain_world_state::{db::KeyInstrumentedDB, DbError, Mpt, MptError, WorldState, V1, V2};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use rocksdb::{DBWithThreadMode, MultiThreaded};
 use statrs::statistics::Statistics;
@@ -382,6 +382,12 @@ impl pchain_world_state::DB for TestDB {
             Err(_) => None,
         }
     }
+
+    fn try_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
+        self.db
+            .get(key)
+            .map_err(|err| DbError::ReadError(err.into_string()))
+    }
 }
 
 impl TestDB {
@@ -18,11 +18,18 @@ use pchain_types::cryptography::{PublicAddress, Sha256Hash};
 use crate::{
     db::{KeyInstrumentedDB, DB},
     error::{DecodeOrEncodeError, MptError, TrieKeyBuildError, WorldStateError},
-    mpt::{proof_level, KeyVisibility, Mpt, Proof, WSProofNode},
-    world_state::WorldStateChanges,
+    mpt::{
+        proof_level, Mpt, MptIterator, Proof, ProofAndValues, TrieCursor, ValueChange, WSProofNode,
+    },
+    node_cache::NodeCache,
+    witness::WitnessRecorder,
+    world_state::{FieldDiff, WorldStateChanges},
     Version, VersionProvider, V1, V2,
 };
 
+pub(crate) use crate::keys::account_key;
+pub use crate::keys::AccountField;
+
 /// Struct store external account information in blockchain
 #[derive(Debug, Clone)]
 pub struct AccountsTrie<'a, S, V>
@@ -53,16 +60,30 @@ impl Account {
     pub fn set_storage_hash(&mut self, storage_hash: Vec<u8>) {
         self.storage_hash = storage_hash;
     }
-}
 
-/// `AccountField` prefix to identify the data type belong to [AccountsTrie](crate::accounts::AccountsTrie)
-#[repr(u8)]
-pub(crate) enum AccountField {
-    Nonce = 0,
-    Balance = 1,
-    ContractCode = 2,
-    CbiVersion = 3,
-    StorageHash = 4,
+    /// `set_field` decodes the value stored in trie and set it to the account according to account field
+    fn set_field(&mut self, field: AccountField, value: Vec<u8>) -> Result<(), WorldStateError> {
+        match field {
+            AccountField::Nonce => {
+                self.nonce = u64::from_le_bytes(value.try_into().map_err(|_| {
+                    WorldStateError::DecodeOrEncodeError(DecodeOrEncodeError::DecodeError)
+                })?);
+            }
+            AccountField::Balance => {
+                self.balance = u64::from_le_bytes(value.try_into().map_err(|_| {
+                    WorldStateError::DecodeOrEncodeError(DecodeOrEncodeError::DecodeError)
+                })?);
+            }
+            AccountField::ContractCode => self.code = value,
+            AccountField::CbiVersion => {
+                self.cbi_version = Some(u32::from_le_bytes(value.try_into().map_err(|_| {
+                    WorldStateError::DecodeOrEncodeError(DecodeOrEncodeError::DecodeError)
+                })?));
+            }
+            AccountField::StorageHash => self.set_storage_hash(value),
+        }
+        Ok(())
+    }
 }
 
 impl TryFrom<u8> for AccountField {
@@ -80,6 +101,181 @@ impl TryFrom<u8> for AccountField {
     }
 }
 
+/// `AccountsTrieIterator` is a lazy iterator of the account fields in [AccountsTrie] as (PublicAddress, AccountField, value),
+/// ordered by account address, created by [AccountsTrie::iter]. The iteration stops after the first error.
+pub struct AccountsTrieIterator<'t, 'a, S, V>
+where
+    S: DB + Send + Sync + Clone,
+    V: VersionProvider + Send + Sync + Clone,
+{
+    inner: MptIterator<'t, 'a, S, V>,
+}
+
+impl<'t, 'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
+    AccountsTrieIterator<'t, 'a, S, V>
+{
+    /// `seek` starts the iteration from the first account whose address is equal to or greater than `address`
+    pub fn seek(self, address: &PublicAddress) -> Self {
+        AccountsTrieIterator {
+            inner: self.inner.seek(address),
+        }
+    }
+
+    /// `prefix` limits the iteration to the accounts whose address starts with `address_prefix`
+    pub fn prefix(self, address_prefix: &[u8]) -> Self {
+        AccountsTrieIterator {
+            inner: self.inner.prefix(address_prefix),
+        }
+    }
+
+    /// `resume` starts the iteration after the position of the cursor returned by [AccountsTrieIterator::cursor]
+    pub fn resume(self, cursor: &TrieCursor) -> Self {
+        AccountsTrieIterator {
+            inner: self.inner.resume(cursor),
+        }
+    }
+
+    /// `cursor` returns the position after the last returned item, None if no item is returned yet
+    pub fn cursor(&self) -> Option<TrieCursor> {
+        self.inner.cursor()
+    }
+}
+
+impl<'t, 'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone> Iterator
+    for AccountsTrieIterator<'t, 'a, S, V>
+{
+    type Item = Result<(PublicAddress, AccountField, Vec<u8>), WorldStateError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next()?;
+        Some(
+            item.map_err(WorldStateError::from)
+                .and_then(|(key, value)| {
+                    let address = account_address(&key)?;
+                    let field = account_field::<V>(&key)?;
+                    Ok((address, field, value))
+                }),
+        )
+    }
+}
+
+/// `AccountsIterator` is a lazy iterator of the accounts in [AccountsTrie] ordered by account address, created by
+/// [AccountsTrie::accounts]. It yields every account with all its fields assembled, or the error of its malformed
+/// field, so that a malformed account does not stop the iteration.
+///
+/// The outer error is the failure to read the trie (e.g. state_hash does not exist or missed some trie nodes) or
+/// a malformed account address, after which the iteration stops.
+pub struct AccountsIterator<'t, 'a, S, V>
+where
+    S: DB + Send + Sync + Clone,
+    V: VersionProvider + Send + Sync + Clone,
+{
+    inner: MptIterator<'t, 'a, S, V>,
+    // the first field of the next account, which is read when the previous account ends
+    next_field: Option<AccountFieldEntry>,
+    // key of the last field of the last returned account
+    last_key: Option<Vec<u8>>,
+    done: bool,
+}
+
+/// `AccountFieldEntry` is a field in AccountTrie as (PublicAddress, key, value)
+type AccountFieldEntry = (PublicAddress, Vec<u8>, Vec<u8>);
+
+/// `ProofAndAccountFields` is a proof of a range of account addresses, with all the account fields in the range
+/// as (PublicAddress, AccountField, value) ordered by account address
+pub type ProofAndAccountFields = (Proof, Vec<(PublicAddress, AccountField, Vec<u8>)>);
+
+/// `AccountsPage` is a page of accounts returned by [AccountsTrie::accounts_page]
+#[derive(Debug, Clone)]
+pub struct AccountsPage {
+    /// accounts ordered by account address
+    pub accounts: Vec<(PublicAddress, Account)>,
+    /// cursor to get the next page, None if this is the last page
+    pub next_cursor: Option<TrieCursor>,
+}
+
+impl<'t, 'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
+    AccountsIterator<'t, 'a, S, V>
+{
+    /// `seek` starts the iteration from the first account whose address is equal to or greater than `address`
+    pub fn seek(mut self, address: &PublicAddress) -> Self {
+        self.inner = self.inner.seek(address);
+        self.last_key = None;
+        self
+    }
+
+    /// `resume` starts the iteration after the account at the position of the cursor returned by [AccountsIterator::cursor]
+    pub fn resume(mut self, cursor: &TrieCursor) -> Self {
+        self.inner = self.inner.resume(cursor);
+        self.last_key = Some(cursor.as_bytes().to_vec());
+        self
+    }
+
+    /// `cursor` returns the position after the last returned account, None if no account is returned yet
+    pub fn cursor(&self) -> Option<TrieCursor> {
+        self.last_key.clone().map(TrieCursor::from_bytes)
+    }
+
+    /// `next_field` returns the next field in the trie
+    fn next_field(&mut self) -> Option<Result<AccountFieldEntry, WorldStateError>> {
+        if let Some(field) = self.next_field.take() {
+            return Some(Ok(field));
+        }
+        let item = match self.inner.next()? {
+            Ok((key, value)) => account_address(&key)
+                .map(|address| (address, key, value))
+                .map_err(WorldStateError::from),
+            Err(err) => Err(err.into()),
+        };
+        Some(item)
+    }
+}
+
+impl<'t, 'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone> Iterator
+    for AccountsIterator<'t, 'a, S, V>
+{
+    type Item = Result<(PublicAddress, Result<Account, WorldStateError>), WorldStateError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut current = None;
+        let mut account = Ok(Account::default());
+        loop {
+            let (address, key, value) = match self.next_field() {
+                Some(Ok(field)) => field,
+                Some(Err(err)) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+                None => {
+                    self.done = true;
+                    break;
+                }
+            };
+            match current {
+                Some(current) if current != address => {
+                    self.next_field = Some((address, key, value));
+                    break;
+                }
+                _ => current = Some(address),
+            }
+            // keep the error of the first malformed field of the account
+            if let Ok(fields) = &mut account {
+                if let Err(err) = account_field::<V>(&key)
+                    .map_err(WorldStateError::from)
+                    .and_then(|field| fields.set_field(field, value))
+                {
+                    account = Err(err);
+                }
+            }
+            self.last_key = Some(key);
+        }
+        current.map(|address| Ok((address, account)))
+    }
+}
+
 /// interfaces can be called by outside user
 impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     AccountsTrie<'a, S, V>
@@ -98,7 +294,8 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
 
     /// `nonce_with_proof` is return the nonce with proof of given account address
     ///
-    /// (empty vector, 0) if the account address is not found in world state
+    /// (proof of absence, 0) if the account address is not found in world state.
+    /// Use [AccountsTrie::absence_proof] to tell an absent field from a field set to 0
     ///
     /// Error if state_hash does not exist or missed some trie nodes
     pub fn nonce_with_proof(&self, address: &PublicAddress) -> Result<(Proof, u64), MptError> {
@@ -124,7 +321,8 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
 
     /// `balance_with_proof` is return the balance with proof of given account address
     ///
-    /// (empty vector, 0) if the account address is not found in world state
+    /// (proof of absence, 0) if the account address is not found in world state.
+    /// Use [AccountsTrie::absence_proof] to tell an absent field from a field set to 0
     ///
     /// Error if state_hash does not exist or missed some trie nodes
     pub fn balance_with_proof(&self, address: &PublicAddress) -> Result<(Proof, u64), MptError> {
@@ -148,7 +346,7 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
 
     /// `code_with_proof` is return the code with proof of given account address
     ///
-    /// (empty vector, empty vector) if the account address is not found in world state
+    /// (proof of absence, None) if the account address is not found in world state
     ///
     /// Error if state_hash does not exist or missed some trie nodes
     pub fn code_with_proof(
@@ -173,7 +371,7 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
 
     /// `cbi_version_with_proof` is return the cbi_version with proof of given account address
     ///
-    /// (empty vector, None) if the account address is not found in world state
+    /// (proof of absence, None) if the account address is not found in world state
     ///
     /// Error if state_hash does not exist or missed some trie nodes
     pub fn cbi_version_with_proof(
@@ -202,7 +400,7 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
 
     /// `storage_hash` is return the storage_hash with proof of given account address
     ///
-    /// (empty vector, empty vector) if the account address is not found in world state
+    /// (proof of absence, None) if the account address is not found in world state
     ///
     /// Error if state_hash does not exist or missed some trie nodes
     pub fn storage_hash_with_proof(
@@ -217,6 +415,91 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
             })
     }
 
+    /// `absence_proof` is return the proof that the account field of given account address is not found in world state,
+    /// which can be verified by [verify_account_field_absence](crate::proof::verify_account_field_absence).
+    ///
+    /// None if the account field is found in world state
+    ///
+    /// Error if state_hash does not exist or missed some trie nodes
+    pub fn absence_proof(
+        &self,
+        address: &PublicAddress,
+        field: AccountField,
+    ) -> Result<Option<Proof>, MptError> {
+        let key = account_key::<V>(address, field);
+        self.get_with_proof_from_trie_key(&key)
+            .map(|(proof, value)| match value {
+                Some(_) => None,
+                None => Some(proof),
+            })
+    }
+
+    /// `fields_with_proof` is return the values of the account fields in the same order as the fields,
+    /// with one proof for all of them, which can be verified by [verify_account_fields_proof](crate::proof::verify_account_fields_proof).
+    ///
+    /// The values are the bytes stored in the trie, None for the fields not found in world state
+    ///
+    /// Error if state_hash does not exist or missed some trie nodes
+    pub fn fields_with_proof(
+        &self,
+        fields: &[(PublicAddress, AccountField)],
+    ) -> Result<ProofAndValues, MptError> {
+        let keys: Vec<Vec<u8>> = fields
+            .iter()
+            .map(|(address, field)| account_key::<V>(address, *field))
+            .collect();
+        self.trie
+            .batch_get_with_proof(&keys)
+            .map(|(proof, values)| {
+                let proof = proof
+                    .into_iter()
+                    .map(|node| WSProofNode::new(proof_level::ACCOUNTS, node).into())
+                    .collect();
+                (proof, values)
+            })
+    }
+
+    /// `range_with_proof` is to return all the account fields of the account addresses in `start..end` ordered by account address,
+    /// with the proof that none of them is omitted, which can be verified by
+    /// [verify_accounts_range_proof](crate::proof::verify_accounts_range_proof).
+    /// The range is not bounded above if `end` is None.
+    ///
+    /// Error if state_hash does not exist or missed some trie nodes
+    pub fn range_with_proof(
+        &self,
+        start: &PublicAddress,
+        end: Option<&PublicAddress>,
+    ) -> Result<ProofAndAccountFields, WorldStateError> {
+        // every account key starts with the account address
+        let (proof, items) = self
+            .trie
+            .range_with_proof(start, end.map(|end| end.as_slice()))?;
+        let proof = proof
+            .into_iter()
+            .map(|node| WSProofNode::new(proof_level::ACCOUNTS, node).into())
+            .collect();
+        let items = items
+            .into_iter()
+            .map(|(key, value)| Ok((account_address(&key)?, account_field::<V>(&key)?, value)))
+            .collect::<Result<_, WorldStateError>>()?;
+        Ok((proof, items))
+    }
+
+    /// `diff` is return the changes of all the account fields from the state root of this AccountsTrie to `other_state_root`,
+    /// as (PublicAddress, AccountField, change) ordered by account address. Subtrees with the same hash in both tries are skipped.
+    ///
+    /// Error if either state root does not exist or missed some trie nodes
+    pub fn diff(
+        &self,
+        other_state_root: Sha256Hash,
+    ) -> Result<Vec<(PublicAddress, AccountField, ValueChange)>, WorldStateError> {
+        self.trie
+            .diff(other_state_root)?
+            .into_iter()
+            .map(|(key, change)| Ok((account_address(&key)?, account_field::<V>(&key)?, change)))
+            .collect()
+    }
+
     /// `all` is to iterator all Account information in AccountTrie
     ///
     /// Return a iterator of (PublicAddress, Account)
@@ -224,48 +507,83 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     /// Error if state_hash does not exist or missed some trie nodes
     pub fn all(&self) -> Result<HashMap<PublicAddress, Account>, WorldStateError> {
         let mut ret_map: HashMap<PublicAddress, Account> = HashMap::new();
+        for item in self.accounts() {
+            let (address, account) = item?;
+            ret_map.insert(address, account?);
+        }
+        Ok(ret_map)
+    }
 
-        self.trie.iterate_all(|key, value| {
-            // Get the account address and the field from the key
-            let account_address = account_address(&key)?;
+    /// `iter` returns a lazy iterator of the account fields in AccountTrie ordered by account address
+    pub fn iter(&self) -> AccountsTrieIterator<'_, 'a, S, V> {
+        AccountsTrieIterator {
+            inner: self.trie.iter(),
+        }
+    }
 
-            let account_field = account_field::<V>(&key)?;
+    /// `accounts` returns a lazy iterator of the accounts in AccountTrie ordered by account address
+    pub fn accounts(&self) -> AccountsIterator<'_, 'a, S, V> {
+        AccountsIterator {
+            inner: self.trie.iter(),
+            next_field: None,
+            last_key: None,
+            done: false,
+        }
+    }
 
-            // Get mutable reference to the account from the account map.
-            let account_value = match ret_map.get_mut(&account_address) {
-                Some(account) => account,
-                None => {
-                    ret_map.insert(account_address, Account::default());
-                    ret_map.get_mut(&account_address).unwrap()
-                }
-            };
+    /// `accounts_page` returns at most `limit` accounts ordered by account address, starting after the cursor
+    /// of the previous page, or from the first account if the cursor is None.
+    ///
+    /// The page is empty if `limit` is 0, and its next_cursor is the position of the given cursor, or the position
+    /// before the first account if the cursor is None. It is None only if there is no account after the position.
+    ///
+    /// Error if state_hash does not exist, missed some trie nodes or any account in the page is malformed
+    pub fn accounts_page(
+        &self,
+        cursor: Option<&TrieCursor>,
+        limit: usize,
+    ) -> Result<AccountsPage, WorldStateError> {
+        let mut iter = self.accounts();
+        if let Some(cursor) = cursor {
+            iter = iter.resume(cursor);
+        }
+        let mut accounts = Vec::with_capacity(limit);
+        for item in iter.by_ref().take(limit) {
+            let (address, account) = item?;
+            accounts.push((address, account?));
+        }
+        // an empty key is the position before the first account, as no account key is empty
+        let next_cursor = iter
+            .cursor()
+            .unwrap_or_else(|| TrieCursor::from_bytes(Vec::new()));
+        let next_cursor = iter.next().map(|_| next_cursor);
+        Ok(AccountsPage {
+            accounts,
+            next_cursor,
+        })
+    }
 
-            // Set the account according to account field
-            match account_field {
-                AccountField::Nonce => {
-                    account_value.nonce = u64::from_le_bytes(value.try_into().map_err(|_| {
-                        WorldStateError::DecodeOrEncodeError(DecodeOrEncodeError::DecodeError)
-                    })?);
-                }
-                AccountField::Balance => {
-                    account_value.balance = u64::from_le_bytes(value.try_into().map_err(|_| {
-                        WorldStateError::DecodeOrEncodeError(DecodeOrEncodeError::DecodeError)
-                    })?);
-                }
-                AccountField::ContractCode => account_value.code = value,
-                AccountField::CbiVersion => {
-                    account_value.cbi_version =
-                        Some(u32::from_le_bytes(value.try_into().map_err(|_| {
-                            WorldStateError::DecodeOrEncodeError(DecodeOrEncodeError::DecodeError)
-                        })?));
-                }
-                AccountField::StorageHash => account_value.set_storage_hash(value),
+    /// `account` is return all the fields of given account address, which are read from the trie in one batch
+    ///
+    /// None if the account address is not found in world state
+    ///
+    /// Error if state_hash does not exist or missed some trie nodes
+    pub fn account(&self, address: &PublicAddress) -> Result<Option<Account>, WorldStateError> {
+        let keys: Vec<Vec<u8>> = AccountField::ALL
+            .iter()
+            .map(|field| account_key::<V>(address, *field))
+            .collect();
+        let values = self.trie.batch_get(&keys)?;
+        if values.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+        let mut account = Account::default();
+        for (field, value) in AccountField::ALL.into_iter().zip(values) {
+            if let Some(value) = value {
+                account.set_field(field, value)?;
             }
-
-            Ok::<(), WorldStateError>(())
-        })?;
-
-        Ok(ret_map)
+        }
+        Ok(Some(account))
     }
 
     /// `contains_nonce` is to check if account field `Nonce` exists in the world state
@@ -377,6 +695,11 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         AccountsTrie { trie }
     }
 
+    /// `root_hash` called by [WorldState](crate::world_state::WorldState) to get the state root of the current trie
+    pub(crate) fn root_hash(&self) -> Sha256Hash {
+        self.trie.root_hash()
+    }
+
     /// `set_storage_hash` called by [WorldState](crate::world_state::WorldState) to set account storage_hash
     pub(crate) fn set_storage_hash(
         &mut self,
@@ -388,14 +711,86 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         self.trie.set(&storage_hash_key, value)
     }
 
+    /// `set_node_cache` called by [WorldState](crate::world_state::WorldState) to set the [NodeCache] of the trie
+    pub(crate) fn set_node_cache(&mut self, node_cache: Option<NodeCache>) {
+        self.trie.set_node_cache(node_cache);
+    }
+
+    /// `set_witness_recorder` called by [WorldState](crate::world_state::WorldState) to set the [WitnessRecorder] of the trie
+    pub(crate) fn set_witness_recorder(&mut self, witness_recorder: Option<WitnessRecorder>) {
+        self.trie.set_witness_recorder(witness_recorder);
+    }
+
+    /// `set_value_recording` called by [WorldState](crate::world_state::WorldState) to enable or disable recording the changes of the values
+    pub(crate) fn set_value_recording(&mut self, enabled: bool) {
+        self.trie.set_value_recording(enabled);
+    }
+
+    /// `field_diffs` called by [WorldState](crate::world_state::WorldState) to return the values before and after of every field
+    /// of the accounts whose fields are changed since the recording is enabled, ordered by account address
+    pub(crate) fn field_diffs(
+        &self,
+    ) -> Result<Vec<(PublicAddress, Vec<FieldDiff>)>, WorldStateError> {
+        let mut changes: HashMap<Vec<u8>, ValueChange> =
+            self.trie.changed_values()?.into_iter().collect();
+        let mut addresses = changes
+            .keys()
+            .map(|key| account_address(key.as_slice()))
+            .collect::<Result<Vec<PublicAddress>, TrieKeyBuildError>>()?;
+        addresses.sort();
+        addresses.dedup();
+
+        let keys: Vec<Vec<u8>> = addresses
+            .iter()
+            .flat_map(|address| AccountField::ALL.map(|field| account_key::<V>(address, field)))
+            .collect();
+        let values = self.trie.batch_get(&keys)?;
+        let num_fields = AccountField::ALL.len();
+        let field_diffs = addresses
+            .into_iter()
+            .zip(keys.chunks(num_fields).zip(values.chunks(num_fields)))
+            .map(|(address, (keys, values))| {
+                let fields = AccountField::ALL
+                    .into_iter()
+                    .zip(keys.iter().zip(values))
+                    .map(|(field, (key, after))| {
+                        let before = match changes.remove(key) {
+                            Some(ValueChange::Added(_)) => None,
+                            Some(ValueChange::Removed(old)) => Some(old),
+                            Some(ValueChange::Changed { old, .. }) => Some(old),
+                            None => after.clone(),
+                        };
+                        FieldDiff {
+                            field,
+                            before,
+                            after: after.clone(),
+                        }
+                    })
+                    .collect();
+                (address, fields)
+            })
+            .collect();
+        Ok(field_diffs)
+    }
+
+    /// `checkpoint` called by [WorldState](crate::world_state::WorldState) to add a checkpoint at the current state of the trie
+    pub(crate) fn checkpoint(&mut self) {
+        self.trie.checkpoint();
+    }
+
+    /// `revert_to_checkpoint` called by [WorldState](crate::world_state::WorldState) to revert the trie to the checkpoint of the index
+    pub(crate) fn revert_to_checkpoint(&mut self, index: usize) {
+        self.trie.revert_to_checkpoint(index);
+    }
+
+    /// `discard_checkpoint` called by [WorldState](crate::world_state::WorldState) to remove the checkpoint of the index and the later ones
+    pub(crate) fn discard_checkpoint(&mut self, index: usize) {
+        self.trie.discard_checkpoint(index);
+    }
+
     /// `close` called by [WorldState](crate::world_state::WorldState) return all cached updates in AccountTrie and updated root_hash of AccountTrie
     pub(crate) fn close(&mut self) -> WorldStateChanges {
-        let mpt_changes = self.trie.close();
-        WorldStateChanges {
-            inserts: mpt_changes.0,
-            deletes: mpt_changes.1,
-            new_root_hash: mpt_changes.2,
-        }
+        self.trie.close().into()
     }
 }
 
@@ -417,30 +812,7 @@ impl<'a, S: DB + Send + Sync + Clone> AccountsTrie<'a, S, crate::V1> {
                     data_map.get_mut(&account_address).unwrap()
                 }
             };
-            match account_field {
-                AccountField::Nonce => {
-                    account.nonce = u64::from_le_bytes(value.try_into().map_err(|_| {
-                        WorldStateError::DecodeOrEncodeError(DecodeOrEncodeError::DecodeError)
-                    })?);
-                }
-                AccountField::Balance => {
-                    account.balance = u64::from_le_bytes(value.try_into().map_err(|_| {
-                        WorldStateError::DecodeOrEncodeError(DecodeOrEncodeError::DecodeError)
-                    })?);
-                }
-                AccountField::ContractCode => {
-                    account.code = value;
-                }
-                AccountField::CbiVersion => {
-                    account.cbi_version =
-                        Some(u32::from_le_bytes(value.try_into().map_err(|_| {
-                            WorldStateError::DecodeOrEncodeError(DecodeOrEncodeError::DecodeError)
-                        })?));
-                }
-                AccountField::StorageHash => {
-                    account.set_storage_hash(value);
-                }
-            }
+            account.set_field(account_field, value)?;
             Ok::<(), WorldStateError>(())
         })?;
         // destroy all account field info
@@ -480,34 +852,6 @@ impl<'a, S: DB + Send + Sync + Clone> AccountsTrie<'a, S, crate::V1> {
     }
 }
 
-/// `account_key` is to create the key for [AccountsTrie](crate::accounts_trie::AccountsTrie)
-///
-/// V1 AccountTrie Key is in form PublicAddress + KeyVisibility + AccountField
-///
-/// V2 AccountTrie Key is in form PublicAddress + AccountField
-pub(crate) fn account_key<V: VersionProvider>(
-    address: &PublicAddress,
-    account_field: AccountField,
-) -> Vec<u8> {
-    match <V>::version() {
-        Version::V1 => {
-            let mut account_key: Vec<u8> =
-                Vec::with_capacity(size_of::<PublicAddress>() + size_of::<u8>() + size_of::<u8>());
-            account_key.extend_from_slice(address);
-            account_key.push(KeyVisibility::Protected as u8);
-            account_key.push(account_field as u8);
-            account_key
-        }
-        Version::V2 => {
-            let mut account_key: Vec<u8> =
-                Vec::with_capacity(size_of::<PublicAddress>() + size_of::<u8>());
-            account_key.extend_from_slice(address);
-            account_key.push(account_field as u8);
-            account_key
-        }
-    }
-}
-
 /// `account_field` is to seperate the AccountField from [AccountsTrie](crate::accounts_trie::AccountsTrie) Key
 pub(crate) fn account_field<V: VersionProvider>(
     key: &[u8],
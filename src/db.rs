@@ -3,19 +3,83 @@
     Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
 */
 
-//! This mod is only public inside crate except [DB]. Provides struct and implementation of database operations
+//! This mod is only public inside crate except [DB], [DBWrite], [WriteBatch] and [MemoryDB]. Provides struct and implementation of database operations
 
 use std::{
     collections::{HashMap, HashSet},
     marker::PhantomData,
+    sync::{Arc, RwLock},
 };
 
-use crate::{Version, VersionProvider, V1, V2};
+use crate::{
+    error::DbError, node_cache::NodeCache, witness::WitnessRecorder,
+    world_state::WorldStateChanges, Version, VersionProvider, V1, V2,
+};
 
 /// Define the methods that a type must implemented to be used as a persistent storage inside WorldState.
 /// The method `get` must be implemented in order to open the Trie.
 pub trait DB {
     fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+
+    /// `try_get` is the fallible version of `get`, which is used by WorldState to read the storage.
+    /// Databases that can fail to read (e.g. I/O error) should implement it to return the error,
+    /// so that it is not mistaken as a missing trie node.
+    fn try_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
+        Ok(self.get(key))
+    }
+
+    /// `multi_get` reads the values of multiple keys and returns them in the same order as the keys.
+    /// Databases supporting batched reads should implement it, otherwise each key is read by `try_get`.
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, DbError> {
+        keys.iter().map(|key| self.try_get(key)).collect()
+    }
+}
+
+/// Define the methods that a type must implemented to let WorldState write its changes into the persistent storage,
+/// e.g. by [WorldState::commit](crate::world_state::WorldState::commit).
+pub trait DBWrite: DB {
+    /// `write` commits all deletes and inserts of the batch atomically. Deletes are applied before inserts.
+    fn write(&self, batch: WriteBatch) -> Result<(), DbError>;
+}
+
+/// `WriteBatch` is a set of inserts and deletes of physical keys to be committed into [DBWrite] at once
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatch {
+    pub inserts: HashMap<Vec<u8>, Vec<u8>>,
+    pub deletes: HashSet<Vec<u8>>,
+}
+
+impl WriteBatch {
+    /// `new` is to create an empty WriteBatch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `put` adds an insert of `<key, value>` and cancels previous delete of the key in the batch
+    pub fn put(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.deletes.remove(&key);
+        self.inserts.insert(key, value);
+    }
+
+    /// `delete` adds a delete of the key and cancels previous insert of the key in the batch
+    pub fn delete(&mut self, key: Vec<u8>) {
+        self.inserts.remove(&key);
+        self.deletes.insert(key);
+    }
+
+    /// `is_empty` checks if the batch contains no insert and no delete
+    pub fn is_empty(&self) -> bool {
+        self.inserts.is_empty() && self.deletes.is_empty()
+    }
+}
+
+impl From<WorldStateChanges> for WriteBatch {
+    fn from(changes: WorldStateChanges) -> Self {
+        WriteBatch {
+            inserts: changes.inserts,
+            deletes: changes.deletes,
+        }
+    }
 }
 
 /// `KeyInstrumentedDB` is a wrapper around implementations of 'DB' that enforces
@@ -34,9 +98,19 @@ where
     deletes: HashSet<Vec<u8>>,
     // for AccoutTrie is None, for StorageTrie is PublicAddress
     prefix: Vec<u8>,
+    // trie nodes read from persistent storage are cached in node_cache if it is set
+    node_cache: Option<NodeCache>,
+    // trie nodes read from persistent storage or node_cache are recorded if witness_recorder is set
+    witness_recorder: Option<WitnessRecorder>,
+    // previous states of the keys written since the journal is started, to revert the writes
+    journal: Option<Vec<JournalEntry>>,
     _type: PhantomData<V>,
 }
 
+/// `JournalEntry` is the state of a physical key in [KeyInstrumentedDB] before a write,
+/// as (physical key, value in `inserts`, whether it is in `deletes`)
+type JournalEntry = (Vec<u8>, Option<Vec<u8>>, bool);
+
 /// `DbChanges` is a wrapper of changes in [KeyInstrumentedDB] when call function close()
 #[derive(Debug, Clone)]
 pub(crate) struct DbChanges(
@@ -54,35 +128,121 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
             inserts: HashMap::new(),
             deletes: HashSet::new(),
             prefix,
+            node_cache: None,
+            witness_recorder: None,
+            journal: None,
             _type: PhantomData,
         }
     }
 
+    /// `set_node_cache` sets the [NodeCache] consulted before reading from the persistent storage
+    pub(crate) fn set_node_cache(&mut self, node_cache: Option<NodeCache>) {
+        self.node_cache = node_cache;
+    }
+
+    /// `set_witness_recorder` sets the [WitnessRecorder] recording the values read from the persistent storage
+    pub(crate) fn set_witness_recorder(&mut self, witness_recorder: Option<WitnessRecorder>) {
+        self.witness_recorder = witness_recorder;
+    }
+
     /// `unsafe_new` is contructor of KeyInstrumentedDB for benchmark test
     pub fn unsafe_new(storage: &'a S, prefix: Vec<u8>) -> KeyInstrumentedDB<S, V> {
         Self::new(storage, prefix)
     }
 
     /// `get` is return value from memory cache `inserts` by input key
-    pub(crate) fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+    ///
+    /// Error when failed to read from the persistent storage
+    pub(crate) fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
         let search_key = self.build_key(key);
         match self.inserts.get(&search_key) {
-            Some(value) => Some(value.to_owned()),
+            Some(value) => Ok(Some(value.to_owned())),
             None => {
                 if self.deletes.contains(&search_key) {
-                    return None;
+                    return Ok(None);
                 }
-                if let Some(value) = self.storage.get(&search_key) {
-                    return Some(value);
+                let value = self.read_storage(&search_key)?;
+                if let (Some(witness_recorder), Some(value)) = (&self.witness_recorder, &value) {
+                    witness_recorder.record(&search_key, value);
                 }
-                None
+                Ok(value)
             }
         }
     }
 
+    /// `read_storage` is return value by physical key from node cache, or from the persistent storage if not cached
+    fn read_storage(&self, search_key: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
+        let node_cache = match &self.node_cache {
+            Some(node_cache) => node_cache,
+            None => return self.storage.try_get(search_key),
+        };
+        if let Some(value) = node_cache.get(search_key) {
+            return Ok(Some(value));
+        }
+        let generation = node_cache.generation();
+        let value = self.storage.try_get(search_key)?;
+        if let Some(value) = &value {
+            node_cache.insert_at_generation(generation, search_key.to_vec(), value.clone());
+        }
+        Ok(value)
+    }
+
+    /// `multi_get` is return values by input keys in the same order as the keys. Values not found in memory cache
+    /// `inserts` or `deletes` are read from the persistent storage in one batch.
+    ///
+    /// Error when failed to read from the persistent storage
+    pub(crate) fn multi_get(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, DbError> {
+        let search_keys: Vec<Vec<u8>> = keys.iter().map(|key| self.build_key(key)).collect();
+        let mut values = Vec::with_capacity(search_keys.len());
+        let mut storage_indexes = Vec::new();
+        for (index, search_key) in search_keys.iter().enumerate() {
+            values.push(self.inserts.get(search_key).cloned());
+            if values[index].is_some() || self.deletes.contains(search_key) {
+                continue;
+            }
+            if let Some(node_cache) = &self.node_cache {
+                values[index] = node_cache.get(search_key);
+            }
+            if values[index].is_none() {
+                storage_indexes.push(index);
+            }
+        }
+        if !storage_indexes.is_empty() {
+            let storage_keys: Vec<&[u8]> = storage_indexes
+                .iter()
+                .map(|index| search_keys[*index].as_slice())
+                .collect();
+            let generation = self.node_cache.as_ref().map(NodeCache::generation);
+            let storage_values = self.storage.multi_get(&storage_keys)?;
+            for (index, value) in storage_indexes.into_iter().zip(storage_values) {
+                if let (Some(node_cache), Some(generation), Some(value)) =
+                    (&self.node_cache, generation, &value)
+                {
+                    node_cache.insert_at_generation(
+                        generation,
+                        search_keys[index].clone(),
+                        value.clone(),
+                    );
+                }
+                values[index] = value;
+            }
+        }
+        if let Some(witness_recorder) = &self.witness_recorder {
+            for (search_key, value) in search_keys.iter().zip(values.iter()) {
+                if let Some(value) = value {
+                    if !self.inserts.contains_key(search_key) {
+                        witness_recorder.record(search_key, value);
+                    }
+                }
+            }
+        }
+        Ok(values)
+    }
+
     /// `put` add input `<key, value>` into memory cache `inserts` and remove input key from memory cache `deletes`
     pub(crate) fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Option<Vec<u8>> {
         let insert_key = self.build_key(&key);
+        self.record_journal(&insert_key);
         self.deletes.remove(&insert_key);
         self.inserts.insert(insert_key, value)
     }
@@ -90,10 +250,59 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     /// `delete` remove `<key, value`> from memory cache `inserts` by input key, and add the input key into memory cache `deletes`
     pub(crate) fn delete(&mut self, key: Vec<u8>) {
         let delete_key = self.build_key(&key);
+        self.record_journal(&delete_key);
         self.inserts.remove(&delete_key);
         self.deletes.insert(delete_key);
     }
 
+    /// `start_journal` starts recording the writes to memory cache `inserts` and `deletes`, so that they can be reverted
+    pub(crate) fn start_journal(&mut self) {
+        if self.journal.is_none() {
+            self.journal = Some(Vec::new());
+        }
+    }
+
+    /// `journal_len` is the number of writes recorded in the journal, which is the position to revert to
+    pub(crate) fn journal_len(&self) -> usize {
+        self.journal.as_ref().map_or(0, |journal| journal.len())
+    }
+
+    /// `revert_journal` reverts the writes recorded after the position `len` of the journal, in the reverse order
+    pub(crate) fn revert_journal(&mut self, len: usize) {
+        let journal = match &mut self.journal {
+            Some(journal) => journal,
+            None => return,
+        };
+        while journal.len() > len {
+            let (key, insert, deleted) = journal.pop().unwrap();
+            match insert {
+                Some(value) => self.inserts.insert(key.clone(), value),
+                None => self.inserts.remove(&key),
+            };
+            if deleted {
+                self.deletes.insert(key);
+            } else {
+                self.deletes.remove(&key);
+            }
+        }
+    }
+
+    /// `stop_journal` stops recording the writes and drops the journal
+    pub(crate) fn stop_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// `record_journal` records the state of the physical key before it is written, if the journal is started
+    fn record_journal(&mut self, key: &[u8]) {
+        if let Some(journal) = &mut self.journal {
+            journal.push((
+                key.to_vec(),
+                self.inserts.get(key).cloned(),
+                self.deletes.contains(key),
+            ));
+        }
+    }
+
     /// `close` return memory cache `inserts` and `deletes`
     pub(crate) fn close(&mut self) -> DbChanges {
         let inserts = self.inserts.clone();
@@ -137,7 +346,118 @@ impl<'a, S: DB + Send + Sync + Clone> KeyInstrumentedDB<'a, S, V1> {
             inserts: self.inserts,
             deletes: self.deletes,
             prefix: self.prefix,
+            node_cache: self.node_cache,
+            witness_recorder: self.witness_recorder,
+            journal: None,
             _type: PhantomData,
         }
     }
 }
+
+/// `MemoryDB` is an in-memory implementation of [DB] for tooling, simulations and tests.
+///
+/// Cloning a MemoryDB returns a handle to the same underlying storage, so changes applied through
+/// one handle are visible through the others. [MemoryDB::snapshot] returns an independent copy instead.
+/// Both are cheap: the key-value map is shared and only copied when it is written while a snapshot still refers to it.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryDB {
+    data: Arc<RwLock<Arc<MemoryDBData>>>,
+}
+
+type MemoryDBData = HashMap<Vec<u8>, Vec<u8>>;
+
+/// `MemoryDBStats` is the size statistics of a [MemoryDB]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryDBStats {
+    /// number of stored keys
+    pub num_keys: usize,
+    /// total length in bytes of all stored keys
+    pub key_bytes: usize,
+    /// total length in bytes of all stored values
+    pub value_bytes: usize,
+}
+
+impl MemoryDBStats {
+    /// `total_bytes` is the total length in bytes of all stored keys and values
+    pub fn total_bytes(&self) -> usize {
+        self.key_bytes + self.value_bytes
+    }
+}
+
+impl MemoryDB {
+    /// `new` is to create an empty MemoryDB
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `apply_changes` applies the deletes and then the inserts of [WorldStateChanges] atomically.
+    /// Readers never observe a partially applied change set.
+    ///
+    /// It does not invalidate a [NodeCache] shared by the WorldStates on this database. Use
+    /// [NodeCache::apply_changes] instead if the database is read through a NodeCache.
+    pub fn apply_changes(&self, changes: WorldStateChanges) {
+        self.apply_batch(changes.into());
+    }
+
+    fn apply_batch(&self, batch: WriteBatch) {
+        let mut data = self.data.write().unwrap();
+        let map = Arc::make_mut(&mut data);
+        for key in batch.deletes.iter() {
+            map.remove(key);
+        }
+        map.extend(batch.inserts);
+    }
+
+    /// `snapshot` returns an independent MemoryDB holding the current content.
+    /// Later changes to either of them are not visible to the other.
+    pub fn snapshot(&self) -> MemoryDB {
+        let data = self.data.read().unwrap().clone();
+        MemoryDB {
+            data: Arc::new(RwLock::new(data)),
+        }
+    }
+
+    /// `len` is the number of stored keys
+    pub fn len(&self) -> usize {
+        self.data.read().unwrap().len()
+    }
+
+    /// `is_empty` checks if nothing is stored
+    pub fn is_empty(&self) -> bool {
+        self.data.read().unwrap().is_empty()
+    }
+
+    /// `stats` returns the size statistics of the stored content
+    pub fn stats(&self) -> MemoryDBStats {
+        let data = self.data.read().unwrap();
+        data.iter().fold(
+            MemoryDBStats {
+                num_keys: data.len(),
+                ..Default::default()
+            },
+            |mut stats, (key, value)| {
+                stats.key_bytes += key.len();
+                stats.value_bytes += value.len();
+                stats
+            },
+        )
+    }
+}
+
+impl DB for MemoryDB {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.data.read().unwrap().get(key).cloned()
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, DbError> {
+        let data = self.data.read().unwrap();
+        Ok(keys.iter().map(|key| data.get(*key).cloned()).collect())
+    }
+}
+
+impl DBWrite for MemoryDB {
+    fn write(&self, batch: WriteBatch) -> Result<(), DbError> {
+        self.apply_batch(batch);
+        Ok(())
+    }
+}
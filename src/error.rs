@@ -7,12 +7,20 @@
 
 use std::fmt::{self};
 
+pub use crate::verify::ProofError;
+
 /// `WorldStateError is wraper of errors triggled inside crate`
 #[derive(Debug)]
 pub enum WorldStateError {
     MptError(MptError),
     TrieKeyBuildError(TrieKeyBuildError),
     DecodeOrEncodeError(DecodeOrEncodeError),
+    DbError(DbError),
+    StateRootRegistryError(StateRootRegistryError),
+    ProofError(ProofError),
+    OverlayError(OverlayError),
+    /// The [Checkpoint](crate::world_state::Checkpoint) is already reverted or discarded, or the WorldState is closed after it
+    InvalidCheckpoint,
 }
 
 impl From<MptError> for WorldStateError {
@@ -33,6 +41,30 @@ impl From<DecodeOrEncodeError> for WorldStateError {
     }
 }
 
+impl From<DbError> for WorldStateError {
+    fn from(error: DbError) -> Self {
+        Self::DbError(error)
+    }
+}
+
+impl From<StateRootRegistryError> for WorldStateError {
+    fn from(error: StateRootRegistryError) -> Self {
+        Self::StateRootRegistryError(error)
+    }
+}
+
+impl From<ProofError> for WorldStateError {
+    fn from(error: ProofError) -> Self {
+        Self::ProofError(error)
+    }
+}
+
+impl From<OverlayError> for WorldStateError {
+    fn from(error: OverlayError) -> Self {
+        Self::OverlayError(error)
+    }
+}
+
 /// `MptError` is error from lib trie_db
 #[derive(Debug, PartialEq, Eq)]
 pub enum MptError {
@@ -48,6 +80,8 @@ pub enum MptError {
     InvalidHash,
     /// Empty Trie
     EmptyTrie,
+    /// Failed to read trie item from the database
+    DbError(DbError),
 }
 
 impl<T, E> From<trie_db::TrieError<T, E>> for MptError {
@@ -79,6 +113,35 @@ pub enum DecodeOrEncodeError {
     EncodeError,
 }
 
+/// `DbError` is error from the physical database behind [DB](crate::db::DB)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbError {
+    /// Failed to open the database, with the message from the database
+    OpenError(String),
+    /// Failed to read from the database, with the message from the database
+    ReadError(String),
+    /// Failed to write into the database, with the message from the database
+    WriteError(String),
+}
+
+/// `StateRootRegistryError` is error triggled when open WorldState by [StateRootRegistry](crate::state_root_registry::StateRootRegistry)
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateRootRegistryError {
+    /// No state root is recorded at the block height
+    UnknownHeight(u64),
+    /// The state root at the block height is recorded with another [Version](crate::version::Version)
+    VersionMismatch,
+}
+
+/// `OverlayError` is error triggled when operating the blocks in [BlockOverlay](crate::overlay::BlockOverlay)
+#[derive(Debug, PartialEq, Eq)]
+pub enum OverlayError {
+    /// The block is not in the overlay
+    UnknownBlock,
+    /// The block is already in the overlay
+    DuplicateBlock,
+}
+
 impl fmt::Display for TrieKeyBuildError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
@@ -97,3 +160,33 @@ impl fmt::Display for DecodeOrEncodeError {
         }
     }
 }
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            DbError::OpenError(msg) => write!(f, "Database Open Error: {}", msg),
+            DbError::ReadError(msg) => write!(f, "Database Read Error: {}", msg),
+            DbError::WriteError(msg) => write!(f, "Database Write Error: {}", msg),
+        }
+    }
+}
+
+impl fmt::Display for StateRootRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            StateRootRegistryError::UnknownHeight(height) => {
+                write!(f, "Unknown Block Height: {}", height)
+            }
+            StateRootRegistryError::VersionMismatch => write!(f, "Version Mismatch"),
+        }
+    }
+}
+
+impl fmt::Display for OverlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            OverlayError::UnknownBlock => write!(f, "Unknown Block"),
+            OverlayError::DuplicateBlock => write!(f, "Duplicate Block"),
+        }
+    }
+}
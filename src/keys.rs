@@ -0,0 +1,110 @@
+/*
+    Copyright © 2023, ParallelChain Lab
+    Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
+*/
+
+//! This mod provides the V1 and V2 key layouts of [AccountsTrie](crate::accounts_trie::AccountsTrie) and
+//! [StorageTrie](crate::storage_trie::StorageTrie), and the prefix of proof nodes.
+//! It does not depend on `std`, so that it is shared by the world state and the [verify](crate::verify) module.
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::version::{Version, VersionProvider};
+
+/// `PublicAddress` is the account address, same as `pchain_types::cryptography::PublicAddress`
+pub(crate) type PublicAddress = [u8; 32];
+
+/// This sub mod provides prefix for proof node.
+pub(crate) mod proof_level {
+    /// ProofLevel forms part of a proof node prefix. It splits the Proof of key into two:
+    ///
+    /// Accounts level
+    ///
+    /// Storage level
+    pub(crate) type ProofLevel = u8;
+
+    /// `ACCOUNTS` is the proof of the storage hash in AccountsTrie
+    pub(crate) const ACCOUNTS: ProofLevel = 0x00;
+
+    /// `STORAGE` is the proof of key inside smart contracts (AppKey) in storage tire.
+    pub(crate) const STORAGE: ProofLevel = 0x01;
+}
+
+/// `Visibility` is the prefix to identify the external account key and contract account key
+#[repr(u8)]
+pub(crate) enum KeyVisibility {
+    Public = 0,
+    Protected = 1,
+}
+
+/// `AccountField` prefix to identify the data type belong to [AccountsTrie](crate::accounts::AccountsTrie)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AccountField {
+    Nonce = 0,
+    Balance = 1,
+    ContractCode = 2,
+    CbiVersion = 3,
+    StorageHash = 4,
+}
+
+impl AccountField {
+    /// all the account fields in the order of their prefixes
+    pub const ALL: [AccountField; 5] = [
+        AccountField::Nonce,
+        AccountField::Balance,
+        AccountField::ContractCode,
+        AccountField::CbiVersion,
+        AccountField::StorageHash,
+    ];
+}
+
+/// `account_key` is to create the key for [AccountsTrie](crate::accounts_trie::AccountsTrie)
+///
+/// V1 AccountTrie Key is in form PublicAddress + KeyVisibility + AccountField
+///
+/// V2 AccountTrie Key is in form PublicAddress + AccountField
+pub(crate) fn account_key<V: VersionProvider>(
+    address: &PublicAddress,
+    account_field: AccountField,
+) -> Vec<u8> {
+    match <V>::version() {
+        Version::V1 => {
+            let mut account_key: Vec<u8> =
+                Vec::with_capacity(size_of::<PublicAddress>() + size_of::<u8>() + size_of::<u8>());
+            account_key.extend_from_slice(address);
+            account_key.push(KeyVisibility::Protected as u8);
+            account_key.push(account_field as u8);
+            account_key
+        }
+        Version::V2 => {
+            let mut account_key: Vec<u8> =
+                Vec::with_capacity(size_of::<PublicAddress>() + size_of::<u8>());
+            account_key.extend_from_slice(address);
+            account_key.push(account_field as u8);
+            account_key
+        }
+    }
+}
+
+/// `storage_key` is to crate the key for [StorageTrie](crate::storage::StorageTrie)
+///
+/// V1 StorageTrie Key is in form KeyVisibility + Vec<u8>
+///
+/// V2 StorageTrie Key is in form Vec<u8>
+pub(crate) fn storage_key<V: VersionProvider>(key: &Vec<u8>) -> Vec<u8> {
+    match <V>::version() {
+        Version::V1 => {
+            let mut storage_key: Vec<u8> = Vec::with_capacity(size_of::<u8>() + key.len());
+            storage_key.push(KeyVisibility::Public as u8);
+            storage_key.extend_from_slice(key);
+            storage_key
+        }
+        Version::V2 => {
+            let mut storage_key: Vec<u8> = Vec::new();
+            storage_key.extend_from_slice(key);
+            storage_key
+        }
+    }
+}
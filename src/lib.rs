@@ -3,7 +3,9 @@
 //! # Example
 //! ```ignore
 //! // init a genesis WorldState with old version
-//! let ws = WorldState::<DummyStorage, V1>::new(&storage);
+//! // MemoryDB is an in-memory implementation of DB
+//! let storage = MemoryDB::new();
+//! let ws = WorldState::<MemoryDB, V1>::new(&storage);
 //! // set nonce for account
 //! ws.account_trie_mut().set_nonce(&address, 1_u64).unwrap();
 //! // set <key, value> to account storage
@@ -11,8 +13,9 @@
 //! // close and get changes as structure WorldStateChanges
 //! let ws_change = ws.close().unwrap();
 //! // caller need to apply the changes provided by WorldStateChanges to physical db
+//! storage.apply_changes(ws_change.clone());
 //! // open WorldState after change
-//! let ws_after_change = WorldState::<DummyStorage, V1>::open(&storage, ws_change.new_root_hash);
+//! let ws_after_change = WorldState::<MemoryDB, V1>::open(&storage, ws_change.new_root_hash);
 //! // get updated nonce
 //! let nonce = ws_after_change.account_trie().nonce(&address);
 //! // get updated account storage change
@@ -22,32 +25,100 @@
 //! # Example
 //! ```ignore
 //! // upgrade worldstate v1 to worldstate v2
-//! let ws_2 = WorldState::<DummyStorage, V1>::upgrade(ws_1);
+//! let ws_2 = WorldState::<MemoryDB, V1>::upgrade(ws_1);
 //! // get changes during the upgrades
 //! let ws_changes = ws_2.close();
 //! // user need to apply the physical db change by ws_change.inserts, and ws_change.deletes
 //! ```
+//!
+//! # Features
+//! - `std` (default): the WorldState and everything on top of it.
+//! - `verify`: the [verify] module only, which can be built with `no_std` + `alloc` for light clients and contracts.
+//! - `rocksdb`: RocksDB implementation of `DB`.
+//! - `serde`: serde serialization of [WorldStateChanges], in addition to borsh.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
 pub mod accounts_trie;
+#[cfg(feature = "std")]
 pub use accounts_trie::*;
 
+#[cfg(feature = "std")]
 pub mod db;
-pub use db::DB;
+#[cfg(feature = "std")]
+pub use db::{DBWrite, MemoryDB, MemoryDBStats, WriteBatch, DB};
 
+#[cfg(feature = "std")]
 pub mod error;
+#[cfg(feature = "std")]
 pub use error::*;
 
+#[cfg(feature = "std")]
 pub mod storage_trie;
+#[cfg(feature = "std")]
 pub use storage_trie::*;
 
+#[cfg(feature = "std")]
 pub mod mpt;
+#[cfg(feature = "std")]
 pub use mpt::*;
 
+#[cfg(feature = "std")]
+pub mod node_cache;
+#[cfg(feature = "std")]
+pub use node_cache::*;
+
+#[cfg(feature = "std")]
+pub mod overlay;
+#[cfg(feature = "std")]
+pub use overlay::*;
+
+#[cfg(feature = "std")]
+pub mod proof;
+#[cfg(feature = "std")]
+pub use proof::*;
+
+#[cfg(feature = "std")]
+pub mod pruning;
+#[cfg(feature = "std")]
+pub use pruning::*;
+
+#[cfg(feature = "std")]
+pub mod state_root_registry;
+#[cfg(feature = "std")]
+pub use state_root_registry::*;
+
+#[cfg(feature = "std")]
+pub mod witness;
+#[cfg(feature = "std")]
+pub use witness::*;
+
+#[cfg(feature = "std")]
 pub mod world_state;
+#[cfg(feature = "std")]
 pub use world_state::*;
 
 pub mod version;
 pub use version::*;
 
+#[cfg(feature = "verify")]
+mod keys;
+
+#[cfg(feature = "verify")]
+mod trie_layout;
+
+#[cfg(feature = "verify")]
+pub mod verify;
+
+#[cfg(feature = "std")]
 pub mod network_account_storage;
+#[cfg(feature = "std")]
 pub use network_account_storage::*;
+
+#[cfg(feature = "rocksdb")]
+pub mod rocks_db;
+#[cfg(feature = "rocksdb")]
+pub use rocks_db::*;
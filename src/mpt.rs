@@ -6,17 +6,42 @@
 //! This mod only public to crate inside. Provides structs and implementations
 
 use crate::db::{KeyInstrumentedDB, DB};
-use crate::error::MptError;
+use crate::error::{DbError, MptError};
+use crate::node_cache::NodeCache;
+use crate::trie_layout::{NoExtensionLayout, RefHasher};
 use crate::version::VersionProvider;
+use crate::witness::WitnessRecorder;
 use hash_db::{AsHashDB, HashDB, HashDBRef, Hasher as KeyHasher, Prefix};
 use pchain_types::cryptography::Sha256Hash;
-use reference_trie::{NoExtensionLayout, RefHasher};
 use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use trie_db::node::{Node, NodeHandle, Value};
 use trie_db::proof::generate_proof;
-use trie_db::{Trie, TrieDBBuilder, TrieDBMutBuilder, TrieMut};
+use trie_db::{
+    NibbleSlice, NodeCodec, Recorder, Trie, TrieDBBuilder, TrieDBMutBuilder, TrieDBRawIterator,
+    TrieLayout, TrieMut,
+};
 
 pub type Proof = Vec<Vec<u8>>;
 
+/// `ProofAndValues` is a proof of many keys, with the values of the keys in the same order as the keys
+pub type ProofAndValues = (Proof, Vec<Option<Vec<u8>>>);
+
+/// `ProofAndItems` is a proof of a range of keys, with all the key-value pairs in the range ordered by key
+pub type ProofAndItems = (Proof, Vec<(Vec<u8>, Vec<u8>)>);
+
+/// `ValueChange` is the change of the value of a key from one root of a trie to another root, returned by
+/// e.g. [AccountsTrie::diff](crate::accounts_trie::AccountsTrie::diff)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueChange {
+    /// The key is only found in the other root
+    Added(Vec<u8>),
+    /// The key is only found in this root
+    Removed(Vec<u8>),
+    /// The key is found in both roots with different values
+    Changed { old: Vec<u8>, new: Vec<u8> },
+}
+
 /// `Mpt` is struct to maintain the Merkle Patricia Trie tree as storage struct
 ///
 /// Merkle Tree: A hash tree in which each node’s hash is computed from its child nodes hashes.
@@ -32,6 +57,38 @@ where
 {
     db: KeyInstrumentedDB<'a, S, V>,
     root_hash: Sha256Hash,
+    read_error: ReadErrorSlot,
+    // values of the keys before their first write since the last close, if recording of changed values is enabled
+    original_values: Option<HashMap<Vec<u8>, Option<Vec<u8>>>>,
+    // root hash and position of the journal in db at each checkpoint, from the earliest to the latest
+    checkpoints: Vec<(Sha256Hash, usize)>,
+}
+
+/// `ReadErrorSlot` keeps the error from [DB::try_get](crate::db::DB::try_get) during a trie operation,
+/// because the HashDB interface used by trie_db can only report a missing trie node.
+#[derive(Debug, Default)]
+struct ReadErrorSlot(Mutex<Option<DbError>>);
+
+impl ReadErrorSlot {
+    fn set(&self, error: DbError) {
+        *self.0.lock().unwrap() = Some(error);
+    }
+
+    fn take(&self) -> Option<DbError> {
+        self.0.lock().unwrap().take()
+    }
+
+    /// `clear` drops the error left by an earlier trie operation, so that it is not reported by the next one
+    fn clear(&self) {
+        self.take();
+    }
+}
+
+/// The error belongs to the trie operation that failed, it is not copied into a clone of the Mpt.
+impl Clone for ReadErrorSlot {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
 }
 
 const PREIMAGE_OF_EMPTY_TRIE_ROOT_HASH: &[u8] = &[0_u8];
@@ -56,6 +113,9 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         let mut genesis_mpt: Mpt<S, V> = Mpt {
             db: db.clone(),
             root_hash: dummy_root_hash,
+            read_error: ReadErrorSlot::default(),
+            original_values: None,
+            checkpoints: Vec::new(),
         };
         let root_hash = {
             let mut trie =
@@ -64,7 +124,13 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
             trie.commit();
             *trie.root()
         };
-        Mpt { db, root_hash }
+        Mpt {
+            db,
+            root_hash,
+            read_error: ReadErrorSlot::default(),
+            original_values: None,
+            checkpoints: Vec::new(),
+        }
     }
 
     /// `unsafe_new` is contructor of MPT for benchmark test
@@ -74,7 +140,13 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
 
     /// `open` is to open the trie from give storage source and state_hash
     pub fn open(db: KeyInstrumentedDB<'a, S, V>, root_hash: Sha256Hash) -> Self {
-        let mpt: Mpt<S, V> = Mpt { db, root_hash };
+        let mpt: Mpt<S, V> = Mpt {
+            db,
+            root_hash,
+            read_error: ReadErrorSlot::default(),
+            original_values: None,
+            checkpoints: Vec::new(),
+        };
         mpt
     }
 
@@ -84,11 +156,148 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     ///
     /// Error when state_hash does not exist or missed some trie nodes
     pub(crate) fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, MptError> {
+        self.read_error.clear();
         let trie = TrieDBBuilder::<NoExtensionLayout>::new(self, &self.root_hash).build();
-        let value = trie.get(key).map_err(|err| MptError::from(*err))?;
+        let value = trie.get(key).map_err(|err| self.trie_error(*err))?;
         Ok(value)
     }
 
+    /// `batch_get` is read and returns the values by keys in a trie, in the same order as the keys
+    ///
+    /// The lookups of all keys go down the trie together. The trie nodes at the same depth are read by
+    /// one [DB::multi_get](crate::db::DB::multi_get), and a node shared by the paths of several keys is read once.
+    ///
+    /// Error when state_hash does not exist or missed some trie nodes
+    pub(crate) fn batch_get(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, MptError> {
+        let mut values = vec![None; keys.len()];
+        // unfinished lookups as (index of key, hash of next node, number of nibbles of key consumed)
+        let mut lookups: Vec<(usize, Hash256, usize)> = (0..keys.len())
+            .map(|index| (index, self.root_hash, 0))
+            .collect();
+        let mut depth = 0;
+        while !lookups.is_empty() {
+            // physical keys of the trie nodes to read at this depth, without duplicates
+            let mut node_keys: Vec<Vec<u8>> = Vec::new();
+            let mut node_indexes: HashMap<Vec<u8>, usize> = HashMap::new();
+            let lookup_nodes: Vec<usize> = lookups
+                .iter()
+                .map(|(index, hash, consumed)| {
+                    let nibble_key = NibbleSlice::new(&keys[*index]).mid(*consumed);
+                    let node_key = prefixed_trie_node_key::<RefHasher>(hash, nibble_key.left());
+                    *node_indexes.entry(node_key.clone()).or_insert_with(|| {
+                        node_keys.push(node_key);
+                        node_keys.len() - 1
+                    })
+                })
+                .collect();
+            let nodes = self.db.multi_get(&node_keys).map_err(MptError::DbError)?;
+
+            let mut next_lookups = Vec::new();
+            for ((index, _, consumed), node_index) in lookups.into_iter().zip(lookup_nodes) {
+                let node = nodes[node_index].as_ref().ok_or(match depth {
+                    0 => MptError::InvalidStateRoot,
+                    _ => MptError::IncompleteDatabase,
+                })?;
+                match descend(&keys[index], consumed, node)? {
+                    Descend::Value(value) => values[index] = value,
+                    Descend::Node(hash, consumed) => next_lookups.push((index, hash, consumed)),
+                    Descend::ValueNode => values[index] = self.get(&keys[index])?,
+                }
+            }
+            lookups = next_lookups;
+            depth += 1;
+        }
+        Ok(values)
+    }
+
+    /// `set_node_cache` sets the [NodeCache] consulted before reading trie nodes from [DB]
+    pub(crate) fn set_node_cache(&mut self, node_cache: Option<NodeCache>) {
+        self.db.set_node_cache(node_cache);
+    }
+
+    /// `set_witness_recorder` sets the [WitnessRecorder] recording the trie nodes read from [DB]
+    pub(crate) fn set_witness_recorder(&mut self, witness_recorder: Option<WitnessRecorder>) {
+        self.db.set_witness_recorder(witness_recorder);
+    }
+
+    /// `set_value_recording` enables or disables recording the value of each key before its first write,
+    /// so that [Mpt::changed_values] can return the changes since then. Values recorded before are dropped.
+    pub(crate) fn set_value_recording(&mut self, enabled: bool) {
+        self.original_values = enabled.then(HashMap::new);
+    }
+
+    /// `changed_values` returns the changes of the values of the keys written since the recording is enabled
+    /// or the last close, ordered by key. Keys whose values are written back to the original values are not included.
+    ///
+    /// Error when state_hash does not exist or missed some trie nodes
+    pub(crate) fn changed_values(&self) -> Result<Vec<(Vec<u8>, ValueChange)>, MptError> {
+        let original_values = match &self.original_values {
+            Some(original_values) => original_values,
+            None => return Ok(Vec::new()),
+        };
+        let mut keys: Vec<Vec<u8>> = original_values.keys().cloned().collect();
+        keys.sort();
+        let values = self.batch_get(&keys)?;
+        let mut changes = Vec::new();
+        for (key, value) in keys.into_iter().zip(values) {
+            let change = match (original_values[&key].clone(), value) {
+                (None, Some(new)) => ValueChange::Added(new),
+                (Some(old), None) => ValueChange::Removed(old),
+                (Some(old), Some(new)) if old != new => ValueChange::Changed { old, new },
+                _ => continue,
+            };
+            changes.push((key, change));
+        }
+        Ok(changes)
+    }
+
+    /// `checkpoint` adds a checkpoint at the current state of the trie, which is the latest checkpoint.
+    /// The writes afterwards are recorded in the journal of [KeyInstrumentedDB].
+    pub(crate) fn checkpoint(&mut self) {
+        self.db.start_journal();
+        self.checkpoints
+            .push((self.root_hash, self.db.journal_len()));
+    }
+
+    /// `revert_to_checkpoint` reverts the trie to the state at the checkpoint of the index, and removes the checkpoint
+    /// together with the later ones. Nothing happens if there is no such checkpoint.
+    pub(crate) fn revert_to_checkpoint(&mut self, index: usize) {
+        if let Some((root_hash, journal_len)) = self.checkpoints.get(index).copied() {
+            self.db.revert_journal(journal_len);
+            self.root_hash = root_hash;
+            self.discard_checkpoint(index);
+        }
+    }
+
+    /// `discard_checkpoint` removes the checkpoint of the index together with the later ones, keeping the changes
+    /// since then. The journal is dropped if no checkpoint is left.
+    pub(crate) fn discard_checkpoint(&mut self, index: usize) {
+        self.checkpoints.truncate(index);
+        if self.checkpoints.is_empty() {
+            self.db.stop_journal();
+        }
+    }
+
+    /// `record_original_values` records the current values of the keys not recorded yet, before they are written
+    fn record_original_values(&mut self, keys: &[Vec<u8>]) -> Result<(), MptError> {
+        let keys: Vec<Vec<u8>> = match &self.original_values {
+            Some(original_values) => keys
+                .iter()
+                .filter(|key| !original_values.contains_key(*key))
+                .cloned()
+                .collect(),
+            None => return Ok(()),
+        };
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let values = self.batch_get(&keys)?;
+        if let Some(original_values) = &mut self.original_values {
+            original_values.extend(keys.into_iter().zip(values));
+        }
+        Ok(())
+    }
+
     /// `root_hash` return the current root_hash of trie
     pub(crate) fn root_hash(&self) -> Sha256Hash {
         self.root_hash
@@ -105,20 +314,251 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         &self,
         key: &Vec<u8>,
     ) -> Result<(Proof, Option<Vec<u8>>), MptError> {
+        self.read_error.clear();
         let trie = TrieDBBuilder::<NoExtensionLayout>::new(self, &self.root_hash).build();
-        let value = trie.get(key).map_err(|err| MptError::from(*err))?;
+        let value = trie.get(key).map_err(|err| self.trie_error(*err))?;
         let proof_ret =
             generate_proof::<_, NoExtensionLayout, _, _>(self, &self.root_hash, [key].iter());
-        let proof = proof_ret.map_err(|err| MptError::from(*err))?;
+        let proof = proof_ret.map_err(|err| self.trie_error(*err))?;
         Ok((proof, value))
     }
 
+    /// `batch_get_with_proof` is read and returns the values by keys in a trie in the same order as the keys,
+    /// with one compact proof for all the keys, including the keys not found in the trie.
+    ///
+    /// The trie nodes shared by the paths of several keys appear once in the proof.
+    ///
+    /// Error when state_hash does not exist or missed some trie nodes
+    pub(crate) fn batch_get_with_proof(
+        &self,
+        keys: &[Vec<u8>],
+    ) -> Result<ProofAndValues, MptError> {
+        let values = self.batch_get(keys)?;
+        self.read_error.clear();
+        let proof_ret =
+            generate_proof::<_, NoExtensionLayout, _, _>(self, &self.root_hash, keys.iter());
+        let proof = proof_ret.map_err(|err| self.trie_error(*err))?;
+        Ok((proof, values))
+    }
+
+    /// `range_with_proof` is read and returns all the key-value pairs with keys in `start..end` ordered by key,
+    /// with the proof that no key-value pair in the range is omitted. The range is not bounded above if `end` is None.
+    ///
+    /// Unlike the compact proof of `get_with_proof`, the proof is the encoded trie nodes read by iterating over the range,
+    /// so that the verifier can walk through the whole range in the trie.
+    ///
+    /// Error when state_hash does not exist or missed some trie nodes
+    pub(crate) fn range_with_proof(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+    ) -> Result<ProofAndItems, MptError> {
+        self.read_error.clear();
+        let mut recorder = Recorder::<NoExtensionLayout>::new();
+        let mut items = Vec::new();
+        {
+            let trie = TrieDBBuilder::<NoExtensionLayout>::new(self, &self.root_hash)
+                .with_recorder(&mut recorder)
+                .build();
+            let mut raw_iter = TrieDBRawIterator::new_prefixed_then_seek(&trie, &[], start)
+                .map_err(|err| self.trie_error(*err))?;
+            while let Some(item) = raw_iter.next_item(&trie) {
+                let (key, value) = item.map_err(|err| self.trie_error(*err))?;
+                if end.is_some_and(|end| key.as_slice() >= end) {
+                    break;
+                }
+                items.push((key, value));
+            }
+        }
+        let proof = recorder
+            .drain()
+            .into_iter()
+            .map(|record| record.data)
+            .collect();
+        Ok((proof, items))
+    }
+
+    /// `diff` returns the changes of all the key-value pairs from the root of this trie to `other_root` ordered by key.
+    /// Both roots must be in the same [DB], e.g. two state roots of AccountsTrie, or two storage roots of the same account.
+    ///
+    /// The two tries are walked down together from the roots. A subtree with the same hash at the same position in both
+    /// tries is skipped without reading it, so the cost is proportional to the size of the changes.
+    ///
+    /// Error when either root does not exist or missed some trie nodes
+    pub(crate) fn diff(
+        &self,
+        other_root: Sha256Hash,
+    ) -> Result<Vec<(Vec<u8>, ValueChange)>, MptError> {
+        let mut changes = Vec::new();
+        self.diff_nodes(
+            Some(DiffNode::root(self.root_hash)),
+            Some(DiffNode::root(other_root)),
+            &mut changes,
+        )?;
+        changes.sort_by(|(key1, _), (key2, _)| key1.cmp(key2));
+        Ok(changes)
+    }
+
+    /// `diff_nodes` compares the old node and the new node at the same position, skipping them if they are identical
+    fn diff_nodes(
+        &self,
+        old: Option<DiffNode>,
+        new: Option<DiffNode>,
+        changes: &mut Vec<(Vec<u8>, ValueChange)>,
+    ) -> Result<(), MptError> {
+        if let (Some(old), Some(new)) = (&old, &new) {
+            if old == new {
+                return Ok(());
+            }
+        }
+        let old = old.map(|node| self.diff_view(&node)).transpose()?;
+        let new = new.map(|node| self.diff_view(&node)).transpose()?;
+        self.diff_views(old, new, changes)
+    }
+
+    /// `diff_views` compares the old node and the new node, whose paths including their partial keys may differ
+    fn diff_views(
+        &self,
+        old: Option<DiffView>,
+        new: Option<DiffView>,
+        changes: &mut Vec<(Vec<u8>, ValueChange)>,
+    ) -> Result<(), MptError> {
+        match (old, new) {
+            (None, None) => {}
+            (Some(old), None) => {
+                push_change(changes, &old.full_path, old.value, None);
+                for child in old.children {
+                    self.diff_nodes(child, None, changes)?;
+                }
+            }
+            (None, Some(new)) => {
+                push_change(changes, &new.full_path, None, new.value);
+                for child in new.children {
+                    self.diff_nodes(None, child, changes)?;
+                }
+            }
+            (Some(old), Some(new)) if old.full_path == new.full_path => {
+                push_change(changes, &old.full_path, old.value, new.value);
+                for (old_child, new_child) in old.children.into_iter().zip(new.children) {
+                    self.diff_nodes(old_child, new_child, changes)?;
+                }
+            }
+            (Some(old), Some(new)) if new.full_path.starts_with(&old.full_path) => {
+                // the new node is in the subtree of a child of the old node
+                let nibble = new.full_path[old.full_path.len()] as usize;
+                push_change(changes, &old.full_path, old.value, None);
+                let mut new = Some(new);
+                for (index, old_child) in old.children.into_iter().enumerate() {
+                    match index == nibble {
+                        true => {
+                            let old_child =
+                                old_child.map(|node| self.diff_view(&node)).transpose()?;
+                            self.diff_views(old_child, new.take(), changes)?;
+                        }
+                        false => self.diff_nodes(old_child, None, changes)?,
+                    }
+                }
+            }
+            (Some(old), Some(new)) if old.full_path.starts_with(&new.full_path) => {
+                // the old node is in the subtree of a child of the new node
+                let nibble = old.full_path[new.full_path.len()] as usize;
+                push_change(changes, &new.full_path, None, new.value);
+                let mut old = Some(old);
+                for (index, new_child) in new.children.into_iter().enumerate() {
+                    match index == nibble {
+                        true => {
+                            let new_child =
+                                new_child.map(|node| self.diff_view(&node)).transpose()?;
+                            self.diff_views(old.take(), new_child, changes)?;
+                        }
+                        false => self.diff_nodes(None, new_child, changes)?,
+                    }
+                }
+            }
+            (Some(old), Some(new)) => {
+                // the paths diverge, so the nodes have no key in common
+                self.diff_views(Some(old), None, changes)?;
+                self.diff_views(None, Some(new), changes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `diff_view` reads and decodes the trie node for [Mpt::diff]
+    fn diff_view(&self, node: &DiffNode) -> Result<DiffView, MptError> {
+        let node_data = match &node.handle {
+            DiffHandle::Hash(hash) => {
+                let (packed, last) = packed_nibbles(&node.path);
+                let node_key = prefixed_trie_node_key::<RefHasher>(hash, (&packed, last));
+                self.db.get(&node_key).map_err(MptError::DbError)?.ok_or(
+                    match node.path.is_empty() {
+                        true => MptError::InvalidStateRoot,
+                        false => MptError::IncompleteDatabase,
+                    },
+                )?
+            }
+            DiffHandle::Inline(data) => data.clone(),
+        };
+        let decoded = <NoExtensionLayout as TrieLayout>::Codec::decode(&node_data)
+            .map_err(|_| MptError::DecoderError)?;
+        let (partial, children, value) = match decoded {
+            Node::Empty => (NibbleSlice::new(&[]), [None; 16], None),
+            Node::Leaf(partial, value) => (partial, [None; 16], Some(value)),
+            Node::Branch(children, value) => (NibbleSlice::new(&[]), children, value),
+            Node::NibbledBranch(partial, children, value) => (partial, children, value),
+            // NoExtensionLayout does not use extension nodes
+            Node::Extension(..) => return Err(MptError::DecoderError),
+        };
+        let mut full_path = node.path.clone();
+        full_path.extend((0..partial.len()).map(|i| partial.at(i)));
+        let value = match value {
+            Some(_) if !full_path.len().is_multiple_of(2) => {
+                return Err(MptError::ValueAtIncompleteKey)
+            }
+            Some(Value::Inline(value)) => Some(value.to_vec()),
+            Some(Value::Node(hash)) => {
+                let hash: Hash256 = hash.try_into().map_err(|_| MptError::InvalidHash)?;
+                let (packed, last) = packed_nibbles(&full_path);
+                let value_key = prefixed_trie_node_key::<RefHasher>(&hash, (&packed, last));
+                let value = self
+                    .db
+                    .get(&value_key)
+                    .map_err(MptError::DbError)?
+                    .ok_or(MptError::IncompleteDatabase)?;
+                Some(value)
+            }
+            None => None,
+        };
+        let children: Vec<Option<DiffNode>> = children
+            .into_iter()
+            .enumerate()
+            .map(|(nibble, child)| -> Result<Option<DiffNode>, MptError> {
+                let handle = match child {
+                    Some(NodeHandle::Hash(hash)) => {
+                        DiffHandle::Hash(hash.try_into().map_err(|_| MptError::InvalidHash)?)
+                    }
+                    Some(NodeHandle::Inline(data)) => DiffHandle::Inline(data.to_vec()),
+                    None => return Ok(None),
+                };
+                let mut path = full_path.clone();
+                path.push(nibble as u8);
+                Ok(Some(DiffNode { path, handle }))
+            })
+            .collect::<Result<_, MptError>>()?;
+        Ok(DiffView {
+            full_path,
+            value,
+            children,
+        })
+    }
+
     /// `contains` check is the key exists in a trie
     ///
     /// Error when state_hash does not exist or missed some trie nodes
     pub(crate) fn contains(&self, key: &[u8]) -> Result<bool, MptError> {
+        self.read_error.clear();
         let trie = TrieDBBuilder::<NoExtensionLayout>::new(self, &self.root_hash).build();
-        let exsits = trie.contains(key).map_err(|err| MptError::from(*err))?;
+        let exsits = trie.contains(key).map_err(|err| self.trie_error(*err))?;
         Ok(exsits)
     }
 
@@ -130,33 +570,46 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         F: FnMut(Vec<u8>, Vec<u8>) -> Result<(), E>,
         E: From<MptError>,
     {
-        let trie = TrieDBBuilder::<NoExtensionLayout>::new(self, &self.root_hash).build();
-        let trie_iter = trie.iter().map_err(|err| MptError::from(*err))?;
-        for item in trie_iter {
-            let (key, value) = item.map_err(|err| MptError::from(*err))?;
+        for item in self.iter() {
+            let (key, value) = item?;
             f(key, value)?;
         }
         Ok(())
     }
 
+    /// `iter` returns a lazy iterator of all key-value pairs in MPT ordered by key.
+    /// Use [MptIterator::seek], [MptIterator::prefix] or [MptIterator::resume] to limit the iteration.
+    pub fn iter(&self) -> MptIterator<'_, 'a, S, V> {
+        MptIterator {
+            mpt: self,
+            prefix: Vec::new(),
+            start_key: Vec::new(),
+            skip_key: None,
+            last_key: None,
+            raw_iter: None,
+            done: false,
+        }
+    }
+
     /// `set` is set <key, value> pair to Trie
     /// Any value change will be reflected on `state_hash` change in Worldstate
     ///
     /// Error when state_hash does not exist or missed some trie nodes
     pub(crate) fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<(), MptError> {
+        self.record_original_values(&[key.to_vec()])?;
+        self.read_error.clear();
         let mut cur_root_hash = self.root_hash;
         let new_root_hash = {
             let mut trie =
                 TrieDBMutBuilder::<NoExtensionLayout>::from_existing(self, &mut cur_root_hash)
                     .build();
-            let _ = trie
-                .insert(key, &value)
-                .map_err(|err| MptError::from(*err))?;
-            trie.commit();
-            *trie.root()
+            trie.insert(key, &value).map(|_| {
+                trie.commit();
+                *trie.root()
+            })
         };
 
-        self.root_hash = new_root_hash;
+        self.root_hash = new_root_hash.map_err(|err| self.trie_error(*err))?;
         Ok(())
     }
 
@@ -165,20 +618,21 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     ///
     /// Error when state_hash does not exist or missed some trie nodes
     pub fn batch_set(&mut self, data: &HashMap<Vec<u8>, Vec<u8>>) -> Result<(), MptError> {
+        self.record_original_values(&data.keys().cloned().collect::<Vec<Vec<u8>>>())?;
+        self.read_error.clear();
         let mut cur_root_hash = self.root_hash;
         let new_root_hash = {
             let mut trie =
                 TrieDBMutBuilder::<NoExtensionLayout>::from_existing(self, &mut cur_root_hash)
                     .build();
-            for (key, value) in data.iter() {
-                let _ = trie
-                    .insert(key, value)
-                    .map_err(|err| MptError::from(*err))?;
-            }
-            trie.commit();
-            *trie.root()
+            data.iter()
+                .try_for_each(|(key, value)| trie.insert(key, value).map(|_| ()))
+                .map(|_| {
+                    trie.commit();
+                    *trie.root()
+                })
         };
-        self.root_hash = new_root_hash;
+        self.root_hash = new_root_hash.map_err(|err| self.trie_error(*err))?;
         Ok(())
     }
 
@@ -190,16 +644,19 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         if !self.contains(key)? {
             return Ok(());
         }
+        self.record_original_values(&[key.to_vec()])?;
+        self.read_error.clear();
         let mut cur_root_hash = self.root_hash;
         let new_root_hash = {
             let mut trie =
                 TrieDBMutBuilder::<NoExtensionLayout>::from_existing(self, &mut cur_root_hash)
                     .build();
-            let _ = trie.remove(key).map_err(|err| MptError::from(*err))?;
-            trie.commit();
-            *trie.root()
+            trie.remove(key).map(|_| {
+                trie.commit();
+                *trie.root()
+            })
         };
-        self.root_hash = new_root_hash;
+        self.root_hash = new_root_hash.map_err(|err| self.trie_error(*err))?;
         Ok(())
     }
 
@@ -208,28 +665,170 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     ///
     /// Error when state_hash does not exist or missed some trie nodes
     pub(crate) fn batch_remove(&mut self, key_set: &HashSet<Vec<u8>>) -> Result<(), MptError> {
+        self.record_original_values(&key_set.iter().cloned().collect::<Vec<Vec<u8>>>())?;
+        self.read_error.clear();
         let mut cur_root_hash = self.root_hash;
         let new_root_hash = {
             let mut trie =
                 TrieDBMutBuilder::<NoExtensionLayout>::from_existing(self, &mut cur_root_hash)
                     .build();
-            for key in key_set.iter() {
-                let _ = trie.remove(key).map_err(|err| MptError::from(*err));
-            }
-            trie.commit();
-            *trie.root()
+            key_set
+                .iter()
+                .try_for_each(|key| trie.remove(key).map(|_| ()))
+                .map(|_| {
+                    trie.commit();
+                    *trie.root()
+                })
         };
-        self.root_hash = new_root_hash;
+        self.root_hash = new_root_hash.map_err(|err| self.trie_error(*err))?;
         Ok(())
     }
 
+    /// `trie_error` converts the error from trie_db into [MptError]. A root or trie node reported missing because
+    /// reading the database failed is converted into [MptError::DbError]
+    fn trie_error<T, E>(&self, err: trie_db::TrieError<T, E>) -> MptError {
+        match (err, self.read_error.take()) {
+            (
+                trie_db::TrieError::InvalidStateRoot(_) | trie_db::TrieError::IncompleteDatabase(_),
+                Some(db_error),
+            ) => MptError::DbError(db_error),
+            (err, _) => MptError::from(err),
+        }
+    }
+
     /// `close` is return and flush cache changes in [DB](crate::db::DB). Also return the updated state_hash
+    ///
+    /// The recorded original values and the checkpoints, if any, are dropped
     pub fn close(&mut self) -> MptChanges {
+        if let Some(original_values) = &mut self.original_values {
+            original_values.clear();
+        }
+        self.discard_checkpoint(0);
         let db_changes = self.db.close();
         MptChanges(db_changes.0, db_changes.1, self.root_hash)
     }
 }
 
+/// `TrieCursor` is an opaque position in a trie, which is the position after the last item returned by an iterator.
+/// It can be kept by the caller (e.g. in a RPC response) to resume the iteration by [MptIterator::resume].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrieCursor(Vec<u8>);
+
+impl TrieCursor {
+    /// `as_bytes` returns the encoded cursor
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// `from_bytes` decodes the cursor from the bytes returned by [TrieCursor::as_bytes]
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        TrieCursor(bytes)
+    }
+}
+
+/// `MptIterator` is a lazy iterator of the key-value pairs in [Mpt] ordered by key. Trie nodes are read from
+/// the database on demand, so the iteration over a large trie is done in bounded memory.
+///
+/// The iteration stops after the first error.
+pub struct MptIterator<'m, 'a, S, V>
+where
+    S: DB + Send + Sync + Clone,
+    V: VersionProvider + Send + Sync + Clone,
+{
+    mpt: &'m Mpt<'a, S, V>,
+    prefix: Vec<u8>,
+    start_key: Vec<u8>,
+    // key of the cursor to resume after, which is skipped if it is still in the trie
+    skip_key: Option<Vec<u8>>,
+    last_key: Option<Vec<u8>>,
+    raw_iter: Option<TrieDBRawIterator<NoExtensionLayout>>,
+    done: bool,
+}
+
+impl<'m, 'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
+    MptIterator<'m, 'a, S, V>
+{
+    /// `seek` starts the iteration from the first key which is equal to or greater than `start_key`
+    pub fn seek(mut self, start_key: &[u8]) -> Self {
+        self.start_key = start_key.to_vec();
+        self.skip_key = None;
+        self.last_key = None;
+        self
+    }
+
+    /// `prefix` limits the iteration to the keys starting with `prefix`
+    pub fn prefix(mut self, prefix: &[u8]) -> Self {
+        self.prefix = prefix.to_vec();
+        self
+    }
+
+    /// `resume` starts the iteration after the position of the cursor returned by [MptIterator::cursor]
+    pub fn resume(mut self, cursor: &TrieCursor) -> Self {
+        self.start_key = cursor.0.clone();
+        self.skip_key = Some(cursor.0.clone());
+        self.last_key = Some(cursor.0.clone());
+        self
+    }
+
+    /// `cursor` returns the position after the last returned item, None if no item is returned yet
+    pub fn cursor(&self) -> Option<TrieCursor> {
+        self.last_key.clone().map(TrieCursor)
+    }
+}
+
+impl<'m, 'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone> Iterator
+    for MptIterator<'m, 'a, S, V>
+{
+    type Item = Result<(Vec<u8>, Vec<u8>), MptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mpt = self.mpt;
+        mpt.read_error.clear();
+        let trie = TrieDBBuilder::<NoExtensionLayout>::new(mpt, &mpt.root_hash).build();
+        let raw_iter = match &mut self.raw_iter {
+            Some(raw_iter) => raw_iter,
+            // trie_db iterates nothing if the start key does not start with the prefix
+            None if !self.start_key.starts_with(&self.prefix) && self.start_key > self.prefix => {
+                self.done = true;
+                return None;
+            }
+            None => match TrieDBRawIterator::new_prefixed_then_seek(
+                &trie,
+                &self.prefix,
+                std::cmp::max(&self.start_key, &self.prefix),
+            ) {
+                Ok(raw_iter) => self.raw_iter.insert(raw_iter),
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(mpt.trie_error(*err)));
+                }
+            },
+        };
+        loop {
+            match raw_iter.next_item(&trie) {
+                Some(Ok((key, value))) => {
+                    if self.skip_key.take().as_ref() == Some(&key) {
+                        continue;
+                    }
+                    self.last_key = Some(key.clone());
+                    return Some(Ok((key, value)));
+                }
+                Some(Err(err)) => {
+                    self.done = true;
+                    return Some(Err(mpt.trie_error(*err)));
+                }
+                None => {
+                    self.done = true;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
 /// `MptChanges` is a wrapper of changes in [Mpt] when call function close()
 ///
 /// The reason that MptChanges struct exposed to public is we need it in benchmark test
@@ -248,8 +847,19 @@ impl<'a, S: DB + Send + Sync + Clone> Mpt<'a, S, crate::V1> {
         if self.root_hash() != RefHasher::hash(PREIMAGE_OF_EMPTY_TRIE_ROOT_HASH) {
             return Err(MptError::InvalidStateRoot);
         }
-        let trie = TrieDBBuilder::<NoExtensionLayout>::new(&self, &self.root_hash).build();
-        if trie.iter().is_ok() {
+        self.read_error.clear();
+        let root_exists = {
+            let trie = TrieDBBuilder::<NoExtensionLayout>::new(&self, &self.root_hash).build();
+            let root_exists = match trie.iter() {
+                Ok(_) => true,
+                Err(err) => match self.trie_error(*err) {
+                    MptError::DbError(db_error) => return Err(MptError::DbError(db_error)),
+                    _ => false,
+                },
+            };
+            root_exists
+        };
+        if root_exists {
             // need to hard delete the root node
             self.db.delete(empty_root_hash);
         }
@@ -265,6 +875,9 @@ impl<'a, S: DB + Send + Sync + Clone> Mpt<'a, S, crate::V1> {
         let mut genesis_mpt: Mpt<S, crate::V2> = Mpt {
             db: new_storage.clone(),
             root_hash: default_root_hash,
+            read_error: ReadErrorSlot::default(),
+            original_values: None,
+            checkpoints: Vec::new(),
         };
         let new_root_hash = {
             let mut trie = TrieDBMutBuilder::<NoExtensionLayout>::new(
@@ -278,6 +891,9 @@ impl<'a, S: DB + Send + Sync + Clone> Mpt<'a, S, crate::V1> {
         Ok(Mpt {
             db: new_storage,
             root_hash: new_root_hash,
+            read_error: ReadErrorSlot::default(),
+            original_values: None,
+            checkpoints: Vec::new(),
         })
     }
 }
@@ -295,13 +911,18 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     /// Look up a given hash into the bytes that hash to it, returning None if the hash is not known.
     fn get(&self, key: &Hash256, nibble_prefix: Prefix) -> Option<Vec<u8>> {
         let key = prefixed_trie_node_key::<RefHasher>(key, nibble_prefix);
-        self.db.get(&key)
+        match self.db.get(&key) {
+            Ok(value) => value,
+            Err(db_error) => {
+                self.read_error.set(db_error);
+                None
+            }
+        }
     }
 
     /// Check for the existence of a hash-key.
     fn contains(&self, key: &Hash256, nibble_prefix: Prefix) -> bool {
-        let key = prefixed_trie_node_key::<RefHasher>(key, nibble_prefix);
-        self.db.get(key.as_ref()).is_some()
+        HashDB::get(self, key, nibble_prefix).is_some()
     }
 
     /// Insert item into the DB and return the hash for a later lookup.
@@ -327,6 +948,140 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     }
 }
 
+/// `DiffNode` is a trie node to compare in [Mpt::diff], with the nibbles of the path to the node
+#[derive(PartialEq, Eq)]
+struct DiffNode {
+    path: Vec<u8>,
+    handle: DiffHandle,
+}
+
+impl DiffNode {
+    fn root(root_hash: Hash256) -> Self {
+        DiffNode {
+            path: Vec::new(),
+            handle: DiffHandle::Hash(root_hash),
+        }
+    }
+}
+
+/// `DiffHandle` is the reference to a trie node from its parent, which is the same for identical subtrees
+#[derive(PartialEq, Eq)]
+enum DiffHandle {
+    Hash(Hash256),
+    Inline(Vec<u8>),
+}
+
+/// `DiffView` is a decoded [DiffNode], with the nibbles of the path including the partial key of the node
+struct DiffView {
+    full_path: Vec<u8>,
+    value: Option<Vec<u8>>,
+    children: Vec<Option<DiffNode>>,
+}
+
+/// `push_change` adds the change of the value of the key in nibbles, if the value is changed
+fn push_change(
+    changes: &mut Vec<(Vec<u8>, ValueChange)>,
+    nibbles: &[u8],
+    old: Option<Vec<u8>>,
+    new: Option<Vec<u8>>,
+) {
+    let change = match (old, new) {
+        (Some(old), Some(new)) if old != new => ValueChange::Changed { old, new },
+        (Some(old), None) => ValueChange::Removed(old),
+        (None, Some(new)) => ValueChange::Added(new),
+        _ => return,
+    };
+    let (key, _) = packed_nibbles(nibbles);
+    changes.push((key, change));
+}
+
+/// `packed_nibbles` packs the nibbles into bytes, with the last nibble of odd number of nibbles returned separately
+/// in the high half of a byte, as the [Prefix] of trie_db
+fn packed_nibbles(nibbles: &[u8]) -> (Vec<u8>, Option<u8>) {
+    let packed = nibbles
+        .chunks_exact(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect();
+    let last = match nibbles.len() % 2 {
+        0 => None,
+        _ => Some(nibbles[nibbles.len() - 1] << 4),
+    };
+    (packed, last)
+}
+
+/// `Descend` is the result of looking up a key from a trie node in [Mpt::batch_get]
+enum Descend {
+    /// The lookup ends with the value of the key, None if the key does not exist
+    Value(Option<Vec<u8>>),
+    /// The lookup continues at the node with the hash, after the number of nibbles of key consumed
+    Node(Hash256, usize),
+    /// The value is stored in a separated value node, which is not used by [NoExtensionLayout]
+    ValueNode,
+}
+
+/// `descend` looks up the key from the encoded trie node (and its inline children), given the number of
+/// nibbles of key consumed to reach the node. It follows the same steps as the lookup in trie_db.
+fn descend(key: &[u8], mut consumed: usize, node_data: &[u8]) -> Result<Descend, MptError> {
+    let nibble_key = NibbleSlice::new(key);
+    let mut node_data = node_data;
+    loop {
+        let partial = nibble_key.mid(consumed);
+        let node = <NoExtensionLayout as TrieLayout>::Codec::decode(node_data)
+            .map_err(|_| MptError::DecoderError)?;
+        let (child, value) = match node {
+            Node::Empty => return Ok(Descend::Value(None)),
+            Node::Leaf(slice, value) => {
+                if slice != partial {
+                    return Ok(Descend::Value(None));
+                }
+                (None, Some(value))
+            }
+            Node::Extension(slice, child) => {
+                if !partial.starts_with(&slice) {
+                    return Ok(Descend::Value(None));
+                }
+                consumed += slice.len();
+                (Some(child), None)
+            }
+            Node::Branch(children, value) => {
+                if partial.is_empty() {
+                    (None, value)
+                } else {
+                    consumed += 1;
+                    match children[partial.at(0) as usize] {
+                        Some(child) => (Some(child), None),
+                        None => return Ok(Descend::Value(None)),
+                    }
+                }
+            }
+            Node::NibbledBranch(slice, children, value) => {
+                if !partial.starts_with(&slice) {
+                    return Ok(Descend::Value(None));
+                }
+                if partial.len() == slice.len() {
+                    (None, value)
+                } else {
+                    consumed += slice.len() + 1;
+                    match children[partial.at(slice.len()) as usize] {
+                        Some(child) => (Some(child), None),
+                        None => return Ok(Descend::Value(None)),
+                    }
+                }
+            }
+        };
+        match (child, value) {
+            (Some(NodeHandle::Hash(hash)), _) => {
+                let hash = hash.try_into().map_err(|_| MptError::InvalidHash)?;
+                return Ok(Descend::Node(hash, consumed));
+            }
+            (Some(NodeHandle::Inline(data)), _) => node_data = data,
+            (None, Some(Value::Inline(value))) => return Ok(Descend::Value(Some(value.to_vec()))),
+            (None, Some(Value::Node(_))) => return Ok(Descend::ValueNode),
+            (None, None) => return Ok(Descend::Value(None)),
+        }
+    }
+}
+
 pub(crate) fn prefixed_trie_node_key<H: KeyHasher>(
     hash: &H::Out,
     nibble_prefix: Prefix,
@@ -374,7 +1129,7 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     }
 }
 
-pub(crate) use proof_level::ProofLevel;
+pub(crate) use crate::keys::proof_level::{self, ProofLevel};
 use std::mem::size_of;
 
 /// WSProofNode is node in the trie traversed while performing lookups on the Key, prefixed by the trie level that they belong to:
@@ -396,66 +1151,23 @@ impl From<WSProofNode> for Vec<u8> {
     }
 }
 
-/// This sub mod provides prefix for proof node.
-pub(crate) mod proof_level {
-    /// ProofLevel forms part of a proof node prefix. It splits the Proof of key into two:
-    ///
-    /// Accounts level
-    ///
-    /// Storage level
-    pub(crate) type ProofLevel = u8;
-
-    /// `ACCOUNTS` is the proof of the storage hash in AccountsTrie
-    pub(crate) const ACCOUNTS: ProofLevel = 0x00;
-
-    /// `STORAGE` is the proof of key inside smart contracts (AppKey) in storage tire.
-    pub(crate) const STORAGE: ProofLevel = 0x01;
-}
-
-/// `Visibility` is the prefix to identify the external account key and contract account key
-#[repr(u8)]
-pub(crate) enum KeyVisibility {
-    Public = 0,
-    Protected = 1,
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::accounts_trie::account_key;
     use crate::accounts_trie::AccountField;
+    use crate::db::MemoryDB;
     use crate::version::{V1, V2};
     use pchain_types::cryptography::PublicAddress;
 
-    #[derive(Debug, Clone)]
-    struct DummyStorage(HashMap<Vec<u8>, Vec<u8>>);
-    impl DB for DummyStorage {
-        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-            match self.0.get(key) {
-                Some(value) => Some(value.to_owned()),
-                None => None,
-            }
-        }
-    }
-    impl DummyStorage {
-        fn apply_changes(&mut self, inserts: HashMap<Vec<u8>, Vec<u8>>, deletes: HashSet<Vec<u8>>) {
-            for (key, value) in inserts.into_iter() {
-                self.0.insert(key, value);
-            }
-            for key in deletes.into_iter() {
-                self.0.remove(&key);
-            }
-        }
-    }
-
     #[derive(Debug, Clone)]
     struct TestEnv {
-        db: DummyStorage,
+        db: MemoryDB,
         address: PublicAddress,
     }
     impl Default for TestEnv {
         fn default() -> Self {
-            let db = DummyStorage(HashMap::new());
+            let db = MemoryDB::new();
             const PUBLIC_KEY: &str = "ipy_VXNiwHNP9mx6-nKxht_ZJNfYoMAcCnLykpq4x_k";
             let address = base64url::decode(PUBLIC_KEY).unwrap().try_into().unwrap();
             Self { db, address }
@@ -464,43 +1176,43 @@ mod test {
 
     #[test]
     pub fn simple_insert_v1() {
-        let mut env = TestEnv::default();
-        let db = KeyInstrumentedDB::<DummyStorage, V1>::new(&env.db, env.address.to_vec());
+        let env = TestEnv::default();
+        let db = KeyInstrumentedDB::<MemoryDB, V1>::new(&env.db, env.address.to_vec());
         let apple_key = b"apple".to_vec();
         let apple_value = b"apple_12345".to_vec();
         let banana_key = b"banana".to_vec();
         let banana_value = b"banana_12345".to_vec();
-        let mut mpt = Mpt::<DummyStorage, V1>::new(db);
+        let mut mpt = Mpt::<MemoryDB, V1>::new(db);
         mpt.set(&apple_key, apple_value.clone()).unwrap();
         mpt.set(&banana_key, banana_value.clone()).unwrap();
         let changes = mpt.close();
         println!("================================mpt changes============================");
         println!("inserts: {:?}, deletes {:?}", changes.0, changes.1);
-        env.db.apply_changes(changes.0, changes.1);
+        env.db.apply_changes(changes.clone().into());
         println!("{:?}", env.db);
     }
 
     #[test]
     pub fn init_and_open() {
         let env = TestEnv::default();
-        let db = KeyInstrumentedDB::<DummyStorage, V1>::new(&env.db, env.address.to_vec());
-        let ret = Mpt::<DummyStorage, V1>::new(db.clone());
+        let db = KeyInstrumentedDB::<MemoryDB, V1>::new(&env.db, env.address.to_vec());
+        let ret = Mpt::<MemoryDB, V1>::new(db.clone());
         let key = account_key::<V1>(&env.address, AccountField::Nonce);
         assert_eq!(ret.get(&key).unwrap(), None);
     }
 
     #[test]
     pub fn init_and_add_null_key() {
-        let mut env = TestEnv::default();
-        let db = KeyInstrumentedDB::<DummyStorage, V1>::new(&env.db, env.address.to_vec());
-        let mut ret = Mpt::<DummyStorage, V1>::new(db);
+        let env = TestEnv::default();
+        let db = KeyInstrumentedDB::<MemoryDB, V1>::new(&env.db, env.address.to_vec());
+        let mut ret = Mpt::<MemoryDB, V1>::new(db);
         let mpt_change = ret.close();
         println!("inserts{:?}", &mpt_change.0);
         println!("deletes{:?}", &mpt_change.1);
         println!("root_hash{:?}", &mpt_change.2);
-        env.db.apply_changes(mpt_change.0, mpt_change.1);
-        let db = KeyInstrumentedDB::<DummyStorage, V1>::new(&env.db, env.address.to_vec());
-        let mut ret = Mpt::<DummyStorage, V1>::open(db, mpt_change.2);
+        env.db.apply_changes(mpt_change.clone().into());
+        let db = KeyInstrumentedDB::<MemoryDB, V1>::new(&env.db, env.address.to_vec());
+        let mut ret = Mpt::<MemoryDB, V1>::open(db, mpt_change.2);
         ret.set(
             &RefHasher::hash(PREIMAGE_OF_EMPTY_TRIE_ROOT_HASH),
             RefHasher::hash(EMPTY_TRIE_DUMMY_ROOT_NODE).to_vec(),
@@ -510,9 +1222,9 @@ mod test {
         println!("inserts{:?}", &mpt_change.0);
         println!("deletes{:?}", &mpt_change.1);
         println!("root_hash{:?}", &mpt_change.2);
-        env.db.apply_changes(mpt_change.0, mpt_change.1);
-        let db = KeyInstrumentedDB::<DummyStorage, V1>::new(&env.db, env.address.to_vec());
-        let ret = Mpt::<DummyStorage, V1>::open(db, mpt_change.2);
+        env.db.apply_changes(mpt_change.clone().into());
+        let db = KeyInstrumentedDB::<MemoryDB, V1>::new(&env.db, env.address.to_vec());
+        let ret = Mpt::<MemoryDB, V1>::open(db, mpt_change.2);
         ret.get(&RefHasher::hash(PREIMAGE_OF_EMPTY_TRIE_ROOT_HASH))
             .unwrap();
     }
@@ -526,16 +1238,16 @@ mod test {
     #[test]
     pub fn init_add_delete_and_open() {
         // init the trie
-        let mut env = TestEnv::default();
-        let db = KeyInstrumentedDB::<DummyStorage, V2>::new(&env.db, env.address.to_vec());
-        let mut ret = Mpt::<DummyStorage, V2>::new(db.clone());
+        let env = TestEnv::default();
+        let db = KeyInstrumentedDB::<MemoryDB, V2>::new(&env.db, env.address.to_vec());
+        let mut ret = Mpt::<MemoryDB, V2>::new(db.clone());
         let changes = ret.close();
-        env.db.apply_changes(changes.0, changes.1);
+        env.db.apply_changes(changes.clone().into());
         println!("{:?}", env.address.to_vec());
         // insert 2 pair of key, values into the trie
-        let db = KeyInstrumentedDB::<DummyStorage, V2>::new(&env.db, env.address.to_vec());
+        let db = KeyInstrumentedDB::<MemoryDB, V2>::new(&env.db, env.address.to_vec());
         println!("root_hash after init, {:?}", changes.2);
-        let mut ret = Mpt::<DummyStorage, V2>::open(db, changes.2);
+        let mut ret = Mpt::<MemoryDB, V2>::open(db, changes.2);
         let data_key = b"apple".to_vec();
         let data_value = b"apple_12345".to_vec();
         let data_key_b = b"banana".to_vec();
@@ -555,12 +1267,12 @@ mod test {
             changes.clone().0,
             changes.clone().1
         );
-        env.db.apply_changes(changes.0, changes.1);
+        env.db.apply_changes(changes.clone().into());
 
         // open the trie after insertion and test if can find the first <key, value> pair in the existing trie
-        let db = KeyInstrumentedDB::<DummyStorage, V2>::new(&env.db, env.address.to_vec());
+        let db = KeyInstrumentedDB::<MemoryDB, V2>::new(&env.db, env.address.to_vec());
         println!("root_hash after insert, {:?}", changes.2);
-        let mut ret = Mpt::<DummyStorage, V2>::open(db, changes.2);
+        let mut ret = Mpt::<MemoryDB, V2>::open(db, changes.2);
         assert!(ret.contains(&data_key).unwrap());
         assert!(ret.contains(&data_key_b).unwrap());
         assert_eq!(ret.get(&data_key).unwrap().unwrap(), data_value);
@@ -575,22 +1287,22 @@ mod test {
             changes.clone().0,
             changes.clone().1
         );
-        env.db.apply_changes(changes.0, changes.1);
+        env.db.apply_changes(changes.clone().into());
 
         // open the trie after deletion, and print out the root_hash, and try to test if can find the second <key, value> pair in the trie (which should return None)
-        let db = KeyInstrumentedDB::<DummyStorage, V2>::new(&env.db, env.address.to_vec());
+        let db = KeyInstrumentedDB::<MemoryDB, V2>::new(&env.db, env.address.to_vec());
         println!("root_hash after delete, {:?}", changes.2);
-        let ret = Mpt::<DummyStorage, V2>::open(db, changes.2);
+        let ret = Mpt::<MemoryDB, V2>::open(db, changes.2);
         // assert_eq!(ret.get(&data_key_b).unwrap().unwrap(), data_value_b);
         assert_eq!(ret.get(&data_key_b).unwrap(), None);
     }
 
     #[test]
     fn delete_root() {
-        let mut env = TestEnv::default();
+        let env = TestEnv::default();
         // do insert
-        let db = KeyInstrumentedDB::<DummyStorage, V1>::new(&env.db, env.address.to_vec());
-        let mut ret = Mpt::<DummyStorage, V1>::new(db);
+        let db = KeyInstrumentedDB::<MemoryDB, V1>::new(&env.db, env.address.to_vec());
+        let mut ret = Mpt::<MemoryDB, V1>::new(db);
         let apple_key = b"apple".to_vec();
         let apple_value = b"apple_12345".to_vec();
         let banana_key = b"banana".to_vec();
@@ -598,25 +1310,64 @@ mod test {
         ret.set(&apple_key, apple_value.clone()).unwrap();
         ret.set(&banana_key, banana_value.clone()).unwrap();
         let ws_changes = ret.close();
-        env.db.apply_changes(ws_changes.0, ws_changes.1);
+        env.db.apply_changes(ws_changes.clone().into());
         println!("==================== db after insert ==================");
         println!("{:?}", &env.db);
         // do delete
-        let db = KeyInstrumentedDB::<DummyStorage, V1>::new(&env.db, env.address.to_vec());
-        let mut ret = Mpt::<DummyStorage, V1>::open(db, ws_changes.2);
+        let db = KeyInstrumentedDB::<MemoryDB, V1>::new(&env.db, env.address.to_vec());
+        let mut ret = Mpt::<MemoryDB, V1>::open(db, ws_changes.2);
         ret.remove(&apple_key).unwrap();
         ret.remove(&banana_key).unwrap();
         let ws_changes = ret.close();
-        env.db.apply_changes(ws_changes.0, ws_changes.1);
+        env.db.apply_changes(ws_changes.clone().into());
         println!("==================== db after delete ==================");
         println!("{:?}", &env.db);
         // do de_init
-        let db = KeyInstrumentedDB::<DummyStorage, V1>::new(&env.db, env.address.to_vec());
-        let ret = Mpt::<DummyStorage, V1>::open(db, ws_changes.2);
+        let db = KeyInstrumentedDB::<MemoryDB, V1>::new(&env.db, env.address.to_vec());
+        let ret = Mpt::<MemoryDB, V1>::open(db, ws_changes.2);
         let mut mpt_v2 = ret.deinit_and_upgrade().unwrap();
         let ws_changes = mpt_v2.close();
-        env.db.apply_changes(ws_changes.0, ws_changes.1);
+        env.db.apply_changes(ws_changes.clone().into());
         println!("==================== db after deinit ==================");
         println!("{:?}", &env.db);
     }
+
+    #[test]
+    fn batch_get() {
+        let env = TestEnv::default();
+        let mut data: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+        for i in 0..200_u32 {
+            // keys of different length so that nodes are both hashed and inlined
+            let key = i.to_le_bytes()[..(i % 4 + 1) as usize].to_vec();
+            data.insert(key, i.to_be_bytes().to_vec());
+        }
+        let db = KeyInstrumentedDB::<MemoryDB, V2>::new(&env.db, env.address.to_vec());
+        let mut mpt = Mpt::<MemoryDB, V2>::new(db);
+        mpt.batch_set(&data).unwrap();
+        let changes = mpt.close();
+        env.db.apply_changes(changes.clone().into());
+
+        let db = KeyInstrumentedDB::<MemoryDB, V2>::new(&env.db, env.address.to_vec());
+        let mut mpt = Mpt::<MemoryDB, V2>::open(db, changes.2);
+        // uncommitted changes are read from the memory cache
+        mpt.set(b"uncommitted", b"value".to_vec()).unwrap();
+        let mut keys: Vec<Vec<u8>> = data.keys().cloned().collect();
+        keys.extend([
+            b"uncommitted".to_vec(),
+            b"not_exist".to_vec(),
+            vec![],
+            vec![0, 0, 0, 0, 0],
+        ]);
+        let values = mpt.batch_get(&keys).unwrap();
+        for (key, value) in keys.iter().zip(values) {
+            assert_eq!(mpt.get(key).unwrap(), value);
+        }
+
+        let db = KeyInstrumentedDB::<MemoryDB, V2>::new(&env.db, env.address.to_vec());
+        let mpt = Mpt::<MemoryDB, V2>::open(db, [1u8; 32]);
+        assert_eq!(
+            mpt.batch_get(&keys).unwrap_err(),
+            MptError::InvalidStateRoot
+        );
+    }
 }
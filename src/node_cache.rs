@@ -0,0 +1,286 @@
+/*
+    Copyright © 2023, ParallelChain Lab
+    Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
+*/
+
+//! This mod provides [NodeCache], a size-bounded LRU cache of trie nodes shared across WorldStates.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{
+    db::{DBWrite, WriteBatch},
+    error::DbError,
+    world_state::WorldStateChanges,
+};
+
+/// `NodeCache` is a read-through cache of trie nodes keyed by physical key, which is consulted by
+/// [KeyInstrumentedDB](crate::db::KeyInstrumentedDB) before reading the persistent storage.
+///
+/// The cache is bounded by the total length in bytes of the cached keys and values. The least recently
+/// used nodes are evicted when the bound is exceeded.
+///
+/// Cloning a NodeCache returns a handle to the same cache, so that it can be shared by the WorldStates opened
+/// on the same database, e.g. by [WorldState::set_node_cache](crate::world_state::WorldState::set_node_cache).
+/// Every write into the database must invalidate the written keys in the cache. [NodeCache::write] and
+/// [NodeCache::apply_changes] write into the database and then invalidate, and they are used by
+/// [WorldState::commit](crate::world_state::WorldState::commit), and by [Pruner](crate::pruning::Pruner) and
+/// [BlockOverlay](crate::overlay::BlockOverlay) with the NodeCache set. Callers writing into the database by other
+/// means must call [NodeCache::invalidate] after the write.
+///
+/// A node read from the database is not cached if the cache is invalidated while it is being read, because the
+/// node may be deleted by the write that the invalidation follows.
+#[derive(Clone)]
+pub struct NodeCache {
+    shared: Arc<SharedNodeCache>,
+}
+
+struct SharedNodeCache {
+    nodes: Mutex<LruNodes>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// `NodeCacheStats` is the statistics of a [NodeCache]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeCacheStats {
+    /// number of reads found in the cache
+    pub hits: u64,
+    /// number of reads not found in the cache
+    pub misses: u64,
+    /// number of cached nodes
+    pub num_nodes: usize,
+    /// total length in bytes of the cached keys and values
+    pub size_bytes: usize,
+}
+
+impl NodeCache {
+    /// `new` is to create an empty NodeCache holding at most `capacity_bytes` bytes of keys and values
+    pub fn new(capacity_bytes: usize) -> Self {
+        NodeCache {
+            shared: Arc::new(SharedNodeCache {
+                nodes: Mutex::new(LruNodes::new(capacity_bytes)),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// `get` returns the cached value of the physical key and marks it as recently used
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let value = self.shared.nodes.lock().unwrap().get(key);
+        match value {
+            Some(_) => self.shared.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.shared.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        value
+    }
+
+    /// `insert` caches the value of the physical key, evicting the least recently used nodes if needed
+    pub fn insert(&self, key: Vec<u8>, value: Vec<u8>) {
+        self.shared.nodes.lock().unwrap().insert(key, value);
+    }
+
+    /// `generation` is the number of invalidations so far. It is taken before reading a node from the database,
+    /// so that the node is cached by [NodeCache::insert_at_generation] only if no invalidation happened in between.
+    pub(crate) fn generation(&self) -> u64 {
+        self.shared.nodes.lock().unwrap().generation
+    }
+
+    /// `insert_at_generation` caches the value of the physical key read from the database at the `generation`,
+    /// unless the cache has been invalidated since then
+    pub(crate) fn insert_at_generation(&self, generation: u64, key: Vec<u8>, value: Vec<u8>) {
+        let mut nodes = self.shared.nodes.lock().unwrap();
+        if nodes.generation == generation {
+            nodes.insert(key, value);
+        }
+    }
+
+    /// `write` writes the batch into the database, and then removes the keys inserted or deleted by the batch from the cache
+    pub fn write<S: DBWrite>(&self, db: &S, batch: WriteBatch) -> Result<(), DbError> {
+        let keys: Vec<Vec<u8>> = batch
+            .inserts
+            .keys()
+            .chain(batch.deletes.iter())
+            .cloned()
+            .collect();
+        let result = db.write(batch);
+        // the database may have applied part of a failed write
+        self.invalidate_keys(keys.iter());
+        result
+    }
+
+    /// `apply_changes` writes the [WorldStateChanges] into the database by [NodeCache::write]. It replaces
+    /// [MemoryDB::apply_changes](crate::db::MemoryDB::apply_changes) for the databases shared with the cache.
+    pub fn apply_changes<S: DBWrite>(
+        &self,
+        db: &S,
+        changes: WorldStateChanges,
+    ) -> Result<(), DbError> {
+        self.write(db, changes.into())
+    }
+
+    /// `invalidate` removes the keys inserted or deleted by the changes from the cache.
+    /// It must be called after the changes are applied to the database.
+    pub fn invalidate(&self, changes: &WorldStateChanges) {
+        self.invalidate_keys(changes.inserts.keys().chain(changes.deletes.iter()));
+    }
+
+    fn invalidate_keys<'k>(&self, keys: impl Iterator<Item = &'k Vec<u8>>) {
+        let mut nodes = self.shared.nodes.lock().unwrap();
+        nodes.generation += 1;
+        for key in keys {
+            nodes.remove(key);
+        }
+    }
+
+    /// `clear` removes all cached nodes. The hit and miss counters are kept.
+    pub fn clear(&self) {
+        let mut nodes = self.shared.nodes.lock().unwrap();
+        nodes.generation += 1;
+        nodes.clear();
+    }
+
+    /// `stats` returns the hit and miss counters and the size of the cache
+    pub fn stats(&self) -> NodeCacheStats {
+        let nodes = self.shared.nodes.lock().unwrap();
+        NodeCacheStats {
+            hits: self.shared.hits.load(Ordering::Relaxed),
+            misses: self.shared.misses.load(Ordering::Relaxed),
+            num_nodes: nodes.entries.len(),
+            size_bytes: nodes.size_bytes,
+        }
+    }
+}
+
+impl fmt::Debug for NodeCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeCache")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// `LruNodes` is the cached nodes with their last used time, in a logical clock `tick`
+struct LruNodes {
+    capacity_bytes: usize,
+    size_bytes: usize,
+    tick: u64,
+    // number of invalidations, to drop the nodes read from the database before the last invalidation
+    generation: u64,
+    entries: HashMap<Vec<u8>, (Vec<u8>, u64)>,
+    // keys ordered by last used time
+    order: BTreeMap<u64, Vec<u8>>,
+}
+
+impl LruNodes {
+    fn new(capacity_bytes: usize) -> Self {
+        LruNodes {
+            capacity_bytes,
+            size_bytes: 0,
+            tick: 0,
+            generation: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let (value, last_used) = self.entries.get_mut(key)?;
+        let key = self.order.remove(last_used).unwrap();
+        self.tick += 1;
+        *last_used = self.tick;
+        self.order.insert(self.tick, key);
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.remove(&key);
+        let size = key.len() + value.len();
+        if size > self.capacity_bytes {
+            return;
+        }
+        while self.size_bytes + size > self.capacity_bytes {
+            let (_, lru_key) = self.order.pop_first().unwrap();
+            let (lru_value, _) = self.entries.remove(&lru_key).unwrap();
+            self.size_bytes -= lru_key.len() + lru_value.len();
+        }
+        self.tick += 1;
+        self.size_bytes += size;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some((value, last_used)) = self.entries.remove(key) {
+            self.order.remove(&last_used);
+            self.size_bytes -= key.len() + value.len();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.size_bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn evict_least_recently_used() {
+        let cache = NodeCache::new(12);
+        cache.insert(b"a".to_vec(), b"aaa".to_vec());
+        cache.insert(b"b".to_vec(), b"bbb".to_vec());
+        cache.insert(b"c".to_vec(), b"ccc".to_vec());
+        assert_eq!(cache.stats().size_bytes, 12);
+        // "a" becomes the most recently used, "b" is evicted
+        assert_eq!(cache.get(b"a"), Some(b"aaa".to_vec()));
+        cache.insert(b"d".to_vec(), b"ddd".to_vec());
+        assert_eq!(cache.get(b"b"), None);
+        assert_eq!(cache.get(b"a"), Some(b"aaa".to_vec()));
+        assert_eq!(cache.get(b"c"), Some(b"ccc".to_vec()));
+        assert_eq!(cache.get(b"d"), Some(b"ddd".to_vec()));
+        // replace the value of a cached key
+        cache.insert(b"c".to_vec(), b"c".to_vec());
+        assert_eq!(cache.get(b"c"), Some(b"c".to_vec()));
+        assert_eq!(cache.stats().size_bytes, 10);
+        // node larger than the capacity is not cached
+        cache.insert(b"e".to_vec(), vec![0; 12]);
+        assert_eq!(cache.get(b"e"), None);
+        let stats = cache.stats();
+        assert_eq!(stats.num_nodes, 3);
+        assert_eq!(stats.hits, 5);
+        assert_eq!(stats.misses, 2);
+    }
+
+    #[test]
+    fn drop_nodes_read_before_invalidation() {
+        let cache = NodeCache::new(12);
+        // "a" is read from the database, and then deleted and invalidated by a concurrent commit before it is cached
+        let generation = cache.generation();
+        cache.invalidate(&WorldStateChanges {
+            inserts: HashMap::new(),
+            deletes: [b"a".to_vec()].into(),
+            new_root_hash: [0; 32],
+        });
+        cache.insert_at_generation(generation, b"a".to_vec(), b"aaa".to_vec());
+        assert_eq!(cache.get(b"a"), None);
+        let generation = cache.generation();
+        cache.insert_at_generation(generation, b"a".to_vec(), b"aaa".to_vec());
+        assert_eq!(cache.get(b"a"), Some(b"aaa".to_vec()));
+        // clear also drops the nodes being read
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert_at_generation(generation, b"b".to_vec(), b"bbb".to_vec());
+        assert_eq!(cache.get(b"b"), None);
+    }
+}
@@ -0,0 +1,220 @@
+/*
+    Copyright © 2023, ParallelChain Lab
+    Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
+*/
+
+//! This mod provides [BlockOverlay] to keep the [WorldStateChanges] of speculative blocks in memory, on top of the
+//! persistent [DB]. The blocks form a tree, e.g. the uncommitted blocks on different branches in a HotStuff-style
+//! consensus. A WorldState can be opened on the state root of any speculative block by [OverlayView], which reads
+//! through the changes of the block and its ancestors.
+
+use std::collections::{HashMap, HashSet};
+
+use pchain_types::cryptography::Sha256Hash;
+
+use crate::{
+    db::{DBWrite, DB},
+    error::{DbError, OverlayError, WorldStateError},
+    node_cache::NodeCache,
+    world_state::WorldStateChanges,
+};
+
+/// `BlockOverlay` keeps the [WorldStateChanges] of speculative blocks by block hash, each on top of its parent block,
+/// or on top of the persistent database if it has no parent in the overlay.
+///
+/// A block is executed on the [OverlayView] of its parent, and its changes are added by [BlockOverlay::insert].
+/// When a block is committed by [BlockOverlay::commit], the changes of the block and its ancestors are written into
+/// the database, and the blocks not descending from it are discarded. If the database is read through a [NodeCache],
+/// it must be set by [BlockOverlay::set_node_cache], so that the committed changes are invalidated in the cache.
+#[derive(Debug, Clone)]
+pub struct BlockOverlay<'a, S: DB> {
+    db: &'a S,
+    blocks: HashMap<Sha256Hash, OverlayBlock>,
+    node_cache: Option<NodeCache>,
+}
+
+/// `OverlayBlock` is a speculative block in [BlockOverlay]
+#[derive(Debug, Clone)]
+struct OverlayBlock {
+    parent: Option<Sha256Hash>,
+    changes: WorldStateChanges,
+}
+
+impl<'a, S: DB> BlockOverlay<'a, S> {
+    /// `new` is to create an empty BlockOverlay on the database
+    pub fn new(db: &'a S) -> Self {
+        BlockOverlay {
+            db,
+            blocks: HashMap::new(),
+            node_cache: None,
+        }
+    }
+
+    /// `set_node_cache` sets the [NodeCache] invalidated by the writes of `commit`
+    pub fn set_node_cache(&mut self, node_cache: NodeCache) {
+        self.node_cache = Some(node_cache);
+    }
+
+    /// `insert` adds the changes of a block on top of the parent block, or on top of the database if parent is None
+    ///
+    /// Error if the block is already in the overlay or the parent block is not
+    pub fn insert(
+        &mut self,
+        block_hash: Sha256Hash,
+        parent: Option<Sha256Hash>,
+        changes: WorldStateChanges,
+    ) -> Result<(), OverlayError> {
+        if self.blocks.contains_key(&block_hash) {
+            return Err(OverlayError::DuplicateBlock);
+        }
+        if let Some(parent) = &parent {
+            if !self.blocks.contains_key(parent) {
+                return Err(OverlayError::UnknownBlock);
+            }
+        }
+        self.blocks
+            .insert(block_hash, OverlayBlock { parent, changes });
+        Ok(())
+    }
+
+    /// `contains` checks if the block is in the overlay
+    pub fn contains(&self, block_hash: &Sha256Hash) -> bool {
+        self.blocks.contains_key(block_hash)
+    }
+
+    /// `len` is the number of blocks in the overlay
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// `is_empty` checks if there is no block in the overlay
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// `state_root` returns the state root after the block, None if the block is not in the overlay
+    pub fn state_root(&self, block_hash: &Sha256Hash) -> Option<Sha256Hash> {
+        self.blocks
+            .get(block_hash)
+            .map(|block| block.changes.new_root_hash)
+    }
+
+    /// `view` returns the [OverlayView] of the state after the block, on which a WorldState can be opened by
+    /// the state root of the block to read the state or to execute a child block.
+    ///
+    /// Error if the block is not in the overlay
+    pub fn view(&self, block_hash: &Sha256Hash) -> Result<OverlayView<'_, S>, OverlayError> {
+        let chain = self
+            .chain(block_hash)?
+            .into_iter()
+            .map(|block| &block.changes)
+            .collect();
+        Ok(OverlayView { db: self.db, chain })
+    }
+
+    /// `discard` removes the block and all its descendants, e.g. a branch which can no longer be committed
+    ///
+    /// Error if the block is not in the overlay
+    pub fn discard(&mut self, block_hash: &Sha256Hash) -> Result<(), OverlayError> {
+        if !self.blocks.contains_key(block_hash) {
+            return Err(OverlayError::UnknownBlock);
+        }
+        let descendants: HashSet<Sha256Hash> = self
+            .blocks
+            .keys()
+            .filter(|hash| self.descends_from(hash, block_hash))
+            .copied()
+            .collect();
+        self.blocks.retain(|hash, _| !descendants.contains(hash));
+        Ok(())
+    }
+
+    /// `chain` returns the block and its ancestors in the overlay, from the block to the oldest ancestor
+    fn chain(&self, block_hash: &Sha256Hash) -> Result<Vec<&OverlayBlock>, OverlayError> {
+        let mut chain = Vec::new();
+        let mut next = Some(*block_hash);
+        while let Some(hash) = next {
+            let block = self.blocks.get(&hash).ok_or(OverlayError::UnknownBlock)?;
+            chain.push(block);
+            next = block.parent;
+        }
+        Ok(chain)
+    }
+
+    /// `descends_from` checks if the block is the ancestor block or one of its descendants
+    fn descends_from(&self, block_hash: &Sha256Hash, ancestor: &Sha256Hash) -> bool {
+        let mut next = Some(*block_hash);
+        while let Some(hash) = next {
+            if hash == *ancestor {
+                return true;
+            }
+            next = self.blocks.get(&hash).and_then(|block| block.parent);
+        }
+        false
+    }
+}
+
+/// implementations for BlockOverlay over a writable database
+impl<'a, S: DBWrite> BlockOverlay<'a, S> {
+    /// `commit` writes the changes of the block and its ancestors into the database in one atomic commit, in the order
+    /// of the blocks. The committed blocks and the blocks not descending from the block are removed, and the children
+    /// of the block are then on top of the database. Return the state root after the block.
+    ///
+    /// Error if the block is not in the overlay, or failed to write into the database
+    pub fn commit(&mut self, block_hash: &Sha256Hash) -> Result<Sha256Hash, WorldStateError> {
+        let chain = self.chain(block_hash)?;
+        let changes = WorldStateChanges::merge_all(
+            chain.into_iter().rev().map(|block| block.changes.clone()),
+        )
+        .unwrap();
+        let new_root_hash = changes.new_root_hash;
+        match &self.node_cache {
+            Some(node_cache) => node_cache.apply_changes(self.db, changes)?,
+            None => self.db.write(changes.into())?,
+        }
+
+        let descendants: HashSet<Sha256Hash> = self
+            .blocks
+            .keys()
+            .filter(|hash| *hash != block_hash && self.descends_from(hash, block_hash))
+            .copied()
+            .collect();
+        self.blocks.retain(|hash, _| descendants.contains(hash));
+        for block in self.blocks.values_mut() {
+            if block.parent.as_ref() == Some(block_hash) {
+                block.parent = None;
+            }
+        }
+        Ok(new_root_hash)
+    }
+}
+
+/// `OverlayView` is the state after a speculative block in [BlockOverlay], returned by [BlockOverlay::view].
+///
+/// It implements [DB]: a physical key is read from the changes of the block and then its ancestors, and from the
+/// persistent database if no block changed it.
+#[derive(Debug, Clone)]
+pub struct OverlayView<'o, S: DB> {
+    db: &'o S,
+    // changes of the block and its ancestors, from the block to the oldest ancestor
+    chain: Vec<&'o WorldStateChanges>,
+}
+
+impl<'o, S: DB> DB for OverlayView<'o, S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.try_get(key).ok().flatten()
+    }
+
+    fn try_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
+        for changes in self.chain.iter() {
+            // deletes are applied before inserts
+            if let Some(value) = changes.inserts.get(key) {
+                return Ok(Some(value.clone()));
+            }
+            if changes.deletes.contains(key) {
+                return Ok(None);
+            }
+        }
+        self.db.try_get(key)
+    }
+}
@@ -0,0 +1,421 @@
+/*
+    Copyright © 2023, ParallelChain Lab
+    Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
+*/
+
+//! This mod provides functions to verify the proofs returned by the `*_with_proof` methods of
+//! [AccountsTrie](crate::accounts_trie::AccountsTrie), [StorageTrie](crate::storage_trie::StorageTrie)
+//! and [WorldState](crate::world_state::WorldState), without access to the database.
+
+use std::collections::HashMap;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use hash_db::Hasher;
+use pchain_types::cryptography::{PublicAddress, Sha256Hash};
+use trie_db::node::{Node, NodeHandle, Value};
+use trie_db::{NibbleSlice, NodeCodec, TrieLayout};
+
+use crate::{
+    accounts_trie::{account_key, AccountField},
+    error::ProofError,
+    mpt::{proof_level, Proof},
+    storage_trie::storage_key,
+    trie_layout::{NoExtensionLayout, RefHasher},
+    verify::{self, strip_proof_level, verify_trie_proof},
+    version::VersionProvider,
+};
+
+/// `WorldStateProof` is the proof of a key in the storage of an account against the state root, which is returned by
+/// [WorldState::storage_with_proof](crate::world_state::WorldState::storage_with_proof) and verified by [verify_world_state_proof].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorldStateProof {
+    /// storage hash of the account, None if the account has no storage
+    pub storage_hash: Option<Sha256Hash>,
+    /// proof nodes of the storage hash in AccountsTrie in proof level ACCOUNTS, followed by
+    /// proof nodes of the key in StorageTrie in proof level STORAGE
+    pub proof: Proof,
+}
+
+/// `AccountProofBundle` is the account fields of an account with their proofs against the state root, and the storage values
+/// of some keys of the account with their proofs against the storage hash, in the shape of the answer of `eth_getProof` (EIP-1186).
+/// It is returned by [WorldState::account_proof_bundle](crate::world_state::WorldState::account_proof_bundle) and verified by
+/// [verify_account_proof_bundle].
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct AccountProofBundle {
+    pub address: PublicAddress,
+    /// None if the nonce is not found in world state
+    pub nonce: Option<u64>,
+    /// None if the balance is not found in world state
+    pub balance: Option<u64>,
+    /// hash of the contract code by the hasher of the trie, None if the account has no contract code
+    pub code_hash: Option<Sha256Hash>,
+    pub cbi_version: Option<u32>,
+    /// storage hash of the account, None if the account has no storage
+    pub storage_hash: Option<Sha256Hash>,
+    /// proof nodes of all the account fields in AccountsTrie in proof level ACCOUNTS
+    pub account_proof: Proof,
+    /// proofs of the storage keys in the same order as the requested keys
+    pub storage_proofs: Vec<StorageProof>,
+}
+
+impl AccountProofBundle {
+    /// `hash_code` returns the hash of the contract code, as `code_hash` of the bundle
+    pub fn hash_code(code: &[u8]) -> Sha256Hash {
+        RefHasher::hash(code)
+    }
+}
+
+/// `StorageProof` is the storage value of a key with its proof against the storage hash, as part of [AccountProofBundle]
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct StorageProof {
+    pub key: Vec<u8>,
+    /// None if the key is not found in the storage
+    pub value: Option<Vec<u8>>,
+    /// proof nodes of the key in StorageTrie in proof level STORAGE, empty if the account has no storage
+    pub proof: Proof,
+}
+
+/// `verify_account_field_proof` verifies the proof of an account field against the state root of the AccountsTrie,
+/// which is returned by e.g. [AccountsTrie::balance_with_proof](crate::accounts_trie::AccountsTrie::balance_with_proof).
+/// The key layout of AccountsTrie is chosen by the version `V`.
+///
+/// `expected` is the value stored in the trie, i.e. little-endian bytes of nonce, balance and cbi version, the code,
+/// or the storage hash. None verifies that the field is absent.
+///
+/// Error when the proof does not show the expected value under the state root
+pub fn verify_account_field_proof<V: VersionProvider>(
+    root: &Sha256Hash,
+    address: &PublicAddress,
+    field: AccountField,
+    proof: &Proof,
+    expected: Option<&[u8]>,
+) -> Result<(), ProofError> {
+    verify::verify_account_field::<V>(root, address, field, proof, expected)
+}
+
+/// `verify_account_field_absence` verifies the proof that an account field is not in the AccountsTrie of the state root,
+/// which is returned by [AccountsTrie::absence_proof](crate::accounts_trie::AccountsTrie::absence_proof),
+/// e.g. to prove that an account has no contract code.
+///
+/// Error when the proof does not show the absence of the field under the state root
+pub fn verify_account_field_absence<V: VersionProvider>(
+    root: &Sha256Hash,
+    address: &PublicAddress,
+    field: AccountField,
+    proof: &Proof,
+) -> Result<(), ProofError> {
+    verify_account_field_proof::<V>(root, address, field, proof, None)
+}
+
+/// `verify_account_fields_proof` verifies the proof of many account fields against the state root of the AccountsTrie,
+/// which is returned by [AccountsTrie::fields_with_proof](crate::accounts_trie::AccountsTrie::fields_with_proof).
+/// The key layout of AccountsTrie is chosen by the version `V`.
+///
+/// `expected` is the account fields with their values stored in the trie, in any order. None verifies that the field is absent.
+///
+/// Error when the proof does not show all the expected values under the state root. All the account fields proved by the proof
+/// with a value must be expected.
+pub fn verify_account_fields_proof<V: VersionProvider>(
+    root: &Sha256Hash,
+    proof: &Proof,
+    expected: &[(PublicAddress, AccountField, Option<&[u8]>)],
+) -> Result<(), ProofError> {
+    let items = expected
+        .iter()
+        .map(|(address, field, value)| (account_key::<V>(address, *field), *value))
+        .collect();
+    verify_trie_proof(root, proof_level::ACCOUNTS, proof, items)
+}
+
+/// `verify_storage_proof` verifies the proof of a key in the storage of an account against the storage hash of the account,
+/// which is returned by [StorageTrie::get_with_proof](crate::storage_trie::StorageTrie::get_with_proof).
+/// The key layout of StorageTrie is chosen by the version `V`.
+///
+/// `expected` is the value stored in the trie. None verifies that the key is absent.
+///
+/// Error when the proof does not show the expected value under the storage hash
+pub fn verify_storage_proof<V: VersionProvider>(
+    storage_root: &Sha256Hash,
+    key: &[u8],
+    proof: &Proof,
+    expected: Option<&[u8]>,
+) -> Result<(), ProofError> {
+    verify::verify_storage::<V>(storage_root, key, proof, expected)
+}
+
+/// `verify_storage_absence` verifies the proof that a key is not in the storage of an account, which is returned by
+/// [StorageTrie::absence_proof](crate::storage_trie::StorageTrie::absence_proof). Use [verify_world_state_proof] with
+/// expected value None to verify the absence against the state root instead of the storage hash.
+///
+/// Error when the proof does not show the absence of the key under the storage hash
+pub fn verify_storage_absence<V: VersionProvider>(
+    storage_root: &Sha256Hash,
+    key: &[u8],
+    proof: &Proof,
+) -> Result<(), ProofError> {
+    verify_storage_proof::<V>(storage_root, key, proof, None)
+}
+
+/// `verify_storage_batch_proof` verifies the proof of many keys in the storage of an account against the storage hash
+/// of the account, which is returned by [StorageTrie::batch_get_with_proof](crate::storage_trie::StorageTrie::batch_get_with_proof).
+/// The key layout of StorageTrie is chosen by the version `V`.
+///
+/// `expected` is the keys with their values stored in the trie, in any order. None verifies that the key is absent.
+///
+/// Error when the proof does not show all the expected values under the storage hash. All the keys proved by the proof
+/// with a value must be expected.
+pub fn verify_storage_batch_proof<V: VersionProvider>(
+    storage_root: &Sha256Hash,
+    proof: &Proof,
+    expected: &[(&[u8], Option<&[u8]>)],
+) -> Result<(), ProofError> {
+    let items = expected
+        .iter()
+        .map(|(key, value)| (storage_key::<V>(&key.to_vec()), *value))
+        .collect();
+    verify_trie_proof(storage_root, proof_level::STORAGE, proof, items)
+}
+
+/// `verify_accounts_range_proof` verifies the proof that the account fields are all the account fields of the account addresses
+/// in `start..end` under the state root, with nothing omitted, which is returned by
+/// [AccountsTrie::range_with_proof](crate::accounts_trie::AccountsTrie::range_with_proof). The range is not bounded above if
+/// `end` is None. The key layout of AccountsTrie is chosen by the version `V`.
+///
+/// `expected` is the account fields with their values stored in the trie, ordered by account address as returned with the proof.
+///
+/// Error when the proof does not show exactly the expected account fields in the range under the state root
+pub fn verify_accounts_range_proof<V: VersionProvider>(
+    root: &Sha256Hash,
+    start: &PublicAddress,
+    end: Option<&PublicAddress>,
+    proof: &Proof,
+    expected: &[(PublicAddress, AccountField, Vec<u8>)],
+) -> Result<(), ProofError> {
+    let nodes = strip_proof_level(proof_level::ACCOUNTS, proof)?;
+    let items = range_items(root, &nodes, start, end.map(|end| end.as_slice()))?;
+    let expected = expected
+        .iter()
+        .map(|(address, field, value)| (account_key::<V>(address, *field), value.clone()));
+    match items.into_iter().eq(expected) {
+        true => Ok(()),
+        false => Err(ProofError::RangeMismatch),
+    }
+}
+
+/// `verify_storage_range_proof` verifies the proof that the key-value pairs are all the key-value pairs with keys in
+/// `start..end` in the storage of an account, with nothing omitted, which is returned by
+/// [StorageTrie::range_with_proof](crate::storage_trie::StorageTrie::range_with_proof). The range is not bounded above
+/// if `end` is None. The key layout of StorageTrie is chosen by the version `V`.
+///
+/// `expected` is the key-value pairs stored in the trie, ordered by key as returned with the proof.
+///
+/// Error when the proof does not show exactly the expected key-value pairs in the range under the storage hash
+pub fn verify_storage_range_proof<V: VersionProvider>(
+    storage_root: &Sha256Hash,
+    start: &[u8],
+    end: Option<&[u8]>,
+    proof: &Proof,
+    expected: &[(Vec<u8>, Vec<u8>)],
+) -> Result<(), ProofError> {
+    let nodes = strip_proof_level(proof_level::STORAGE, proof)?;
+    let start = storage_key::<V>(&start.to_vec());
+    let end = end.map(|end| storage_key::<V>(&end.to_vec()));
+    let items = range_items(storage_root, &nodes, &start, end.as_deref())?;
+    let expected = expected
+        .iter()
+        .map(|(key, value)| (storage_key::<V>(key), value.clone()));
+    match items.into_iter().eq(expected) {
+        true => Ok(()),
+        false => Err(ProofError::RangeMismatch),
+    }
+}
+
+/// `verify_world_state_proof` verifies the proof of a key in the storage of an account against only the state root.
+/// The storage hash carried by the proof is verified against the state root first, and then the key is verified against
+/// the storage hash. The key layouts are chosen by the version `V`.
+///
+/// `expected` is the value stored in the storage. None verifies that the key is absent, including the case that
+/// the account has no storage.
+///
+/// Error when the proof does not show the expected value under the state root
+pub fn verify_world_state_proof<V: VersionProvider>(
+    state_root: &Sha256Hash,
+    address: &PublicAddress,
+    key: &[u8],
+    proof: &WorldStateProof,
+    expected: Option<&[u8]>,
+) -> Result<(), ProofError> {
+    verify::verify_world_state::<V>(
+        state_root,
+        address,
+        key,
+        proof.storage_hash.as_ref(),
+        &proof.proof,
+        expected,
+    )
+}
+
+/// `verify_account_proof_bundle` verifies all the account fields and storage values in the bundle against the state root,
+/// which is returned by [WorldState::account_proof_bundle](crate::world_state::WorldState::account_proof_bundle).
+/// The key layouts are chosen by the version `V`.
+///
+/// `code` is the contract code of the account, which is required to verify the proof of the account fields because the proof
+/// does not carry the values. It must match `code_hash` of the bundle.
+///
+/// Error when the proofs do not show the account fields or any storage value of the bundle under the state root
+pub fn verify_account_proof_bundle<V: VersionProvider>(
+    state_root: &Sha256Hash,
+    bundle: &AccountProofBundle,
+    code: Option<&[u8]>,
+) -> Result<(), ProofError> {
+    if code.map(AccountProofBundle::hash_code) != bundle.code_hash {
+        return Err(ProofError::ValueMismatch);
+    }
+    let nonce = bundle.nonce.map(|nonce| nonce.to_le_bytes());
+    let balance = bundle.balance.map(|balance| balance.to_le_bytes());
+    let cbi_version = bundle
+        .cbi_version
+        .map(|cbi_version| cbi_version.to_le_bytes());
+    let values: [Option<&[u8]>; 5] = [
+        nonce.as_ref().map(|value| value.as_slice()),
+        balance.as_ref().map(|value| value.as_slice()),
+        code,
+        cbi_version.as_ref().map(|value| value.as_slice()),
+        bundle.storage_hash.as_ref().map(|hash| hash.as_slice()),
+    ];
+    let expected: Vec<(PublicAddress, AccountField, Option<&[u8]>)> = AccountField::ALL
+        .into_iter()
+        .zip(values)
+        .map(|(field, value)| (bundle.address, field, value))
+        .collect();
+    verify_account_fields_proof::<V>(state_root, &bundle.account_proof, &expected)?;
+
+    for storage_proof in bundle.storage_proofs.iter() {
+        match &bundle.storage_hash {
+            Some(storage_hash) => verify_storage_proof::<V>(
+                storage_hash,
+                &storage_proof.key,
+                &storage_proof.proof,
+                storage_proof.value.as_deref(),
+            )?,
+            None if !storage_proof.proof.is_empty() => return Err(ProofError::InvalidProof),
+            None if storage_proof.value.is_some() => return Err(ProofError::ValueMismatch),
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// `range_items` walks through the trie nodes of the range proof from the root, and returns all the key-value pairs
+/// with trie keys in `start..end` ordered by key. Every trie node overlapping with the range must be in the proof,
+/// so that no key-value pair in the range can be omitted.
+fn range_items(
+    root: &Sha256Hash,
+    nodes: &[Vec<u8>],
+    start: &[u8],
+    end: Option<&[u8]>,
+) -> Result<RangeItems, ProofError> {
+    let mut walk = RangeWalk {
+        nodes: nodes
+            .iter()
+            .map(|node| (RefHasher::hash(node), node.as_slice()))
+            .collect(),
+        start: nibbles_of_bytes(start),
+        end: end.map(nibbles_of_bytes),
+        items: Vec::new(),
+    };
+    let root_node = *walk.nodes.get(root).ok_or(ProofError::RootMismatch)?;
+    walk.walk(root_node, Vec::new())?;
+    Ok(walk.items)
+}
+
+/// `RangeItems` is the key-value pairs in a range, ordered by key
+type RangeItems = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// `RangeWalk` is the state of [range_items], with the range in nibbles
+struct RangeWalk<'p> {
+    nodes: HashMap<Sha256Hash, &'p [u8]>,
+    start: Vec<u8>,
+    end: Option<Vec<u8>>,
+    items: RangeItems,
+}
+
+impl<'p> RangeWalk<'p> {
+    /// `walk` visits the encoded trie node with the nibbles of the path to it, and then its children overlapping with the range
+    fn walk(&mut self, node_data: &'p [u8], mut path: Vec<u8>) -> Result<(), ProofError> {
+        let node = <NoExtensionLayout as TrieLayout>::Codec::decode(node_data)
+            .map_err(|_| ProofError::InvalidProof)?;
+        let (partial, children, value) = match node {
+            Node::Empty => return Ok(()),
+            Node::Leaf(partial, value) => (partial, [None; 16], Some(value)),
+            Node::Branch(children, value) => (NibbleSlice::new(&[]), children, value),
+            Node::NibbledBranch(partial, children, value) => (partial, children, value),
+            // NoExtensionLayout does not use extension nodes
+            Node::Extension(..) => return Err(ProofError::InvalidProof),
+        };
+        path.extend((0..partial.len()).map(|i| partial.at(i)));
+        if !self.overlaps(&path) {
+            return Ok(());
+        }
+        if let Some(value) = value {
+            if path.len().is_multiple_of(2) && self.contains(&path) {
+                let value = match value {
+                    Value::Inline(value) => value.to_vec(),
+                    Value::Node(hash) => self.node(hash)?.to_vec(),
+                };
+                self.items.push((bytes_of_nibbles(&path), value));
+            }
+        }
+        for (nibble, child) in children.into_iter().enumerate() {
+            let child = match child {
+                Some(child) => child,
+                None => continue,
+            };
+            let mut child_path = path.clone();
+            child_path.push(nibble as u8);
+            if !self.overlaps(&child_path) {
+                continue;
+            }
+            let child_data = match child {
+                NodeHandle::Hash(hash) => self.node(hash)?,
+                NodeHandle::Inline(data) => data,
+            };
+            self.walk(child_data, child_path)?;
+        }
+        Ok(())
+    }
+
+    /// `node` returns the trie node of the hash in the proof
+    fn node(&self, hash: &[u8]) -> Result<&'p [u8], ProofError> {
+        let hash: Sha256Hash = hash.try_into().map_err(|_| ProofError::InvalidProof)?;
+        self.nodes
+            .get(&hash)
+            .copied()
+            .ok_or(ProofError::IncompleteProof)
+    }
+
+    /// `overlaps` checks if a key starting with the nibbles can be in the range
+    fn overlaps(&self, path: &[u8]) -> bool {
+        path >= &self.start[..path.len().min(self.start.len())]
+            && self.end.as_ref().is_none_or(|end| path < end.as_slice())
+    }
+
+    /// `contains` checks if the key of the nibbles is in the range
+    fn contains(&self, path: &[u8]) -> bool {
+        path >= self.start.as_slice() && self.end.as_ref().is_none_or(|end| path < end.as_slice())
+    }
+}
+
+fn nibbles_of_bytes(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+fn bytes_of_nibbles(nibbles: &[u8]) -> Vec<u8> {
+    nibbles
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect()
+}
@@ -0,0 +1,272 @@
+/*
+    Copyright © 2023, ParallelChain Lab
+    Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
+*/
+
+//! This mod provides [Pruner], which commits [WorldStateChanges] into a [DBWrite] with a journal of the
+//! trie nodes inserted and removed by every committed state root, and deletes the trie nodes that are no longer
+//! reachable from the retained state roots.
+
+use std::collections::HashMap;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use pchain_types::cryptography::Sha256Hash;
+
+use crate::{
+    db::{DBWrite, WriteBatch},
+    error::{DbError, DecodeOrEncodeError, WorldStateError},
+    node_cache::NodeCache,
+    world_state::WorldStateChanges,
+};
+
+/// `PRUNING_KEYSPACE` is the first byte of the physical keys of the pruning journal, which is outside the
+/// V2 accounts keyspace (0) and V2 storage keyspace (1). V1 trie nodes are stored without keyspace byte, so the
+/// journal keys are further prefixed by [PRUNING_KEY_TAG] to keep them apart from V1 trie node keys.
+pub const PRUNING_KEYSPACE: u8 = 2;
+
+/// `PRUNING_KEY_TAG` follows [PRUNING_KEYSPACE] in the physical keys of the pruning journal
+const PRUNING_KEY_TAG: &[u8] = b"pchain-world-state/pruning/";
+
+// kinds of the journal keys, following PRUNING_KEY_TAG
+const META_KEY: u8 = 0;
+const ERA_KEY: u8 = 1;
+const REF_COUNT_KEY: u8 = 2;
+
+/// `Pruner` keeps the database from growing without bound by deleting trie nodes of old state roots.
+///
+/// Every [WorldStateChanges] committed by [Pruner::commit] is an era of the journal. The trie nodes inserted by an
+/// era are written immediately, while the trie nodes removed by an era are kept until the state root of the
+/// previous era is pruned, because the previous state root still refers to them. Every trie node inserted by a
+/// journaled era has a reference count, which is the number of journaled eras that inserted it. A removed trie node
+/// is deleted by [Pruner::prune] only if no later era inserted it again. This works on physical keys, so it is
+/// the same for V1 and V2 key layouts, including the changes from [WorldState::upgrade](crate::world_state::WorldState::upgrade).
+///
+/// The eras must be committed in order, each opening the state root of the previous one, and `commit` and `prune`
+/// must not be called concurrently. The state root opened by the first journaled era is not retained: the trie nodes
+/// it removes are deleted immediately.
+///
+/// If the database is read through a [NodeCache], it must be set by [Pruner::set_node_cache], so that the trie nodes
+/// deleted by `prune` are not read from the cache any more.
+#[derive(Debug, Clone)]
+pub struct Pruner<'a, S: DBWrite> {
+    db: &'a S,
+    node_cache: Option<NodeCache>,
+}
+
+/// `PruningStats` is the result of [Pruner::prune]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruningStats {
+    /// number of state roots removed from the journal
+    pub pruned_roots: usize,
+    /// number of trie nodes deleted from the database
+    pub deleted_nodes: usize,
+}
+
+/// `JournalMeta` is the range of eras in the journal, `first_era..next_era`
+#[derive(Debug, Clone, Copy, Default, BorshSerialize, BorshDeserialize)]
+struct JournalMeta {
+    first_era: u64,
+    next_era: u64,
+}
+
+/// `EraRecord` is the journal of one committed [WorldStateChanges]
+#[derive(Debug, Clone, Default, BorshSerialize, BorshDeserialize)]
+struct EraRecord {
+    /// state root committed by the era
+    root_hash: Sha256Hash,
+    /// trie nodes inserted by the era
+    inserts: Vec<Vec<u8>>,
+    /// trie nodes removed by the next era, which can be deleted once `root_hash` is pruned
+    removed_by_next: Vec<Vec<u8>>,
+}
+
+impl<'a, S: DBWrite> Pruner<'a, S> {
+    /// `new` is to create a Pruner on the database. The journal is stored in the same database.
+    pub fn new(db: &'a S) -> Self {
+        Pruner {
+            db,
+            node_cache: None,
+        }
+    }
+
+    /// `set_node_cache` sets the [NodeCache] invalidated by the writes of `commit` and `prune`
+    pub fn set_node_cache(&mut self, node_cache: NodeCache) {
+        self.node_cache = Some(node_cache);
+    }
+
+    /// `commit` writes the inserts of [WorldStateChanges] into the database in one atomic commit and journals
+    /// them as a new era. The deletes are journaled to be applied by [Pruner::prune].
+    ///
+    /// It replaces [MemoryDB::apply_changes](crate::db::MemoryDB::apply_changes) or
+    /// [WorldState::commit](crate::world_state::WorldState::commit) for callers using the Pruner.
+    pub fn commit(&self, changes: WorldStateChanges) -> Result<(), WorldStateError> {
+        let mut meta = self.meta()?;
+        let mut batch = WriteBatch::new();
+
+        // the removed trie nodes belong to the state root of the previous era
+        let removed: Vec<Vec<u8>> = changes.deletes.into_iter().collect();
+        if meta.next_era > meta.first_era {
+            let previous_era = meta.next_era - 1;
+            let mut previous = self.era_record(previous_era)?;
+            previous.removed_by_next.extend(removed);
+            batch.put(era_key(previous_era), encode(&previous)?);
+        } else {
+            for key in removed {
+                batch.delete(key);
+            }
+        }
+
+        let mut inserts: Vec<Vec<u8>> = changes.inserts.keys().cloned().collect();
+        inserts.sort();
+        let ref_counts = self.ref_counts(&inserts)?;
+        for (key, ref_count) in inserts.iter().zip(ref_counts) {
+            batch.put(ref_count_key(key), (ref_count + 1).to_be_bytes().to_vec());
+        }
+        let record = EraRecord {
+            root_hash: changes.new_root_hash,
+            inserts,
+            removed_by_next: Vec::new(),
+        };
+        batch.put(era_key(meta.next_era), encode(&record)?);
+        meta.next_era += 1;
+        batch.put(meta_key(), encode(&meta)?);
+
+        for (key, value) in changes.inserts {
+            batch.put(key, value);
+        }
+        self.write(batch)?;
+        Ok(())
+    }
+
+    /// `prune` removes all but the last `keep_last_n_roots` state roots from the journal, and deletes the trie nodes
+    /// that are not reachable from the retained state roots in one atomic commit. The latest state root is always kept.
+    pub fn prune(&self, keep_last_n_roots: usize) -> Result<PruningStats, WorldStateError> {
+        let mut meta = self.meta()?;
+        let keep_last_n_roots = keep_last_n_roots.max(1) as u64;
+        if meta.next_era - meta.first_era <= keep_last_n_roots {
+            return Ok(PruningStats::default());
+        }
+        let prune_until = meta.next_era - keep_last_n_roots;
+        let records = (meta.first_era..prune_until)
+            .map(|era| self.era_record(era))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut keys: Vec<Vec<u8>> = records
+            .iter()
+            .flat_map(|record| record.inserts.iter().chain(record.removed_by_next.iter()))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        let ref_counts = self.ref_counts(&keys)?;
+        let mut ref_counts: HashMap<Vec<u8>, u32> = keys.into_iter().zip(ref_counts).collect();
+
+        let mut batch = WriteBatch::new();
+        let mut stats = PruningStats::default();
+        // eras are pruned in order, so that a removed trie node is checked against the eras after its removal
+        for (era, record) in (meta.first_era..prune_until).zip(records) {
+            for key in record.inserts {
+                let ref_count = ref_counts.get_mut(&key).unwrap();
+                *ref_count = ref_count.saturating_sub(1);
+            }
+            for key in record.removed_by_next {
+                if ref_counts[&key] == 0 {
+                    batch.delete(key);
+                    stats.deleted_nodes += 1;
+                }
+            }
+            batch.delete(era_key(era));
+            stats.pruned_roots += 1;
+        }
+
+        for (key, ref_count) in ref_counts {
+            if ref_count == 0 {
+                batch.delete(ref_count_key(&key));
+            } else {
+                batch.put(ref_count_key(&key), ref_count.to_be_bytes().to_vec());
+            }
+        }
+        meta.first_era = prune_until;
+        batch.put(meta_key(), encode(&meta)?);
+        self.write(batch)?;
+        Ok(stats)
+    }
+
+    /// `roots` returns the retained state roots in the journal, from the oldest to the latest
+    pub fn roots(&self) -> Result<Vec<Sha256Hash>, WorldStateError> {
+        let meta = self.meta()?;
+        (meta.first_era..meta.next_era)
+            .map(|era| Ok(self.era_record(era)?.root_hash))
+            .collect()
+    }
+
+    /// `write` writes the batch into the database, through the node cache if it is set
+    fn write(&self, batch: WriteBatch) -> Result<(), DbError> {
+        match &self.node_cache {
+            Some(node_cache) => node_cache.write(self.db, batch),
+            None => self.db.write(batch),
+        }
+    }
+
+    fn meta(&self) -> Result<JournalMeta, WorldStateError> {
+        match self.db.try_get(&meta_key())? {
+            Some(bytes) => decode(&bytes),
+            None => Ok(JournalMeta::default()),
+        }
+    }
+
+    fn era_record(&self, era: u64) -> Result<EraRecord, WorldStateError> {
+        match self.db.try_get(&era_key(era))? {
+            Some(bytes) => decode(&bytes),
+            None => Err(DecodeOrEncodeError::DecodeError.into()),
+        }
+    }
+
+    /// `ref_counts` reads the reference counts of the trie nodes in one batch. Trie nodes not inserted by any journaled era have 0.
+    fn ref_counts(&self, keys: &[Vec<u8>]) -> Result<Vec<u32>, WorldStateError> {
+        let ref_count_keys: Vec<Vec<u8>> = keys.iter().map(|key| ref_count_key(key)).collect();
+        let ref_count_keys: Vec<&[u8]> = ref_count_keys.iter().map(Vec::as_slice).collect();
+        self.db
+            .multi_get(&ref_count_keys)?
+            .into_iter()
+            .map(|value| match value {
+                Some(bytes) => bytes
+                    .try_into()
+                    .map(u32::from_be_bytes)
+                    .map_err(|_| DecodeOrEncodeError::DecodeError.into()),
+                None => Ok(0),
+            })
+            .collect()
+    }
+}
+
+fn journal_key(kind: u8, suffix: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(2 + PRUNING_KEY_TAG.len() + suffix.len());
+    key.push(PRUNING_KEYSPACE);
+    key.extend_from_slice(PRUNING_KEY_TAG);
+    key.push(kind);
+    key.extend_from_slice(suffix);
+    key
+}
+
+fn meta_key() -> Vec<u8> {
+    journal_key(META_KEY, &[])
+}
+
+fn era_key(era: u64) -> Vec<u8> {
+    journal_key(ERA_KEY, &era.to_be_bytes())
+}
+
+fn ref_count_key(node_key: &[u8]) -> Vec<u8> {
+    journal_key(REF_COUNT_KEY, node_key)
+}
+
+fn encode<T: BorshSerialize>(value: &T) -> Result<Vec<u8>, WorldStateError> {
+    value
+        .try_to_vec()
+        .map_err(|_| DecodeOrEncodeError::EncodeError.into())
+}
+
+fn decode<T: BorshDeserialize>(bytes: &[u8]) -> Result<T, WorldStateError> {
+    T::try_from_slice(bytes).map_err(|_| DecodeOrEncodeError::DecodeError.into())
+}
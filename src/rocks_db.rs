@@ -0,0 +1,182 @@
+/*
+    Copyright © 2023, ParallelChain Lab
+    Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
+*/
+
+//! This mod provides [RocksDB], an implementation of [DB] and [DBWrite] on RocksDB. Enabled by feature `rocksdb`.
+
+use std::{fmt, path::Path, sync::Arc};
+
+use rocksdb::{
+    BoundColumnFamily, ColumnFamilyDescriptor, DBWithThreadMode, MultiThreaded, Options,
+    ReadOptions, DEFAULT_COLUMN_FAMILY_NAME,
+};
+
+use crate::{
+    db::{DBWrite, WriteBatch, DB},
+    error::DbError,
+    world_state::WorldStateChanges,
+};
+
+/// `ACCOUNTS_COLUMN_FAMILY` stores the physical keys starting with byte 0, which include the V2 accounts keyspace
+pub const ACCOUNTS_COLUMN_FAMILY: &str = "accounts";
+
+/// `STORAGE_COLUMN_FAMILY` stores the physical keys starting with byte 1, which include the V2 storage keyspace
+pub const STORAGE_COLUMN_FAMILY: &str = "storage";
+
+/// Block cache size in MB used for point lookups of trie nodes
+const POINT_LOOKUP_BLOCK_CACHE_SIZE_MB: u64 = 64;
+
+type RocksDBInner = DBWithThreadMode<MultiThreaded>;
+
+/// `RocksDB` is a persistent storage for WorldState backed by RocksDB.
+///
+/// Cloning a RocksDB returns a handle to the same opened database.
+///
+/// If the database is opened with column families, a column family is chosen by the first byte of the physical key alone,
+/// as keys are kept intact and do not carry the version: keys starting with 0 are stored in [ACCOUNTS_COLUMN_FAMILY],
+/// keys starting with 1 in [STORAGE_COLUMN_FAMILY], and all other keys in the default column family. The V2 accounts
+/// keyspace and V2 storage keyspace are therefore separated, but V1 keys are not: the V1 keys starting with 0 or 1, e.g.
+/// some AccountTrie nodes and the StorageTrie nodes of the addresses starting with 0 or 1, are stored there as well.
+#[derive(Clone)]
+pub struct RocksDB {
+    db: Arc<RocksDBInner>,
+    column_families: bool,
+}
+
+impl RocksDB {
+    /// `open` opens (or creates) the database at `path` with all keys stored in the default column family
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, DbError> {
+        let db = RocksDBInner::open(&Self::options(), path)
+            .map_err(|err| DbError::OpenError(err.into_string()))?;
+        Ok(Self {
+            db: Arc::new(db),
+            column_families: false,
+        })
+    }
+
+    /// `open_with_column_families` opens (or creates) the database at `path` with the V2 accounts keyspace and
+    /// V2 storage keyspace stored in separated column families, chosen by the first byte of the key as described in [RocksDB]
+    ///
+    /// A database created by `open_with_column_families` must always be opened by `open_with_column_families`
+    pub fn open_with_column_families<P: AsRef<Path>>(path: P) -> Result<Self, DbError> {
+        let column_families = [ACCOUNTS_COLUMN_FAMILY, STORAGE_COLUMN_FAMILY]
+            .into_iter()
+            .map(|name| ColumnFamilyDescriptor::new(name, Self::options()));
+        let db = RocksDBInner::open_cf_descriptors(&Self::options(), path, column_families)
+            .map_err(|err| DbError::OpenError(err.into_string()))?;
+        Ok(Self {
+            db: Arc::new(db),
+            column_families: true,
+        })
+    }
+
+    /// `apply_changes` writes the deletes and the inserts of [WorldStateChanges] in one atomic write batch.
+    /// Use [NodeCache::apply_changes](crate::node_cache::NodeCache::apply_changes) instead if the database is read
+    /// through a NodeCache, so that the changes are also invalidated in the cache.
+    pub fn apply_changes(&self, changes: WorldStateChanges) -> Result<(), DbError> {
+        self.write(changes.into())
+    }
+
+    /// `inner` returns the underlying RocksDB instance, e.g. to query its properties
+    pub fn inner(&self) -> &DBWithThreadMode<MultiThreaded> {
+        &self.db
+    }
+
+    /// `options` is the options for opening the database and its column families.
+    /// Trie nodes are read by point lookups on their hash, so the block cache and bloom filters are set up for it.
+    fn options() -> Options {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        options.optimize_for_point_lookup(POINT_LOOKUP_BLOCK_CACHE_SIZE_MB);
+        if let Ok(parallelism) = std::thread::available_parallelism() {
+            options.increase_parallelism(parallelism.get() as i32);
+        }
+        options
+    }
+
+    /// `read_options` is the options for looking up a trie node. The nodes are kept in block cache as the upper
+    /// nodes of the trie are read by every lookup, and readahead is disabled as the lookups are random.
+    fn read_options() -> ReadOptions {
+        let mut read_options = ReadOptions::default();
+        read_options.fill_cache(true);
+        read_options.set_readahead_size(0);
+        read_options
+    }
+
+    /// `column_family` returns the column family handle of the key, or None if all keys are in the default column family
+    fn column_family(&self, key: &[u8]) -> Option<Arc<BoundColumnFamily<'_>>> {
+        if !self.column_families {
+            return None;
+        }
+        let name = match key.first() {
+            Some(0) => ACCOUNTS_COLUMN_FAMILY,
+            Some(1) => STORAGE_COLUMN_FAMILY,
+            _ => DEFAULT_COLUMN_FAMILY_NAME,
+        };
+        self.db.cf_handle(name)
+    }
+}
+
+impl DB for RocksDB {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.try_get(key).ok().flatten()
+    }
+
+    fn try_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
+        let ret = match self.column_family(key) {
+            Some(cf) => self.db.get_cf_opt(&cf, key, &Self::read_options()),
+            None => self.db.get_opt(key, &Self::read_options()),
+        };
+        ret.map_err(|err| DbError::ReadError(err.into_string()))
+    }
+
+    fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, DbError> {
+        let ret = if self.column_families {
+            let column_families: Vec<_> = keys
+                .iter()
+                .map(|key| self.column_family(key).unwrap())
+                .collect();
+            self.db.multi_get_cf_opt(
+                column_families.iter().zip(keys.iter()),
+                &Self::read_options(),
+            )
+        } else {
+            self.db.multi_get_opt(keys.iter(), &Self::read_options())
+        };
+        ret.into_iter()
+            .map(|value| value.map_err(|err| DbError::ReadError(err.into_string())))
+            .collect()
+    }
+}
+
+impl DBWrite for RocksDB {
+    fn write(&self, batch: WriteBatch) -> Result<(), DbError> {
+        let mut write_batch = rocksdb::WriteBatch::default();
+        for key in batch.deletes {
+            match self.column_family(&key) {
+                Some(cf) => write_batch.delete_cf(&cf, key),
+                None => write_batch.delete(key),
+            }
+        }
+        for (key, value) in batch.inserts {
+            match self.column_family(&key) {
+                Some(cf) => write_batch.put_cf(&cf, key, value),
+                None => write_batch.put(key, value),
+            }
+        }
+        self.db
+            .write(write_batch)
+            .map_err(|err| DbError::WriteError(err.into_string()))
+    }
+}
+
+impl fmt::Debug for RocksDB {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RocksDB")
+            .field("path", &self.db.path())
+            .field("column_families", &self.column_families)
+            .finish()
+    }
+}
@@ -0,0 +1,274 @@
+/*
+    Copyright © 2023, ParallelChain Lab
+    Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
+*/
+
+//! This mod provides [StateRootRegistry], an index of the state roots committed at every block height,
+//! which is stored in its own keyspace of the same [DB] as the WorldState.
+
+use std::{
+    collections::VecDeque,
+    ops::{Bound, RangeBounds},
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use pchain_types::cryptography::Sha256Hash;
+
+use crate::{
+    db::{DBWrite, WriteBatch, DB},
+    error::{DecodeOrEncodeError, StateRootRegistryError, WorldStateError},
+    version::{Version, VersionProvider},
+    world_state::WorldState,
+};
+
+/// `STATE_ROOT_REGISTRY_KEYSPACE` is the first byte of the physical keys of the registry, which is outside the
+/// V2 accounts keyspace (0), V2 storage keyspace (1) and [PRUNING_KEYSPACE](crate::pruning::PRUNING_KEYSPACE) (2).
+/// V1 trie nodes are stored without keyspace byte, so the registry keys are further prefixed by [REGISTRY_KEY_TAG]
+/// to keep them apart from V1 trie node keys.
+pub const STATE_ROOT_REGISTRY_KEYSPACE: u8 = 3;
+
+/// `REGISTRY_KEY_TAG` follows [STATE_ROOT_REGISTRY_KEYSPACE] in the physical keys of the registry
+const REGISTRY_KEY_TAG: &[u8] = b"pchain-world-state/state-roots/";
+
+// kinds of the registry keys, following REGISTRY_KEY_TAG
+const META_KEY: u8 = 0;
+const HEIGHT_KEY: u8 = 1;
+
+/// `StateRootRegistry` maps block heights to the state roots committed at them, so that historical states
+/// can be opened by block height, e.g. by archive nodes and explorers.
+///
+/// State roots are recorded by [WorldState::commit_at_height](crate::world_state::WorldState::commit_at_height)
+/// in the same atomic commit as the changes, or by [StateRootRegistry::record].
+/// Recording a block height again replaces its state root.
+///
+/// A recorded state root can be opened only if its trie nodes are still in the database. The deletes of
+/// [WorldStateChanges](crate::world_state::WorldStateChanges) remove the trie nodes of the previous state root,
+/// so `commit_at_height` writes only the inserts. A caller writing the changes by itself and recording the state root
+/// by [StateRootRegistry::record] should do the same.
+#[derive(Debug, Clone)]
+pub struct StateRootRegistry<'a, S: DB> {
+    db: &'a S,
+}
+
+/// `StateRootEntry` is a state root recorded in [StateRootRegistry]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct StateRootEntry {
+    /// block height
+    pub height: u64,
+    /// state root committed at the block height
+    pub root_hash: Sha256Hash,
+    /// version of the WorldState committed the state root
+    pub version: Version,
+}
+
+/// `RegistryMeta` is the lowest and highest recorded block heights
+#[derive(Debug, Clone, Copy, BorshSerialize, BorshDeserialize)]
+struct RegistryMeta {
+    lowest_height: u64,
+    highest_height: u64,
+}
+
+impl<'a, S: DB> StateRootRegistry<'a, S> {
+    /// `new` is to create a StateRootRegistry on the database
+    pub fn new(db: &'a S) -> Self {
+        StateRootRegistry { db }
+    }
+
+    /// `get` returns the state root recorded at the block height, None if it is not recorded
+    pub fn get(&self, height: u64) -> Result<Option<StateRootEntry>, WorldStateError> {
+        match self.db.try_get(&height_key(height))? {
+            Some(bytes) => Ok(Some(decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// `latest` returns the state root recorded at the highest block height, None if nothing is recorded
+    pub fn latest(&self) -> Result<Option<StateRootEntry>, WorldStateError> {
+        match self.meta()? {
+            Some(meta) => self.get(meta.highest_height),
+            None => Ok(None),
+        }
+    }
+
+    /// `range` returns a lazy iterator of the state roots recorded at the block heights in the range, ordered by block height.
+    /// Block heights without recorded state root are skipped.
+    ///
+    /// The block heights are read in chunks of [RANGE_CHUNK_SIZE] on demand, so the memory used does not grow with
+    /// the range. The reads still cover every block height walked through, including the ones without state root.
+    pub fn range<R: RangeBounds<u64>>(
+        &self,
+        heights: R,
+    ) -> Result<StateRootRange<'a, S>, WorldStateError> {
+        let mut range = StateRootRange {
+            db: self.db,
+            next_height: 0,
+            end_height: 0,
+            entries: VecDeque::new(),
+            done: true,
+        };
+        let meta = match self.meta()? {
+            Some(meta) => meta,
+            None => return Ok(range),
+        };
+        let start = match heights.start_bound() {
+            Bound::Included(height) => *height,
+            Bound::Excluded(height) => height.saturating_add(1),
+            Bound::Unbounded => 0,
+        }
+        .max(meta.lowest_height);
+        let end = match heights.end_bound() {
+            Bound::Included(height) => Some(*height),
+            Bound::Excluded(height) => height.checked_sub(1),
+            Bound::Unbounded => Some(u64::MAX),
+        };
+        if let Some(end) = end {
+            let end = end.min(meta.highest_height);
+            if start <= end {
+                range.next_height = start;
+                range.end_height = end;
+                range.done = false;
+            }
+        }
+        Ok(range)
+    }
+
+    fn meta(&self) -> Result<Option<RegistryMeta>, WorldStateError> {
+        match self.db.try_get(&meta_key())? {
+            Some(bytes) => Ok(Some(decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// `record_batch` returns the write batch to record the state root
+    pub(crate) fn record_batch(
+        &self,
+        entry: &StateRootEntry,
+    ) -> Result<WriteBatch, WorldStateError> {
+        let meta = match self.meta()? {
+            Some(meta) => RegistryMeta {
+                lowest_height: meta.lowest_height.min(entry.height),
+                highest_height: meta.highest_height.max(entry.height),
+            },
+            None => RegistryMeta {
+                lowest_height: entry.height,
+                highest_height: entry.height,
+            },
+        };
+        let mut batch = WriteBatch::new();
+        batch.put(height_key(entry.height), encode(entry)?);
+        batch.put(meta_key(), encode(&meta)?);
+        Ok(batch)
+    }
+}
+
+/// `RANGE_CHUNK_SIZE` is the number of block heights read at once by [StateRootRange]
+pub const RANGE_CHUNK_SIZE: u64 = 256;
+
+/// `StateRootRange` is a lazy iterator of the state roots recorded in [StateRootRegistry] ordered by block height,
+/// created by [StateRootRegistry::range]. The iteration stops after the first error.
+#[derive(Debug)]
+pub struct StateRootRange<'a, S: DB> {
+    db: &'a S,
+    // next block height to read, and the last block height of the range
+    next_height: u64,
+    end_height: u64,
+    // state roots read but not returned yet
+    entries: VecDeque<Result<StateRootEntry, WorldStateError>>,
+    // true if all block heights in the range are read
+    done: bool,
+}
+
+impl<'a, S: DB> StateRootRange<'a, S> {
+    /// `read_chunk` reads the next chunk of block heights
+    fn read_chunk(&mut self) {
+        let chunk_end = self
+            .next_height
+            .saturating_add(RANGE_CHUNK_SIZE - 1)
+            .min(self.end_height);
+        let keys: Vec<Vec<u8>> = (self.next_height..=chunk_end).map(height_key).collect();
+        let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+        match self.db.multi_get(&keys) {
+            Ok(values) => self
+                .entries
+                .extend(values.into_iter().flatten().map(|bytes| decode(&bytes))),
+            Err(error) => self.entries.push_back(Err(error.into())),
+        }
+        match chunk_end == self.end_height {
+            true => self.done = true,
+            false => self.next_height = chunk_end + 1,
+        }
+    }
+}
+
+impl<'a, S: DB> Iterator for StateRootRange<'a, S> {
+    type Item = Result<StateRootEntry, WorldStateError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.entries.pop_front() {
+                Some(Ok(entry)) => return Some(Ok(entry)),
+                Some(Err(error)) => {
+                    self.entries.clear();
+                    self.done = true;
+                    return Some(Err(error));
+                }
+                None if self.done => return None,
+                None => self.read_chunk(),
+            }
+        }
+    }
+}
+
+impl<'a, S: DB + Send + Sync + Clone> StateRootRegistry<'a, S> {
+    /// `open_at_height` opens the WorldState by the state root recorded at the block height
+    ///
+    /// Error when no state root is recorded at the block height, or it is recorded by WorldState of another version
+    pub fn open_at_height<V: VersionProvider + Send + Sync + Clone>(
+        &self,
+        height: u64,
+    ) -> Result<WorldState<'a, S, V>, WorldStateError> {
+        let entry = self
+            .get(height)?
+            .ok_or(StateRootRegistryError::UnknownHeight(height))?;
+        if entry.version != V::version() {
+            return Err(StateRootRegistryError::VersionMismatch.into());
+        }
+        Ok(WorldState::open(self.db, entry.root_hash))
+    }
+}
+
+impl<'a, S: DBWrite> StateRootRegistry<'a, S> {
+    /// `record` writes the state root into the registry. Use it if the changes of the state root are written
+    /// into the database by the caller, e.g. by [MemoryDB::apply_changes](crate::db::MemoryDB::apply_changes).
+    pub fn record(&self, entry: StateRootEntry) -> Result<(), WorldStateError> {
+        self.db.write(self.record_batch(&entry)?)?;
+        Ok(())
+    }
+}
+
+fn registry_key(kind: u8, suffix: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(2 + REGISTRY_KEY_TAG.len() + suffix.len());
+    key.push(STATE_ROOT_REGISTRY_KEYSPACE);
+    key.extend_from_slice(REGISTRY_KEY_TAG);
+    key.push(kind);
+    key.extend_from_slice(suffix);
+    key
+}
+
+fn meta_key() -> Vec<u8> {
+    registry_key(META_KEY, &[])
+}
+
+fn height_key(height: u64) -> Vec<u8> {
+    registry_key(HEIGHT_KEY, &height.to_be_bytes())
+}
+
+fn encode<T: BorshSerialize>(value: &T) -> Result<Vec<u8>, WorldStateError> {
+    value
+        .try_to_vec()
+        .map_err(|_| DecodeOrEncodeError::EncodeError.into())
+}
+
+fn decode<T: BorshDeserialize>(bytes: &[u8]) -> Result<T, WorldStateError> {
+    T::try_from_slice(bytes).map_err(|_| DecodeOrEncodeError::DecodeError.into())
+}
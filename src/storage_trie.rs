@@ -9,7 +9,13 @@ use std::collections::{HashMap, HashSet};
 use std::mem::size_of;
 
 use crate::error::{MptError, WorldStateError};
-use crate::mpt::{proof_level, KeyVisibility, Mpt, Proof, WSProofNode};
+use crate::mpt::{
+    proof_level, Mpt, MptIterator, Proof, ProofAndItems, ProofAndValues, TrieCursor, ValueChange,
+    WSProofNode,
+};
+use crate::node_cache::NodeCache;
+use crate::trie_layout::RefHasher;
+use crate::witness::WitnessRecorder;
 use crate::world_state::WorldStateChanges;
 use crate::TrieKeyBuildError;
 use crate::{
@@ -18,7 +24,8 @@ use crate::{
 };
 use hash_db::Hasher;
 use pchain_types::cryptography::{PublicAddress, Sha256Hash};
-use reference_trie::RefHasher;
+
+pub(crate) use crate::keys::storage_key;
 
 const NULL_NODE_KEY: &[u8] = &[0_u8];
 /// Struct store account storage information for contract account
@@ -45,9 +52,20 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         self.trie.get(&trie_key)
     }
 
+    /// `batch_get` return storage values by storage keys in the same order as the keys.
+    /// The values are read from the trie in one batch.
+    ///
+    /// None for key not found in storage trie
+    ///
+    /// Error if storage_hash does not exists or missed some trie nodes
+    pub fn batch_get(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, MptError> {
+        let trie_keys: Vec<Vec<u8>> = keys.iter().map(|key| storage_key::<V>(key)).collect();
+        self.trie.batch_get(&trie_keys)
+    }
+
     /// `get_with_proof` return storage value with proof by specific storage key
     ///
-    /// (empty vector, empty vector) if key is not found in storage trie
+    /// (proof of absence, None) if key is not found in storage trie
     ///
     /// Error if storage_hash does not exists or missed some trie nodes
     pub fn get_with_proof(&self, key: &Vec<u8>) -> Result<(Proof, Option<Vec<u8>>), MptError> {
@@ -61,6 +79,77 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         })
     }
 
+    /// `absence_proof` return the proof that the key is not found in storage trie,
+    /// which can be verified by [verify_storage_absence](crate::proof::verify_storage_absence).
+    ///
+    /// None if the key is found in storage trie
+    ///
+    /// Error if storage_hash does not exists or missed some trie nodes
+    pub fn absence_proof(&self, key: &Vec<u8>) -> Result<Option<Proof>, MptError> {
+        self.get_with_proof(key).map(|(proof, value)| match value {
+            Some(_) => None,
+            None => Some(proof),
+        })
+    }
+
+    /// `batch_get_with_proof` return storage values by storage keys in the same order as the keys, with one proof
+    /// for all the keys, which can be verified by [verify_storage_batch_proof](crate::proof::verify_storage_batch_proof).
+    ///
+    /// None for key not found in storage trie
+    ///
+    /// Error if storage_hash does not exists or missed some trie nodes
+    pub fn batch_get_with_proof(&self, keys: &[Vec<u8>]) -> Result<ProofAndValues, MptError> {
+        let trie_keys: Vec<Vec<u8>> = keys.iter().map(|key| storage_key::<V>(key)).collect();
+        self.trie
+            .batch_get_with_proof(&trie_keys)
+            .map(|(proof, values)| {
+                let proof = proof
+                    .into_iter()
+                    .map(|node| WSProofNode::new(proof_level::STORAGE, node).into())
+                    .collect();
+                (proof, values)
+            })
+    }
+
+    /// `range_with_proof` return all the storage key-value pairs with keys in `start..end` ordered by key, with the proof
+    /// that none of them is omitted, which can be verified by [verify_storage_range_proof](crate::proof::verify_storage_range_proof).
+    /// The range is not bounded above if `end` is None.
+    ///
+    /// Error if storage_hash does not exists or missed some trie nodes
+    pub fn range_with_proof(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+    ) -> Result<ProofAndItems, WorldStateError> {
+        let start = storage_key::<V>(&start.to_vec());
+        let end = end.map(|end| storage_key::<V>(&end.to_vec()));
+        let (proof, items) = self.trie.range_with_proof(&start, end.as_deref())?;
+        let proof = proof
+            .into_iter()
+            .map(|node| WSProofNode::new(proof_level::STORAGE, node).into())
+            .collect();
+        let items = items
+            .into_iter()
+            .map(|(key, value)| Ok((drop_visibility_type::<V>(&key)?, value)))
+            .collect::<Result<_, WorldStateError>>()?;
+        Ok((proof, items))
+    }
+
+    /// `diff` return the changes of all the storage key-value pairs from the storage hash of this StorageTrie to
+    /// `other_storage_hash` of the same account, ordered by key. Subtrees with the same hash in both tries are skipped.
+    ///
+    /// Error if either storage hash does not exist or missed some trie nodes
+    pub fn diff(
+        &self,
+        other_storage_hash: Sha256Hash,
+    ) -> Result<Vec<(Vec<u8>, ValueChange)>, WorldStateError> {
+        self.trie
+            .diff(other_storage_hash)?
+            .into_iter()
+            .map(|(key, change)| Ok((drop_visibility_type::<V>(&key)?, change)))
+            .collect()
+    }
+
     /// `contains` is to check if the key exists in current StorageTrie or not
     ///
     /// Error if storage_hash does not exists or missed some trie nodes
@@ -69,6 +158,13 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         self.trie.contains(&storage_key)
     }
 
+    /// `iter` returns a lazy iterator of the <Key, Value> pairs in StorageTrie ordered by key
+    pub fn iter(&self) -> StorageTrieIterator<'_, 'a, S, V> {
+        StorageTrieIterator {
+            inner: self.trie.iter(),
+        }
+    }
+
     /// `set` is to set/update <Key, Value> pair in StorageTrie
     pub fn set(&mut self, key: &Vec<u8>, value: Vec<u8>) -> Result<(), MptError> {
         let storage_key: Vec<u8> = storage_key::<V>(key);
@@ -103,6 +199,63 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     }
 }
 
+/// `StorageTrieIterator` is a lazy iterator of the <Key, Value> pairs in [StorageTrie] ordered by key,
+/// created by [StorageTrie::iter]. The iteration stops after the first error.
+pub struct StorageTrieIterator<'t, 'a, S, V>
+where
+    S: DB + Send + Sync + Clone,
+    V: VersionProvider + Send + Sync + Clone,
+{
+    inner: MptIterator<'t, 'a, S, V>,
+}
+
+impl<'t, 'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
+    StorageTrieIterator<'t, 'a, S, V>
+{
+    /// `seek` starts the iteration from the first key which is equal to or greater than `start_key`
+    pub fn seek(self, start_key: &[u8]) -> Self {
+        StorageTrieIterator {
+            inner: self.inner.seek(&storage_key::<V>(&start_key.to_vec())),
+        }
+    }
+
+    /// `prefix` limits the iteration to the keys starting with `prefix`
+    pub fn prefix(self, prefix: &[u8]) -> Self {
+        StorageTrieIterator {
+            inner: self.inner.prefix(&storage_key::<V>(&prefix.to_vec())),
+        }
+    }
+
+    /// `resume` starts the iteration after the position of the cursor returned by [StorageTrieIterator::cursor]
+    pub fn resume(self, cursor: &TrieCursor) -> Self {
+        StorageTrieIterator {
+            inner: self.inner.resume(cursor),
+        }
+    }
+
+    /// `cursor` returns the position after the last returned item, None if no item is returned yet
+    pub fn cursor(&self) -> Option<TrieCursor> {
+        self.inner.cursor()
+    }
+}
+
+impl<'t, 'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone> Iterator
+    for StorageTrieIterator<'t, 'a, S, V>
+{
+    type Item = Result<(Vec<u8>, Vec<u8>), WorldStateError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next()?;
+        Some(
+            item.map_err(WorldStateError::from)
+                .and_then(|(key, value)| {
+                    let key = drop_visibility_type::<V>(&key)?;
+                    Ok((key, value))
+                }),
+        )
+    }
+}
+
 /// intefaces called by [WorldState](crate::world_state::WorldState)
 impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
     StorageTrie<'a, S, V>
@@ -126,14 +279,49 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         self.trie.root_hash()
     }
 
+    /// `set_node_cache` called by [WorldState](crate::world_state::WorldState) to set the [NodeCache] of the trie
+    pub(crate) fn set_node_cache(&mut self, node_cache: Option<NodeCache>) {
+        self.trie.set_node_cache(node_cache);
+    }
+
+    /// `set_witness_recorder` called by [WorldState](crate::world_state::WorldState) to set the [WitnessRecorder] of the trie
+    pub(crate) fn set_witness_recorder(&mut self, witness_recorder: Option<WitnessRecorder>) {
+        self.trie.set_witness_recorder(witness_recorder);
+    }
+
+    /// `set_value_recording` called by [WorldState](crate::world_state::WorldState) to enable or disable recording the changes of the values
+    pub(crate) fn set_value_recording(&mut self, enabled: bool) {
+        self.trie.set_value_recording(enabled);
+    }
+
+    /// `changed_values` called by [WorldState](crate::world_state::WorldState) to return the changes of the values of the keys
+    /// written since the recording is enabled, ordered by key
+    pub(crate) fn changed_values(&self) -> Result<Vec<(Vec<u8>, ValueChange)>, WorldStateError> {
+        self.trie
+            .changed_values()?
+            .into_iter()
+            .map(|(key, change)| Ok((drop_visibility_type::<V>(&key)?, change)))
+            .collect()
+    }
+
+    /// `checkpoint` called by [WorldState](crate::world_state::WorldState) to add a checkpoint at the current state of the trie
+    pub(crate) fn checkpoint(&mut self) {
+        self.trie.checkpoint();
+    }
+
+    /// `revert_to_checkpoint` called by [WorldState](crate::world_state::WorldState) to revert the trie to the checkpoint of the index
+    pub(crate) fn revert_to_checkpoint(&mut self, index: usize) {
+        self.trie.revert_to_checkpoint(index);
+    }
+
+    /// `discard_checkpoint` called by [WorldState](crate::world_state::WorldState) to remove the checkpoint of the index and the later ones
+    pub(crate) fn discard_checkpoint(&mut self, index: usize) {
+        self.trie.discard_checkpoint(index);
+    }
+
     /// `close` called by [WorldState](crate::world_state::WorldState) return all cached updates in current StorageTrie and updated storage_hash
     pub(crate) fn close(&mut self) -> WorldStateChanges {
-        let mpt_changes = self.trie.close();
-        WorldStateChanges {
-            inserts: mpt_changes.0,
-            deletes: mpt_changes.1,
-            new_root_hash: mpt_changes.2,
-        }
+        self.trie.close().into()
     }
 }
 
@@ -164,27 +352,6 @@ impl<'a, S: DB + Send + Sync + Clone> StorageTrie<'a, S, V1> {
     }
 }
 
-/// `storage_key` is to crate the key for [StorageTrie](crate::storage::StorageTrie)
-///
-/// V1 StorageTrie Key is in form KeyVisibility + Vec<u8>
-///
-/// V2 StorageTrie Key is in form Vec<u8>
-pub(crate) fn storage_key<V: VersionProvider>(key: &Vec<u8>) -> Vec<u8> {
-    match <V>::version() {
-        Version::V1 => {
-            let mut storage_key: Vec<u8> = Vec::with_capacity(size_of::<u8>() + key.len());
-            storage_key.push(KeyVisibility::Public as u8);
-            storage_key.extend_from_slice(key);
-            storage_key
-        }
-        Version::V2 => {
-            let mut storage_key: Vec<u8> = Vec::new();
-            storage_key.extend_from_slice(key);
-            storage_key
-        }
-    }
-}
-
 /// `drop_visibility_type` is to drop the visibility byte from [AccountsTrie](crate::accounts_trie::AccountsTrie) Key or [StorageTrie](crate::storage_trie::StorageTrie) Key
 pub(crate) fn drop_visibility_type<V: VersionProvider>(
     key: &[u8],
@@ -0,0 +1,470 @@
+/*
+    Copyright © 2023, ParallelChain Lab
+    Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
+*/
+
+//! This mod provides [RefHasher] and [NoExtensionLayout], the hasher and the trie layout of the world state.
+//! They encode and hash the trie nodes in exactly the same way as `RefHasher` and `NoExtensionLayout` of
+//! `reference-trie` 0.29.0 (Apache-2.0, Parity Technologies), which the trie nodes in the database are built with.
+//!
+//! They are defined here over `trie-db` and `hash-db` without their default features because `reference-trie`
+//! depends on `std`, so that the [verify](crate::verify) module can be built with `no_std`.
+
+use alloc::vec::Vec;
+use core::{borrow::Borrow, marker::PhantomData, ops::Range};
+
+use hash256_std_hasher::Hash256StdHasher;
+use hash_db::Hasher;
+use parity_scale_codec::{Compact, Decode, Encode, Error as CodecError, Input, Output};
+use tiny_keccak::{Hasher as _, Keccak};
+use trie_db::{
+    nibble_ops,
+    node::{NibbleSlicePlan, NodeHandlePlan, NodePlan, Value, ValuePlan},
+    triedbmut::ChildReference,
+    NodeCodec, TrieLayout,
+};
+
+/// `RefHasher` is the Keccak-256 hasher of the trie nodes
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct RefHasher;
+
+impl Hasher for RefHasher {
+    type Out = [u8; 32];
+    type StdHasher = Hash256StdHasher;
+    const LENGTH: usize = 32;
+
+    fn hash(x: &[u8]) -> Self::Out {
+        let mut keccak = Keccak::v256();
+        keccak.update(x);
+        let mut out = [0u8; 32];
+        keccak.finalize(&mut out);
+        out
+    }
+}
+
+/// `NoExtensionLayout` is the trie layout without extension nodes, with values always inlined in the trie nodes
+#[derive(Default, Clone)]
+pub struct NoExtensionLayout;
+
+impl TrieLayout for NoExtensionLayout {
+    const USE_EXTENSION: bool = false;
+    const ALLOW_EMPTY: bool = false;
+    const MAX_INLINE_VALUE: Option<u32> = None;
+    type Hash = RefHasher;
+    type Codec = NodeCodecNoExt<RefHasher>;
+}
+
+/// `NodeCodecNoExt` encodes and decodes the trie nodes of [NoExtensionLayout]
+#[derive(Default, Clone)]
+pub struct NodeCodecNoExt<H>(PhantomData<H>);
+
+// number of bytes of the children bitmap of a branch node
+const BITMAP_LENGTH: usize = 2;
+
+// bounds and prefixes of the node header
+const NIBBLE_SIZE_BOUND_NO_EXT: usize = u16::MAX as usize;
+const LEAF_PREFIX_MASK_NO_EXT: u8 = 0b_01 << 6;
+const BRANCH_WITHOUT_MASK_NO_EXT: u8 = 0b_10 << 6;
+const BRANCH_WITH_MASK_NO_EXT: u8 = 0b_11 << 6;
+const EMPTY_TRIE_NO_EXT: u8 = 0;
+
+/// `Bitmap` is the children bitmap of a branch node
+struct Bitmap(u16);
+
+impl Bitmap {
+    fn decode(data: &[u8]) -> Result<Self, CodecError> {
+        Ok(Bitmap(u16::decode(&mut &data[..])?))
+    }
+
+    fn value_at(&self, i: usize) -> bool {
+        self.0 & (1u16 << i) != 0
+    }
+
+    fn encode<I: Iterator<Item = bool>>(has_children: I, output: &mut [u8]) {
+        let mut bitmap: u16 = 0;
+        let mut cursor: u16 = 1;
+        for has_child in has_children {
+            if has_child {
+                bitmap |= cursor
+            }
+            cursor <<= 1;
+        }
+        output[0] = (bitmap % 256) as u8;
+        output[1] = (bitmap / 256) as u8;
+    }
+}
+
+/// `NodeHeader` is the type of the node and the number of nibbles of its partial key
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum NodeHeader {
+    Null,
+    Branch(bool, usize),
+    Leaf(usize),
+}
+
+impl Encode for NodeHeader {
+    fn encode_to<T: Output + ?Sized>(&self, output: &mut T) {
+        match self {
+            NodeHeader::Null => output.push_byte(EMPTY_TRIE_NO_EXT),
+            NodeHeader::Branch(true, nibble_count) => {
+                encode_size_and_prefix(*nibble_count, BRANCH_WITH_MASK_NO_EXT, output)
+            }
+            NodeHeader::Branch(false, nibble_count) => {
+                encode_size_and_prefix(*nibble_count, BRANCH_WITHOUT_MASK_NO_EXT, output)
+            }
+            NodeHeader::Leaf(nibble_count) => {
+                encode_size_and_prefix(*nibble_count, LEAF_PREFIX_MASK_NO_EXT, output)
+            }
+        }
+    }
+}
+
+impl Decode for NodeHeader {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, CodecError> {
+        let i = input.read_byte()?;
+        if i == EMPTY_TRIE_NO_EXT {
+            return Ok(NodeHeader::Null);
+        }
+        match i & (0b11 << 6) {
+            LEAF_PREFIX_MASK_NO_EXT => Ok(NodeHeader::Leaf(decode_size(i, input)?)),
+            BRANCH_WITHOUT_MASK_NO_EXT => Ok(NodeHeader::Branch(false, decode_size(i, input)?)),
+            BRANCH_WITH_MASK_NO_EXT => Ok(NodeHeader::Branch(true, decode_size(i, input)?)),
+            // do not allow any special encoding
+            _ => Err("Unknown type of node".into()),
+        }
+    }
+}
+
+/// `encode_size_and_prefix` encodes the node type prefix and the number of nibbles
+fn encode_size_and_prefix(size: usize, prefix: u8, output: &mut (impl Output + ?Sized)) {
+    let size = size.min(NIBBLE_SIZE_BOUND_NO_EXT);
+    let l1 = size.min(62);
+    let (first_byte, mut rem) = match size == l1 {
+        true => (prefix + l1 as u8, 0),
+        false => (prefix + 63, size - l1),
+    };
+    output.push_byte(first_byte);
+    while rem > 0 {
+        if rem < 256 {
+            output.push_byte((rem - 1) as u8);
+            rem = 0;
+        } else {
+            output.push_byte(255);
+            rem = rem.saturating_sub(255);
+        }
+    }
+}
+
+/// `decode_size` decodes the number of nibbles encoded by [encode_size_and_prefix]
+fn decode_size<I: Input>(first: u8, input: &mut I) -> Result<usize, CodecError> {
+    let mut result = (first & (255u8 >> 2)) as usize;
+    if result < 63 {
+        return Ok(result);
+    }
+    result -= 1;
+    while result <= NIBBLE_SIZE_BOUND_NO_EXT {
+        let n = input.read_byte()? as usize;
+        if n < 255 {
+            return Ok(result + n + 1);
+        }
+        result += 255;
+    }
+    Err("Size limit reached for a nibble slice".into())
+}
+
+/// `partial_encode` encodes the node header followed by the partial key
+fn partial_encode<I: Iterator<Item = u8>>(
+    partial: I,
+    nibble_count: usize,
+    header: impl FnOnce(usize) -> NodeHeader,
+) -> Vec<u8> {
+    let nibble_count = nibble_count.min(NIBBLE_SIZE_BOUND_NO_EXT);
+    let mut output = Vec::with_capacity(3 + (nibble_count / nibble_ops::NIBBLE_PER_BYTE));
+    header(nibble_count).encode_to(&mut output);
+    output.extend(partial);
+    output
+}
+
+/// `ByteSliceInput` reads an encoded node while keeping the offset, so that the node plan refers to ranges of the node
+struct ByteSliceInput<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteSliceInput<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ByteSliceInput { data, offset: 0 }
+    }
+
+    fn take(&mut self, count: usize) -> Result<Range<usize>, CodecError> {
+        if self.offset + count > self.data.len() {
+            return Err("out of data".into());
+        }
+        let range = self.offset..(self.offset + count);
+        self.offset += count;
+        Ok(range)
+    }
+}
+
+impl<'a> Input for ByteSliceInput<'a> {
+    fn remaining_len(&mut self) -> Result<Option<usize>, CodecError> {
+        Ok(self.data.len().checked_sub(self.offset))
+    }
+
+    fn read(&mut self, into: &mut [u8]) -> Result<(), CodecError> {
+        let range = self.take(into.len())?;
+        into.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> Result<u8, CodecError> {
+        if self.offset >= self.data.len() {
+            return Err("out of data".into());
+        }
+        let byte = self.data[self.offset];
+        self.offset += 1;
+        Ok(byte)
+    }
+}
+
+impl<H: Hasher> NodeCodec for NodeCodecNoExt<H> {
+    type Error = CodecError;
+    type HashOut = <H as Hasher>::Out;
+
+    fn hashed_null_node() -> <H as Hasher>::Out {
+        H::hash(<Self as NodeCodec>::empty_node())
+    }
+
+    fn decode_plan(data: &[u8]) -> Result<NodePlan, Self::Error> {
+        if data.is_empty() {
+            return Err(CodecError::from("Empty encoded node."));
+        }
+        let mut input = ByteSliceInput::new(data);
+
+        Ok(match NodeHeader::decode(&mut input)? {
+            NodeHeader::Null => NodePlan::Empty,
+            NodeHeader::Branch(has_value, nibble_count) => {
+                let padding = nibble_count % nibble_ops::NIBBLE_PER_BYTE != 0;
+                // check that the padding is valid (if any)
+                if padding && nibble_ops::pad_left(data[input.offset]) != 0 {
+                    return Err(CodecError::from("Bad format"));
+                }
+                let partial = input.take(nibble_count.div_ceil(nibble_ops::NIBBLE_PER_BYTE))?;
+                let partial_padding = nibble_ops::number_padding(nibble_count);
+                let bitmap_range = input.take(BITMAP_LENGTH)?;
+                let bitmap = Bitmap::decode(&data[bitmap_range])?;
+                let value = match has_value {
+                    true => {
+                        let count = <Compact<u32>>::decode(&mut input)?.0 as usize;
+                        Some(ValuePlan::Inline(input.take(count)?))
+                    }
+                    false => None,
+                };
+                let mut children: [Option<NodeHandlePlan>; nibble_ops::NIBBLE_LENGTH] =
+                    Default::default();
+                for (i, child) in children.iter_mut().enumerate() {
+                    if bitmap.value_at(i) {
+                        let count = <Compact<u32>>::decode(&mut input)?.0 as usize;
+                        let range = input.take(count)?;
+                        *child = Some(match count == H::LENGTH {
+                            true => NodeHandlePlan::Hash(range),
+                            false => NodeHandlePlan::Inline(range),
+                        });
+                    }
+                }
+                NodePlan::NibbledBranch {
+                    partial: NibbleSlicePlan::new(partial, partial_padding),
+                    value,
+                    children,
+                }
+            }
+            NodeHeader::Leaf(nibble_count) => {
+                let padding = nibble_count % nibble_ops::NIBBLE_PER_BYTE != 0;
+                // check that the padding is valid (if any)
+                if padding && nibble_ops::pad_left(data[input.offset]) != 0 {
+                    return Err(CodecError::from("Bad format"));
+                }
+                let partial = input.take(nibble_count.div_ceil(nibble_ops::NIBBLE_PER_BYTE))?;
+                let partial_padding = nibble_ops::number_padding(nibble_count);
+                let count = <Compact<u32>>::decode(&mut input)?.0 as usize;
+                let value = ValuePlan::Inline(input.take(count)?);
+                NodePlan::Leaf {
+                    partial: NibbleSlicePlan::new(partial, partial_padding),
+                    value,
+                }
+            }
+        })
+    }
+
+    fn is_empty_node(data: &[u8]) -> bool {
+        data == <Self as NodeCodec>::empty_node()
+    }
+
+    fn empty_node() -> &'static [u8] {
+        &[EMPTY_TRIE_NO_EXT]
+    }
+
+    fn leaf_node(partial: impl Iterator<Item = u8>, number_nibble: usize, value: Value) -> Vec<u8> {
+        let mut output = partial_encode(partial, number_nibble, NodeHeader::Leaf);
+        match value {
+            Value::Inline(value) => {
+                Compact(value.len() as u32).encode_to(&mut output);
+                output.extend_from_slice(value);
+            }
+            // MAX_INLINE_VALUE is None, so values are never stored in separated nodes
+            Value::Node(..) => unreachable!("no value node in NoExtensionLayout"),
+        }
+        output
+    }
+
+    fn extension_node(
+        _partial: impl Iterator<Item = u8>,
+        _number_nibble: usize,
+        _child: ChildReference<<H as Hasher>::Out>,
+    ) -> Vec<u8> {
+        unreachable!("no extension node in NoExtensionLayout")
+    }
+
+    fn branch_node(
+        _children: impl Iterator<Item = impl Borrow<Option<ChildReference<<H as Hasher>::Out>>>>,
+        _maybe_value: Option<Value>,
+    ) -> Vec<u8> {
+        unreachable!("no extension node in NoExtensionLayout")
+    }
+
+    fn branch_node_nibbled(
+        partial: impl Iterator<Item = u8>,
+        number_nibble: usize,
+        children: impl Iterator<Item = impl Borrow<Option<ChildReference<Self::HashOut>>>>,
+        maybe_value: Option<Value>,
+    ) -> Vec<u8> {
+        let has_value = maybe_value.is_some();
+        let mut output = partial_encode(partial, number_nibble, |nibble_count| {
+            NodeHeader::Branch(has_value, nibble_count)
+        });
+        let bitmap_index = output.len();
+        let mut bitmap: [u8; BITMAP_LENGTH] = [0; BITMAP_LENGTH];
+        output.extend_from_slice(&[0; BITMAP_LENGTH]);
+        match maybe_value {
+            Some(Value::Inline(value)) => {
+                Compact(value.len() as u32).encode_to(&mut output);
+                output.extend_from_slice(value);
+            }
+            // MAX_INLINE_VALUE is None, so values are never stored in separated nodes
+            Some(Value::Node(..)) => unreachable!("no value node in NoExtensionLayout"),
+            None => (),
+        }
+        Bitmap::encode(
+            children.map(|maybe_child| match maybe_child.borrow() {
+                Some(ChildReference::Hash(hash)) => {
+                    hash.as_ref().encode_to(&mut output);
+                    true
+                }
+                &Some(ChildReference::Inline(inline_data, len)) => {
+                    inline_data.as_ref()[..len].encode_to(&mut output);
+                    true
+                }
+                None => false,
+            }),
+            &mut bitmap,
+        );
+        output[bitmap_index..bitmap_index + BITMAP_LENGTH].copy_from_slice(&bitmap);
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use memory_db::{HashKey, MemoryDB};
+    use trie_db::{TrieDBMutBuilder, TrieMut};
+
+    type ReferenceLayout = reference_trie::NoExtensionLayout;
+
+    /// `Items` is the key-value pairs inserted into a trie, or the hashes and encodings of its nodes
+    type Items = Vec<(Vec<u8>, Vec<u8>)>;
+
+    /// `build` inserts the key-value pairs into an empty trie, and returns the root and the trie nodes ordered by hash
+    fn build<L: TrieLayout>(items: &[(Vec<u8>, Vec<u8>)]) -> (Vec<u8>, Items) {
+        let mut db = MemoryDB::<L::Hash, HashKey<L::Hash>, Vec<u8>>::default();
+        let mut root = Default::default();
+        {
+            let mut trie = TrieDBMutBuilder::<L>::new(&mut db, &mut root).build();
+            for (key, value) in items {
+                trie.insert(key, value).unwrap();
+            }
+            trie.commit();
+        }
+        let mut nodes: Items = db
+            .drain()
+            .into_iter()
+            .filter(|(_, (_, ref_count))| *ref_count > 0)
+            .map(|(hash, (node, _))| (hash.as_ref().to_vec(), node))
+            .collect();
+        nodes.sort();
+        (root.as_ref().to_vec(), nodes)
+    }
+
+    #[test]
+    fn same_as_reference_trie() {
+        assert_eq!(
+            RefHasher::hash(b"pchain"),
+            <reference_trie::RefHasher as Hasher>::hash(b"pchain")
+        );
+        let mut cases: Vec<(&str, Items)> = vec![
+            ("empty trie", vec![]),
+            (
+                "branch with inline children",
+                vec![(vec![1, 2], vec![3]), (vec![1, 3], vec![4])],
+            ),
+            (
+                "partial key over 62 nibbles",
+                vec![(vec![5; 40], vec![6; 40])],
+            ),
+            (
+                "branch with partial key over 62 nibbles",
+                vec![(vec![5; 40], vec![6; 40]), (vec![5; 200], vec![7])],
+            ),
+            (
+                "branch with a value",
+                vec![
+                    (b"a".to_vec(), b"1".to_vec()),
+                    (b"ab".to_vec(), b"2".to_vec()),
+                    (b"ac".to_vec(), vec![3; 40]),
+                ],
+            ),
+        ];
+        // keys of different lengths sharing prefixes, and values below and above the length of a hash
+        let items = (0..300_u32)
+            .map(|i| {
+                let hash = RefHasher::hash(&i.to_le_bytes());
+                let key = hash[..1 + i as usize % 32].to_vec();
+                (key, vec![hash[0]; i as usize % 70])
+            })
+            .collect();
+        cases.push(("mixed keys and values", items));
+
+        for (case, items) in cases {
+            let (root, nodes) = build::<NoExtensionLayout>(&items);
+            assert_eq!(
+                (root, nodes.clone()),
+                build::<ReferenceLayout>(&items),
+                "{case}"
+            );
+            for (_, node) in nodes {
+                assert_eq!(
+                    format!("{:?}", NodeCodecNoExt::<RefHasher>::decode_plan(&node)),
+                    format!(
+                        "{:?}",
+                        <ReferenceLayout as TrieLayout>::Codec::decode_plan(&node)
+                    ),
+                    "{case}"
+                );
+            }
+        }
+        assert_eq!(build::<NoExtensionLayout>(&[]).0, RefHasher::hash(&[0]));
+        // the children of the branch are inlined in it
+        let items = [(vec![1, 2], vec![3]), (vec![1, 3], vec![4])];
+        assert_eq!(build::<NoExtensionLayout>(&items).1.len(), 1);
+    }
+}
@@ -0,0 +1,174 @@
+/*
+    Copyright © 2023, ParallelChain Lab
+    Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
+*/
+
+//! This mod provides functions to verify the proofs of account fields and storage values against the state root,
+//! for light clients and contracts. It does not depend on `std` or `pchain-types`, but only on the hasher and
+//! trie codec of the trie and the V1 and V2 key layouts, so it can be built alone with
+//! `default-features = false, features = ["verify"]`.
+//!
+//! Hashes and account addresses are 32 bytes, same as `Sha256Hash` and `PublicAddress` in `pchain-types`.
+//! The proofs are the proof nodes returned by the `*_with_proof` methods, prefixed by the proof level.
+//! [proof](crate::proof) provides the same verification, and more, for the types of the world state.
+
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+use trie_db::proof::verify_proof;
+
+pub use crate::keys::AccountField;
+use crate::{
+    keys::{account_key, proof_level, proof_level::ProofLevel, storage_key},
+    trie_layout::NoExtensionLayout,
+    version::VersionProvider,
+};
+
+/// `ProofError` is error triggled when a proof fails the verification in [verify](crate::verify) or [proof](crate::proof)
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProofError {
+    /// A proof node is not prefixed by the expected proof level (accounts or storage)
+    InvalidProofLevel,
+    /// The root hash computed from the proof and the expected value is not the expected root hash.
+    /// The proofs do not contain the values, so a wrong expected value is also reported as RootMismatch.
+    RootMismatch,
+    /// The proof shows a value while the expected value is None, or the other way round
+    ValueMismatch,
+    /// The proof is missing trie nodes required to verify
+    IncompleteProof,
+    /// The proof contains malformed or extraneous trie nodes
+    InvalidProof,
+    /// The key-value pairs in the range of the proof are not the expected key-value pairs
+    RangeMismatch,
+}
+
+impl<HO, CE> From<trie_db::proof::VerifyError<HO, CE>> for ProofError {
+    fn from(err: trie_db::proof::VerifyError<HO, CE>) -> Self {
+        match err {
+            trie_db::proof::VerifyError::RootMismatch(_) => ProofError::RootMismatch,
+            trie_db::proof::VerifyError::ValueMismatch(_) => ProofError::ValueMismatch,
+            trie_db::proof::VerifyError::IncompleteProof => ProofError::IncompleteProof,
+            _ => ProofError::InvalidProof,
+        }
+    }
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            ProofError::InvalidProofLevel => write!(f, "Invalid Proof Level"),
+            ProofError::RootMismatch => write!(f, "Root Mismatch"),
+            ProofError::ValueMismatch => write!(f, "Value Mismatch"),
+            ProofError::IncompleteProof => write!(f, "Incomplete Proof"),
+            ProofError::InvalidProof => write!(f, "Invalid Proof"),
+            ProofError::RangeMismatch => write!(f, "Range Mismatch"),
+        }
+    }
+}
+
+/// `verify_account_field` verifies the proof of an account field against the state root of the AccountsTrie.
+/// The key layout of AccountsTrie is chosen by the version `V`.
+///
+/// `expected` is the value stored in the trie, i.e. little-endian bytes of nonce, balance and cbi version, the code,
+/// or the storage hash. None verifies that the field is absent.
+///
+/// Error when the proof does not show the expected value under the state root
+pub fn verify_account_field<V: VersionProvider>(
+    root: &[u8; 32],
+    address: &[u8; 32],
+    field: AccountField,
+    proof: &[Vec<u8>],
+    expected: Option<&[u8]>,
+) -> Result<(), ProofError> {
+    let key = account_key::<V>(address, field);
+    verify_trie_proof(root, proof_level::ACCOUNTS, proof, vec![(key, expected)])
+}
+
+/// `verify_storage` verifies the proof of a key in the storage of an account against the storage hash of the account.
+/// The key layout of StorageTrie is chosen by the version `V`.
+///
+/// `expected` is the value stored in the trie. None verifies that the key is absent.
+///
+/// Error when the proof does not show the expected value under the storage hash
+pub fn verify_storage<V: VersionProvider>(
+    storage_root: &[u8; 32],
+    key: &[u8],
+    proof: &[Vec<u8>],
+    expected: Option<&[u8]>,
+) -> Result<(), ProofError> {
+    let key = storage_key::<V>(&key.to_vec());
+    verify_trie_proof(
+        storage_root,
+        proof_level::STORAGE,
+        proof,
+        vec![(key, expected)],
+    )
+}
+
+/// `verify_world_state` verifies the proof of a key in the storage of an account against only the state root.
+/// The storage hash is verified against the state root first, and then the key is verified against the storage hash.
+/// The key layouts are chosen by the version `V`.
+///
+/// `storage_hash` is the storage hash of the account, None if the account has no storage. `proof` is the proof nodes of
+/// the storage hash in proof level ACCOUNTS, followed by the proof nodes of the key in proof level STORAGE.
+/// `expected` is the value stored in the storage. None verifies that the key is absent, including the case that
+/// the account has no storage.
+///
+/// Error when the proof does not show the expected value under the state root
+pub fn verify_world_state<V: VersionProvider>(
+    state_root: &[u8; 32],
+    address: &[u8; 32],
+    key: &[u8],
+    storage_hash: Option<&[u8; 32]>,
+    proof: &[Vec<u8>],
+    expected: Option<&[u8]>,
+) -> Result<(), ProofError> {
+    let (accounts_proof, storage_proof): (Vec<Vec<u8>>, Vec<Vec<u8>>) = proof
+        .iter()
+        .cloned()
+        .partition(|node| node.first() == Some(&proof_level::ACCOUNTS));
+
+    verify_account_field::<V>(
+        state_root,
+        address,
+        AccountField::StorageHash,
+        &accounts_proof,
+        storage_hash.map(|hash| hash.as_slice()),
+    )?;
+
+    match storage_hash {
+        Some(storage_hash) => verify_storage::<V>(storage_hash, key, &storage_proof, expected),
+        None if !storage_proof.is_empty() => Err(ProofError::InvalidProof),
+        None if expected.is_some() => Err(ProofError::ValueMismatch),
+        None => Ok(()),
+    }
+}
+
+/// `verify_trie_proof` strips the proof level from the proof nodes and verifies the trie proof of the trie keys.
+/// The trie keys are sorted as required by the trie proof, and repeated items are verified once.
+pub(crate) fn verify_trie_proof(
+    root: &[u8; 32],
+    level: ProofLevel,
+    proof: &[Vec<u8>],
+    mut items: Vec<(Vec<u8>, Option<&[u8]>)>,
+) -> Result<(), ProofError> {
+    let nodes = strip_proof_level(level, proof)?;
+    items.sort();
+    items.dedup();
+    verify_proof::<NoExtensionLayout, _, _, _>(root, &nodes, &items)?;
+    Ok(())
+}
+
+/// `strip_proof_level` returns the trie nodes of the proof nodes in the proof level
+pub(crate) fn strip_proof_level(
+    level: ProofLevel,
+    proof: &[Vec<u8>],
+) -> Result<Vec<Vec<u8>>, ProofError> {
+    proof
+        .iter()
+        .map(|node| match node.split_first() {
+            Some((node_level, node)) if *node_level == level => Ok(node.to_vec()),
+            _ => Err(ProofError::InvalidProofLevel),
+        })
+        .collect()
+}
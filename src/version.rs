@@ -7,7 +7,11 @@
 
 /// `Version` is to identify the different between the old version WorldState and new version WorldState.
 /// V1 is the old version and V2 is the new version
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "std",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
 pub enum Version {
     V1,
     V2,
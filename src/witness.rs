@@ -0,0 +1,101 @@
+/*
+    Copyright © 2023, ParallelChain Lab
+    Licensed under the Apache License, Version 2.0: http://www.apache.org/licenses/LICENSE-2.0
+*/
+
+//! This mod provides [WitnessRecorder] and [Witness] for stateless block verification.
+//! A block is executed once on a WorldState with a WitnessRecorder, which records every trie node read from the database.
+//! The recorded [Witness] is a [DB] holding only these trie nodes, so the block can be executed again on a WorldState
+//! opened on the Witness, without the full database, to check the state root after the block.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::db::DB;
+
+/// `WitnessRecorder` records the trie nodes read from the database by
+/// [KeyInstrumentedDB](crate::db::KeyInstrumentedDB), including the nodes found in the [NodeCache](crate::node_cache::NodeCache).
+/// Trie nodes written by the WorldState itself are not read from the database, so they are not recorded.
+///
+/// Cloning a WitnessRecorder returns a handle to the same records, so that it can be set to a WorldState by
+/// [WorldState::set_witness_recorder](crate::world_state::WorldState::set_witness_recorder) and read by the caller afterwards.
+#[derive(Clone, Default)]
+pub struct WitnessRecorder {
+    nodes: Arc<Mutex<BTreeMap<Vec<u8>, Vec<u8>>>>,
+}
+
+impl WitnessRecorder {
+    /// `new` is to create a WitnessRecorder with no records
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `record` records the value of the physical key read from the database
+    pub(crate) fn record(&self, key: &[u8], value: &[u8]) {
+        let mut nodes = self.nodes.lock().unwrap();
+        if !nodes.contains_key(key) {
+            nodes.insert(key.to_vec(), value.to_vec());
+        }
+    }
+
+    /// `witness` returns the [Witness] of all the trie nodes recorded so far
+    pub fn witness(&self) -> Witness {
+        Witness {
+            nodes: self.nodes.lock().unwrap().clone(),
+        }
+    }
+
+    /// `clear` removes all the records, e.g. before recording the next block
+    pub fn clear(&self) {
+        self.nodes.lock().unwrap().clear();
+    }
+}
+
+impl fmt::Debug for WitnessRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WitnessRecorder")
+            .field("num_nodes", &self.nodes.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// `Witness` is the trie nodes recorded by [WitnessRecorder], keyed by physical key.
+///
+/// It implements [DB], so a WorldState can be opened on it by the state root before the block.
+/// Reading a trie node not in the witness fails with [MptError::IncompleteDatabase](crate::error::MptError::IncompleteDatabase),
+/// or [MptError::InvalidStateRoot](crate::error::MptError::InvalidStateRoot) if it is the root node.
+#[derive(Debug, Clone, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct Witness {
+    nodes: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Witness {
+    /// `len` is the number of trie nodes in the witness
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// `is_empty` checks if the witness contains no trie node
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// `size_bytes` is the total length in bytes of the physical keys and trie nodes in the witness
+    pub fn size_bytes(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum()
+    }
+}
+
+impl DB for Witness {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.nodes.get(key).cloned()
+    }
+}
@@ -8,29 +8,221 @@
 //! [WorldState] read and update data in trie structrue.
 
 use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
 
+use borsh::{BorshDeserialize, BorshSerialize};
+use hash_db::Hasher;
 use pchain_types::cryptography::{PublicAddress, Sha256Hash};
 
-use crate::db::DB;
+use crate::db::{DBWrite, WriteBatch, DB};
 
 use crate::{
-    accounts_trie::AccountsTrie,
-    error::{MptError, WorldStateError},
+    accounts_trie::{AccountField, AccountsTrie},
+    error::{DecodeOrEncodeError, MptError, WorldStateError},
+    mpt::{MptChanges, ValueChange},
+    node_cache::NodeCache,
+    proof::{AccountProofBundle, StorageProof, WorldStateProof},
+    state_root_registry::{StateRootEntry, StateRootRegistry},
     storage_trie::StorageTrie,
+    trie_layout::RefHasher,
     version::*,
+    witness::WitnessRecorder,
 };
 
 /// `WorldStateChanges` store the WorldState changes since opening.
 /// For Fullnode and Runtime to create AppState updates
 ///
 /// Keys in inserts and deletes are physical keys
-#[derive(Debug, Clone)]
+///
+/// It is serialized by borsh, and by serde with the feature `serde`, with the inserts and deletes sorted by key,
+/// so that the same changes are always serialized into the same bytes, e.g. for write-ahead logs and transfers
+/// between processes. [WorldStateChanges::content_hash] identifies the changes by these bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "SortedWorldStateChanges", try_from = "SortedWorldStateChanges")
+)]
 pub struct WorldStateChanges {
     pub inserts: HashMap<Vec<u8>, Vec<u8>>,
     pub deletes: HashSet<Vec<u8>>,
     pub new_root_hash: Sha256Hash,
 }
 
+impl WorldStateChanges {
+    /// `merge` merges the changes of the later block into these changes, so that applying the merged changes is the same as
+    /// applying these changes and then the later changes. The new_root_hash is the one of the later changes.
+    ///
+    /// A key deleted by the later changes is no longer inserted, and is still deleted because it may be in the database
+    /// before these changes. A key inserted by the later changes is no longer deleted.
+    pub fn merge(&mut self, later: WorldStateChanges) {
+        for key in later.deletes {
+            self.inserts.remove(&key);
+            self.deletes.insert(key);
+        }
+        for (key, value) in later.inserts {
+            self.deletes.remove(&key);
+            self.inserts.insert(key, value);
+        }
+        self.new_root_hash = later.new_root_hash;
+    }
+
+    /// `merge_all` merges the changes of consecutive blocks in order into one set of changes by [WorldStateChanges::merge].
+    /// Return None if there is no changes.
+    pub fn merge_all<I: IntoIterator<Item = WorldStateChanges>>(
+        changes: I,
+    ) -> Option<WorldStateChanges> {
+        let mut changes = changes.into_iter();
+        let mut merged = changes.next()?;
+        for later in changes {
+            merged.merge(later);
+        }
+        Some(merged)
+    }
+}
+
+/// implementations of the serialization of WorldStateChanges
+impl WorldStateChanges {
+    /// `to_bytes` serializes the changes by borsh, with the inserts and deletes sorted by key
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // writing into Vec does not fail
+        self.try_to_vec().unwrap()
+    }
+
+    /// `from_bytes` deserializes the changes serialized by [WorldStateChanges::to_bytes]
+    ///
+    /// Error if the bytes are malformed, or the inserts or deletes are not sorted by key without duplicates
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WorldStateError> {
+        Self::try_from_slice(bytes).map_err(|_| DecodeOrEncodeError::DecodeError.into())
+    }
+
+    /// `content_hash` is the hash of the serialized changes by the hasher of the trie nodes,
+    /// which is the same for the same changes
+    pub fn content_hash(&self) -> Sha256Hash {
+        RefHasher::hash(&self.to_bytes())
+    }
+}
+
+/// The serialization is the same as `SortedWorldStateChanges`, without copying the changes
+impl BorshSerialize for WorldStateChanges {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut inserts: Vec<(&Vec<u8>, &Vec<u8>)> = self.inserts.iter().collect();
+        inserts.sort();
+        (inserts.len() as u32).serialize(writer)?;
+        for (key, value) in inserts {
+            key.serialize(writer)?;
+            value.serialize(writer)?;
+        }
+        let mut deletes: Vec<&Vec<u8>> = self.deletes.iter().collect();
+        deletes.sort();
+        (deletes.len() as u32).serialize(writer)?;
+        for key in deletes {
+            key.serialize(writer)?;
+        }
+        self.new_root_hash.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for WorldStateChanges {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        SortedWorldStateChanges::deserialize_reader(reader)?
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "unsorted WorldStateChanges"))
+    }
+}
+
+/// `SortedWorldStateChanges` is the serialized form of [WorldStateChanges], with the inserts and deletes sorted by key
+#[derive(Clone, BorshSerialize, BorshDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SortedWorldStateChanges {
+    inserts: Vec<(Vec<u8>, Vec<u8>)>,
+    deletes: Vec<Vec<u8>>,
+    new_root_hash: Sha256Hash,
+}
+
+impl From<WorldStateChanges> for SortedWorldStateChanges {
+    fn from(changes: WorldStateChanges) -> Self {
+        let mut inserts: Vec<(Vec<u8>, Vec<u8>)> = changes.inserts.into_iter().collect();
+        inserts.sort();
+        let mut deletes: Vec<Vec<u8>> = changes.deletes.into_iter().collect();
+        deletes.sort();
+        SortedWorldStateChanges {
+            inserts,
+            deletes,
+            new_root_hash: changes.new_root_hash,
+        }
+    }
+}
+
+/// Unsorted or duplicated keys are rejected, so that only one serialization is accepted for the same changes
+impl TryFrom<SortedWorldStateChanges> for WorldStateChanges {
+    type Error = DecodeOrEncodeError;
+
+    fn try_from(changes: SortedWorldStateChanges) -> Result<Self, Self::Error> {
+        let inserts_sorted = changes.inserts.windows(2).all(|pair| pair[0].0 < pair[1].0);
+        let deletes_sorted = changes.deletes.windows(2).all(|pair| pair[0] < pair[1]);
+        if !inserts_sorted || !deletes_sorted {
+            return Err(DecodeOrEncodeError::DecodeError);
+        }
+        Ok(WorldStateChanges {
+            inserts: changes.inserts.into_iter().collect(),
+            deletes: changes.deletes.into_iter().collect(),
+            new_root_hash: changes.new_root_hash,
+        })
+    }
+}
+
+impl From<MptChanges> for WorldStateChanges {
+    fn from(mpt_changes: MptChanges) -> Self {
+        WorldStateChanges {
+            inserts: mpt_changes.0,
+            deletes: mpt_changes.1,
+            new_root_hash: mpt_changes.2,
+        }
+    }
+}
+
+/// `StorageChanges` is the inserts and deletes collected from the StorageTries of a WorldState
+type StorageChanges = (HashMap<Vec<u8>, Vec<u8>>, HashSet<Vec<u8>>);
+
+/// `StateDiff` is the logical changes of the accounts in a WorldState session, returned by
+/// [WorldState::close_with_state_diff], so that indexers can be updated without diffing the tries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// the changed accounts ordered by account address
+    pub accounts: Vec<AccountDiff>,
+}
+
+/// `AccountDiff` is the changes of an account in [StateDiff]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountDiff {
+    pub address: PublicAddress,
+    /// the values before and after of every account field, in the order of [AccountField::ALL]
+    pub fields: Vec<FieldDiff>,
+    /// the changes of the modified storage keys ordered by key
+    pub storage: Vec<(Vec<u8>, ValueChange)>,
+}
+
+/// `FieldDiff` is the values of an account field before and after the changes, None if the field is not set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: AccountField,
+    pub before: Option<Vec<u8>>,
+    pub after: Option<Vec<u8>>,
+}
+
+impl FieldDiff {
+    /// `is_changed` checks if the value after is different from the value before
+    pub fn is_changed(&self) -> bool {
+        self.before != self.after
+    }
+}
+
+/// `Checkpoint` is a savepoint in a WorldState session returned by [WorldState::checkpoint]. The changes after it can be
+/// reverted by [WorldState::revert_to] or kept by [WorldState::discard]. Checkpoints can be nested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
 /// WorldState is a struct to read and update data in trie structrue.
 /// It caches account information and account storage change by [KeyInstrumentedDB](crate::db::KeyInstrumentedDB).
 /// And `close` will return the cached changes as struct [WorldStateChanges] to caller, which can store the change to physical database.
@@ -47,6 +239,13 @@ where
     accounts_trie: AccountsTrie<'a, S, V>,
     storage_trie_map: HashMap<PublicAddress, StorageTrie<'a, S, V>>,
     db: &'a S,
+    node_cache: Option<NodeCache>,
+    witness_recorder: Option<WitnessRecorder>,
+    record_state_diff: bool,
+    // number of checkpoints not reverted or discarded yet
+    num_checkpoints: usize,
+    // number of checkpoints when the StorageTrie is opened, for StorageTries opened after a checkpoint
+    storage_trie_checkpoints: HashMap<PublicAddress, usize>,
 }
 
 impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
@@ -61,6 +260,11 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
             accounts_trie,
             storage_trie_map: HashMap::new(),
             db,
+            node_cache: None,
+            witness_recorder: None,
+            record_state_diff: false,
+            num_checkpoints: 0,
+            storage_trie_checkpoints: HashMap::new(),
         }
     }
 
@@ -73,9 +277,47 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
             accounts_trie,
             storage_trie_map: HashMap::new(),
             db,
+            node_cache: None,
+            witness_recorder: None,
+            record_state_diff: false,
+            num_checkpoints: 0,
+            storage_trie_checkpoints: HashMap::new(),
         }
     }
 
+    /// `set_node_cache` sets the [NodeCache] shared by the AccountTrie and StorageTries of the WorldState.
+    /// Trie nodes are looked up in the cache before reading from the database.
+    pub fn set_node_cache(&mut self, node_cache: NodeCache) {
+        self.accounts_trie.set_node_cache(Some(node_cache.clone()));
+        for storage_trie in self.storage_trie_map.values_mut() {
+            storage_trie.set_node_cache(Some(node_cache.clone()));
+        }
+        self.node_cache = Some(node_cache);
+    }
+
+    /// `set_witness_recorder` sets the [WitnessRecorder] shared by the AccountTrie and StorageTries of the WorldState.
+    /// All trie nodes read from the database afterwards are recorded, so that the WorldState can be used again
+    /// on the recorded [Witness](crate::witness::Witness) without the database.
+    pub fn set_witness_recorder(&mut self, witness_recorder: WitnessRecorder) {
+        self.accounts_trie
+            .set_witness_recorder(Some(witness_recorder.clone()));
+        for storage_trie in self.storage_trie_map.values_mut() {
+            storage_trie.set_witness_recorder(Some(witness_recorder.clone()));
+        }
+        self.witness_recorder = Some(witness_recorder);
+    }
+
+    /// `set_state_diff_recording` enables or disables recording the values of the account fields and storage keys before
+    /// they are first written, so that [WorldState::close_with_state_diff] can return the [StateDiff] of the session.
+    /// Changes made before the recording is enabled are not included.
+    pub fn set_state_diff_recording(&mut self, enabled: bool) {
+        self.accounts_trie.set_value_recording(enabled);
+        for storage_trie in self.storage_trie_map.values_mut() {
+            storage_trie.set_value_recording(enabled);
+        }
+        self.record_state_diff = enabled;
+    }
+
     /// `account_trie_mut` return the created AccountTrie mut ref from created/opened WorldState for mutable operation
     pub fn account_trie_mut(&mut self) -> &mut AccountsTrie<'a, S, V> {
         &mut self.accounts_trie
@@ -101,29 +343,8 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         &mut self,
         address: &PublicAddress,
     ) -> Result<&mut StorageTrie<'a, S, V>, MptError> {
-        // if StorageTrie has been created, just return the created StorageTrie
-        if self.storage_trie_map.contains_key(address) {
-            return Ok(self.storage_trie_map.get_mut(address).unwrap());
-        }
-        // let mut account_trie = self.accounts_trie1;
-        let storage_trie = match self.accounts_trie.storage_hash(address)? {
-            Some(storage_hash) => {
-                // StorageTrie of input account address has been init
-                StorageTrie::<S, V>::open(self.db, storage_hash, address)
-            }
-            None => {
-                // StorageTrie of input account address has not been init
-                let storage_trie = StorageTrie::new(self.db, address);
-                // get the new storage_hash and insert into AccountTrie
-                let storage_trie_root = storage_trie.root_hash();
-                self.accounts_trie
-                    .set_storage_hash(address, storage_trie_root)?;
-                storage_trie
-            }
-        };
-        // insert created StorageTrie into storage_trie_map
-        self.storage_trie_map.insert(*address, storage_trie.clone());
-        return Ok(self.storage_trie_map.get_mut(address).unwrap());
+        self.open_storage_trie(address)?;
+        Ok(self.storage_trie_map.get_mut(address).unwrap())
     }
 
     /// `storage_trie` return StorageTrie unmut ref
@@ -141,14 +362,21 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
         &mut self,
         address: &PublicAddress,
     ) -> Result<&StorageTrie<'a, S, V>, MptError> {
-        // if StorageTrie has been created, just return the created StorageTrie
+        self.open_storage_trie(address)?;
+        Ok(self.storage_trie_map.get(address).unwrap())
+    }
+
+    /// `open_storage_trie` opens the StorageTrie of the account by its storage_hash, or initializes an empty one, with the
+    /// settings of the WorldState, and puts it into storage_trie_map. Nothing happens if it is already in storage_trie_map.
+    fn open_storage_trie(&mut self, address: &PublicAddress) -> Result<(), MptError> {
+        // if StorageTrie has been created, just use the created StorageTrie
         if self.storage_trie_map.contains_key(address) {
-            return Ok(self.storage_trie_map.get(address).unwrap());
+            return Ok(());
         }
-        let storage_trie = match self.accounts_trie.storage_hash(address)? {
+        let mut storage_trie = match self.accounts_trie.storage_hash(address)? {
             Some(storage_hash) => {
                 // StorageTrie of input account address has been init
-                StorageTrie::open(self.db, storage_hash, address)
+                StorageTrie::<S, V>::open(self.db, storage_hash, address)
             }
             None => {
                 // StorageTrie of input account address has not been init
@@ -160,35 +388,322 @@ impl<'a, S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
                 storage_trie
             }
         };
+        storage_trie.set_node_cache(self.node_cache.clone());
+        storage_trie.set_witness_recorder(self.witness_recorder.clone());
+        storage_trie.set_value_recording(self.record_state_diff);
+        // the checkpoints before the StorageTrie is opened are added to it at the opened state,
+        // and the StorageTrie is removed when reverting to these checkpoints
+        for _ in 0..self.num_checkpoints {
+            storage_trie.checkpoint();
+        }
+        if self.num_checkpoints > 0 {
+            self.storage_trie_checkpoints
+                .insert(*address, self.num_checkpoints);
+        }
         // insert created StorageTrie into storage_trie_map
-        self.storage_trie_map.insert(*address, storage_trie.clone());
-        return Ok(self.storage_trie_map.get(address).unwrap());
+        self.storage_trie_map.insert(*address, storage_trie);
+        Ok(())
+    }
+
+    /// `checkpoint` adds a checkpoint at the current state of the AccountTrie and all the cached StorageTries, such that
+    /// the changes afterwards, e.g. by a failed transaction, can be reverted by [WorldState::revert_to].
+    ///
+    /// The changes are recorded in a journal of the written trie nodes, so a checkpoint does not copy the tries.
+    /// Closing the WorldState removes all the checkpoints.
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        self.accounts_trie.checkpoint();
+        for storage_trie in self.storage_trie_map.values_mut() {
+            storage_trie.checkpoint();
+        }
+        self.num_checkpoints += 1;
+        Checkpoint(self.num_checkpoints - 1)
+    }
+
+    /// `revert_to` reverts the AccountTrie and all the cached StorageTries to the state at the checkpoint.
+    /// The checkpoint and the checkpoints after it are removed.
+    ///
+    /// Error if the checkpoint is already reverted or discarded
+    pub fn revert_to(&mut self, checkpoint: Checkpoint) -> Result<(), WorldStateError> {
+        self.check_checkpoint(checkpoint)?;
+        self.accounts_trie.revert_to_checkpoint(checkpoint.0);
+        // remove the StorageTries opened after the checkpoint
+        let storage_trie_map = &mut self.storage_trie_map;
+        self.storage_trie_checkpoints
+            .retain(|address, num_checkpoints| {
+                if *num_checkpoints > checkpoint.0 {
+                    storage_trie_map.remove(address);
+                    return false;
+                }
+                true
+            });
+        for storage_trie in self.storage_trie_map.values_mut() {
+            storage_trie.revert_to_checkpoint(checkpoint.0);
+        }
+        self.num_checkpoints = checkpoint.0;
+        Ok(())
+    }
+
+    /// `discard` removes the checkpoint and the checkpoints after it, keeping the changes since then.
+    /// The changes can still be reverted by an earlier checkpoint.
+    ///
+    /// Error if the checkpoint is already reverted or discarded
+    pub fn discard(&mut self, checkpoint: Checkpoint) -> Result<(), WorldStateError> {
+        self.check_checkpoint(checkpoint)?;
+        self.accounts_trie.discard_checkpoint(checkpoint.0);
+        for storage_trie in self.storage_trie_map.values_mut() {
+            storage_trie.discard_checkpoint(checkpoint.0);
+        }
+        // the StorageTries opened after the checkpoint are kept by reverting to the remaining checkpoints after it
+        for num_checkpoints in self.storage_trie_checkpoints.values_mut() {
+            *num_checkpoints = (*num_checkpoints).min(checkpoint.0);
+        }
+        self.num_checkpoints = checkpoint.0;
+        Ok(())
+    }
+
+    /// `check_checkpoint` checks that the checkpoint is not reverted or discarded yet
+    fn check_checkpoint(&self, checkpoint: Checkpoint) -> Result<(), WorldStateError> {
+        if checkpoint.0 >= self.num_checkpoints {
+            return Err(WorldStateError::InvalidCheckpoint);
+        }
+        Ok(())
+    }
+
+    /// `storage_with_proof` return the storage value of the account by key, with the proof of the value against
+    /// the state root, which can be verified by [verify_world_state_proof](crate::proof::verify_world_state_proof).
+    ///
+    /// The proof is built from the AccountTrie and the committed StorageTrie of the account, so the WorldState
+    /// should not have uncommitted storage changes of the account.
+    ///
+    /// Error if state_hash or storage_hash does not exist or missed some trie nodes
+    pub fn storage_with_proof(
+        &self,
+        address: &PublicAddress,
+        key: &Vec<u8>,
+    ) -> Result<(WorldStateProof, Option<Vec<u8>>), WorldStateError> {
+        let (mut proof, storage_hash) = self.accounts_trie.storage_hash_with_proof(address)?;
+        let value = match storage_hash {
+            Some(storage_hash) => {
+                let mut storage_trie = StorageTrie::<S, V>::open(self.db, storage_hash, address);
+                storage_trie.set_node_cache(self.node_cache.clone());
+                storage_trie.set_witness_recorder(self.witness_recorder.clone());
+                let (storage_proof, value) = storage_trie.get_with_proof(key)?;
+                proof.extend(storage_proof);
+                value
+            }
+            None => None,
+        };
+        Ok((
+            WorldStateProof {
+                storage_hash,
+                proof,
+            },
+            value,
+        ))
+    }
+
+    /// `account_proof_bundle` return the account fields of the account with one proof of all of them against the state root,
+    /// and the storage values of the keys with a proof of each key against the storage hash, in the shape of the answer of
+    /// `eth_getProof` (EIP-1186). The bundle can be verified by [verify_account_proof_bundle](crate::proof::verify_account_proof_bundle).
+    ///
+    /// The proofs are built from the AccountTrie and the committed StorageTrie of the account, so the WorldState
+    /// should not have uncommitted changes of the account.
+    ///
+    /// Error if state_hash or storage_hash does not exist or missed some trie nodes, or a stored account field is malformed
+    pub fn account_proof_bundle(
+        &self,
+        address: &PublicAddress,
+        storage_keys: &[Vec<u8>],
+    ) -> Result<AccountProofBundle, WorldStateError> {
+        let fields = AccountField::ALL.map(|field| (*address, field));
+        let (account_proof, values) = self.accounts_trie.fields_with_proof(&fields)?;
+        let [nonce, balance, code, cbi_version, storage_hash]: [Option<Vec<u8>>; 5] = values
+            .try_into()
+            .map_err(|_| DecodeOrEncodeError::DecodeError)?;
+
+        // the values are read from the database, a value of the wrong length is a decode error instead of a panic
+        let storage_hash: Option<Sha256Hash> = storage_hash
+            .map(|hash| hash.try_into())
+            .transpose()
+            .map_err(|_| DecodeOrEncodeError::DecodeError)?;
+        let nonce = nonce
+            .map(|value| value.try_into().map(u64::from_le_bytes))
+            .transpose()
+            .map_err(|_| DecodeOrEncodeError::DecodeError)?;
+        let balance = balance
+            .map(|value| value.try_into().map(u64::from_le_bytes))
+            .transpose()
+            .map_err(|_| DecodeOrEncodeError::DecodeError)?;
+        let cbi_version = cbi_version
+            .map(|value| value.try_into().map(u32::from_le_bytes))
+            .transpose()
+            .map_err(|_| DecodeOrEncodeError::DecodeError)?;
+        let storage_trie = storage_hash.map(|storage_hash| {
+            let mut storage_trie = StorageTrie::<S, V>::open(self.db, storage_hash, address);
+            storage_trie.set_node_cache(self.node_cache.clone());
+            storage_trie.set_witness_recorder(self.witness_recorder.clone());
+            storage_trie
+        });
+        let storage_proofs: Vec<StorageProof> = storage_keys
+            .iter()
+            .map(|key| {
+                let (proof, value) = match &storage_trie {
+                    Some(storage_trie) => storage_trie.get_with_proof(key)?,
+                    None => (Vec::new(), None),
+                };
+                Ok(StorageProof {
+                    key: key.clone(),
+                    value,
+                    proof,
+                })
+            })
+            .collect::<Result<_, MptError>>()?;
+
+        Ok(AccountProofBundle {
+            address: *address,
+            nonce,
+            balance,
+            code_hash: code.map(|code| AccountProofBundle::hash_code(&code)),
+            cbi_version,
+            storage_hash,
+            account_proof,
+            storage_proofs,
+        })
+    }
+
+    /// `state_root` updates the storage_hash of the accounts in AccountTrie by the current root hashes of the cached StorageTries,
+    /// and returns the current state root, e.g. for the receipt of a transaction. Unlike `close`, the cached changes are kept
+    /// for a later `close`.
+    ///
+    /// Error if state_hash does not exist or missed some trie nodes
+    pub fn state_root(&mut self) -> Result<Sha256Hash, WorldStateError> {
+        for (address, storage_trie) in self.storage_trie_map.iter() {
+            let storage_hash = storage_trie.root_hash();
+            if self.accounts_trie.storage_hash(address)? != Some(storage_hash) {
+                self.accounts_trie.set_storage_hash(address, storage_hash)?;
+            }
+        }
+        Ok(self.accounts_trie.root_hash())
     }
 
     /// `close` return all cached changes from the WorldState for caller to create App updates
     pub fn close(&mut self) -> Result<WorldStateChanges, WorldStateError> {
+        let storage_changes = self.close_storage_tries()?;
+        Ok(self.close_accounts_trie(storage_changes))
+    }
+
+    /// `close_with_state_diff` is `close` which also returns the [StateDiff] of the accounts changed since
+    /// [WorldState::set_state_diff_recording] or the last close. The StateDiff is empty if the recording is not enabled.
+    ///
+    /// Error if state_hash or storage_hash does not exist or missed some trie nodes
+    pub fn close_with_state_diff(
+        &mut self,
+    ) -> Result<(WorldStateChanges, StateDiff), WorldStateError> {
+        let mut storage_diffs = HashMap::new();
+        for (address, storage_trie) in self.storage_trie_map.iter() {
+            storage_diffs.insert(*address, storage_trie.changed_values()?);
+        }
+        let storage_changes = self.close_storage_tries()?;
+        // the storage hashes are updated by closing the StorageTries, so the field diffs include them
+        let accounts = self
+            .accounts_trie
+            .field_diffs()?
+            .into_iter()
+            .map(|(address, fields)| AccountDiff {
+                address,
+                fields,
+                storage: storage_diffs.remove(&address).unwrap_or_default(),
+            })
+            .collect();
+        Ok((
+            self.close_accounts_trie(storage_changes),
+            StateDiff { accounts },
+        ))
+    }
+
+    /// `close_storage_tries` collects all changes from the cached StorageTries, and updates the storage_hash in AccountTrie
+    fn close_storage_tries(&mut self) -> Result<StorageChanges, WorldStateError> {
         let mut inserts = HashMap::new();
         let mut deletes = HashSet::new();
-        // collect all changes from StorageTire by close all cached StorageTrie
-        for (address, mut storage_trie) in self.storage_trie_map.clone().into_iter() {
-            let storage_change = storage_trie.close();
+        for (address, storage_trie) in self.storage_trie_map.iter_mut() {
+            let storage_change = storage_trie.clone().close();
+            // drop the recorded values and checkpoints of the cached StorageTrie as the closed one
+            storage_trie.set_value_recording(self.record_state_diff);
+            storage_trie.discard_checkpoint(0);
             // update storage_hash for matched AccountTrie by closed storage_change's stroage_hash
             self.accounts_trie
-                .set_storage_hash(&address, storage_change.new_root_hash)?;
+                .set_storage_hash(address, storage_change.new_root_hash)?;
             // merge the inserts and deletes from StroageTrie
             inserts.extend(storage_change.inserts);
             deletes.extend(storage_change.deletes);
         }
-        // collect all changes from AccountTrie by close AccountTrie
+        Ok((inserts, deletes))
+    }
+
+    /// `close_accounts_trie` collects all changes from AccountTrie, merged with the changes from the StorageTries
+    fn close_accounts_trie(&mut self, storage_changes: StorageChanges) -> WorldStateChanges {
+        let (mut inserts, mut deletes) = storage_changes;
         let accounts_change = self.accounts_trie.close();
+        self.num_checkpoints = 0;
+        self.storage_trie_checkpoints.clear();
         // merge the inserts and deletes from AccountTrie
         inserts.extend(accounts_change.inserts);
         deletes.extend(accounts_change.deletes);
-        Ok(WorldStateChanges {
+        WorldStateChanges {
             inserts,
             deletes,
             new_root_hash: accounts_change.new_root_hash,
-        })
+        }
+    }
+}
+
+/// implementations for WorldState over a writable database
+impl<'a, S: DBWrite + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>
+    WorldState<'a, S, V>
+{
+    /// `commit` closes the WorldState and writes all the changes into the database in one atomic commit.
+    /// Return the new state root hash.
+    ///
+    /// Use `close` instead if the caller needs to batch the changes across blocks
+    pub fn commit(&mut self) -> Result<Sha256Hash, WorldStateError> {
+        let changes = self.close()?;
+        self.write_changes(changes, WriteBatch::new())
+    }
+
+    /// `commit_at_height` is the archive commit, which closes the WorldState and writes only the inserts of the changes,
+    /// together with the new state root recorded at the block height into the [StateRootRegistry], in one atomic commit.
+    /// The deletes are not written, so that the trie nodes of the state roots recorded before are kept and they can still
+    /// be opened by [StateRootRegistry::open_at_height]. Return the new state root hash.
+    pub fn commit_at_height(
+        &mut self,
+        registry: &StateRootRegistry<S>,
+        height: u64,
+    ) -> Result<Sha256Hash, WorldStateError> {
+        let mut changes = self.close()?;
+        changes.deletes.clear();
+        let entry = StateRootEntry {
+            height,
+            root_hash: changes.new_root_hash,
+            version: V::version(),
+        };
+        let batch = registry.record_batch(&entry)?;
+        self.write_changes(changes, batch)
+    }
+
+    /// `write_changes` writes the changes together with the writes in the batch, through the node cache if it is set
+    fn write_changes(
+        &self,
+        changes: WorldStateChanges,
+        mut batch: WriteBatch,
+    ) -> Result<Sha256Hash, WorldStateError> {
+        let new_root_hash = changes.new_root_hash;
+        batch.deletes.extend(changes.deletes);
+        batch.inserts.extend(changes.inserts);
+        match &self.node_cache {
+            Some(node_cache) => node_cache.write(self.db, batch)?,
+            None => self.db.write(batch)?,
+        }
+        Ok(new_root_hash)
     }
 }
 
@@ -207,13 +722,23 @@ impl<'a, S: DB + Send + Sync + Clone> WorldState<'a, S, V1> {
                     StorageTrie::open(self.db, storage_hash, &address)
                 }
             };
-            let storage_trie_v2 = storage_trie_v1.upgrade()?;
+            let mut storage_trie_v2 = storage_trie_v1.upgrade()?;
+            storage_trie_v2.set_node_cache(self.node_cache.clone());
+            storage_trie_v2.set_witness_recorder(self.witness_recorder.clone());
+            storage_trie_v2.set_value_recording(self.record_state_diff);
             storage_map.insert(address, storage_trie_v2);
         }
+        let mut accounts_trie = account_v2;
+        accounts_trie.set_value_recording(self.record_state_diff);
         Ok(WorldState {
-            accounts_trie: account_v2,
+            accounts_trie,
             storage_trie_map: storage_map,
             db: self.db,
+            node_cache: self.node_cache,
+            witness_recorder: self.witness_recorder,
+            record_state_diff: self.record_state_diff,
+            num_checkpoints: 0,
+            storage_trie_checkpoints: HashMap::new(),
         })
     }
 }
@@ -4,7 +4,7 @@ use pchain_world_state::*;
 use rand::rngs::OsRng;
 use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
 use reference_trie::RefHasher;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::time::Instant;
 pub type Key = Vec<u8>;
 pub type Value = Vec<u8>;
@@ -21,28 +21,6 @@ pub struct AccountInstance {
     pub storages: HashMap<Vec<u8>, Vec<u8>>,
 }
 
-#[derive(Debug, Clone)]
-struct DummyStorage(HashMap<Key, Value>);
-impl DB for DummyStorage {
-    fn get(&self, key: &[u8]) -> Option<Value> {
-        match self.0.get(key) {
-            Some(value) => Some(value.to_owned()),
-            None => None,
-        }
-    }
-}
-
-impl DummyStorage {
-    fn apply_changes(&mut self, inserts: HashMap<Vec<u8>, Vec<u8>>, deletes: HashSet<Vec<u8>>) {
-        for (key, value) in inserts.into_iter() {
-            self.0.insert(key, value);
-        }
-        for key in deletes.into_iter() {
-            self.0.remove(&key);
-        }
-    }
-}
-
 pub fn generate_public_addr() -> PublicAddress {
     let mut osrng = OsRng {};
     let mut chacha_20rng = ChaCha20Rng::from_rng(&mut osrng).unwrap();
@@ -94,8 +72,8 @@ pub fn generate_accounts() -> HashMap<PublicAddress, AccountInstance> {
 #[test]
 pub fn upgrade() {
     println!("start to build in v1....");
-    let mut db = DummyStorage(HashMap::new());
-    let mut ws_1: WorldState<'_, DummyStorage, V1> = WorldState::<DummyStorage, V1>::new(&db);
+    let db = MemoryDB::new();
+    let mut ws_1: WorldState<'_, MemoryDB, V1> = WorldState::<MemoryDB, V1>::new(&db);
     let accounts = generate_accounts();
     for (address, account) in accounts.into_iter() {
         let account_trie_mut = ws_1.account_trie_mut();
@@ -115,15 +93,15 @@ pub fn upgrade() {
         }
     }
     let ws_1_changes = ws_1.close().unwrap();
-    db.apply_changes(ws_1_changes.inserts, ws_1_changes.deletes);
+    db.apply_changes(ws_1_changes.clone());
     println!("finished build...");
 
     println!("start upgrade..");
     let start_time = Instant::now();
-    let ws1_new = WorldState::<DummyStorage, V1>::open(&db, ws_1_changes.new_root_hash);
+    let ws1_new = WorldState::<MemoryDB, V1>::open(&db, ws_1_changes.new_root_hash);
     let mut ws_2 = ws1_new.upgrade().unwrap();
     let ws_2_changes = ws_2.close().unwrap();
-    db.apply_changes(ws_2_changes.inserts, ws_2_changes.deletes);
+    db.apply_changes(ws_2_changes.clone());
     let end_time = Instant::now();
     println!(
         "upgrade cost: {} milliseconds", //181184 milliseconds
@@ -131,7 +109,7 @@ pub fn upgrade() {
     );
 
     println!("start to iter...");
-    let mut ws_2_new = WorldState::<DummyStorage, V2>::open(&db, ws_2_changes.new_root_hash);
+    let mut ws_2_new = WorldState::<MemoryDB, V2>::open(&db, ws_2_changes.new_root_hash);
     let start_time = Instant::now();
     let accounts = ws_2_new.account_trie().all().unwrap();
     for (address, _) in accounts.iter() {
@@ -146,7 +124,7 @@ pub fn upgrade() {
 
 #[test]
 pub fn test_upgrade_v1_to_v2() {
-    let mut db = DummyStorage(HashMap::new());
+    let db = MemoryDB::new();
     let mut ws_1 = WorldState::<_, V1>::new(&db);
 
     // Setup an account with some data in its storage
@@ -171,7 +149,7 @@ pub fn test_upgrade_v1_to_v2() {
     // Save to DB
     let ws_2_changes = ws_2.close().unwrap();
     let ws_2_root_hash = ws_2_changes.new_root_hash;
-    db.apply_changes(ws_2_changes.inserts, ws_2_changes.deletes);
+    db.apply_changes(ws_2_changes.clone());
 
     // Open world state from new root hash
     let mut ws_2 = WorldState::<_, V2>::open(&db, ws_2_root_hash);
@@ -4,11 +4,12 @@
 */
 
 //! Unit Test on functionalities on this crate
-//! The test use [DummyStorage] to simulate the pysical data base.
+//! The test use [MemoryDB] to simulate the pysical data base.
 //! [TestEnv] is a struct contains a simulate db and one account address
+//! [TestEnv::commit_genesis] commits the accounts and storage read by the proof and session tests
 //! [TestEnvWithSeveralAccounts] is a struct contains a simulate db and two accounts address
 //!
-//! There are 10 tests currently
+//! There are 38 tests currently
 //! 1.  [diff_version] test build WorldState by different version, which will return different keys in WorldStateChanges.inserts
 //! 2.  [update_nonce] test AccountTrie nonce operation
 //! 3.  [update_balance] test AccountTrie balance operation
@@ -19,58 +20,102 @@
 //! 8.  [search_with_proof] test AccountTrie and StorageTrie search with proof
 //! 9.  [upgrade] test upgrade from WorldState Version 1 to Version 2
 //! 10. [remove_storage_info] test remove <key, value > pair from StorageTrie by key
+//! 11. [memory_db] test MemoryDB apply changes, snapshot and stats
+//! 12. [commit] test WorldState commit changes into DBWrite
+//! 13. [read_error] test error from DB::try_get is returned instead of missing trie node, including during upgrade
+//! 14. [rocks_db] test WorldState on RocksDB with column families (feature `rocksdb`)
+//! 15. [batch_read] test reading account fields and storage values in one batch
+//! 16. [node_cache] test trie nodes are read from NodeCache and invalidated by commit and pruning
+//! 17. [pruning] test Pruner deletes trie nodes not reachable from the retained state roots in V1 and V2
+//! 18. [state_root_registry] test recording state roots by block height and opening WorldState at block height
+//! 19. [lazy_iter] test AccountTrie and StorageTrie lazy iteration with seek, prefix and resume
+//! 20. [accounts_page] test paginated account listing, and account iteration and proof bundle over a malformed account
+//! 21. [verify_proof] test verifying the proofs of account fields and storage values in V1 and V2
+//! 22. [world_state_proof] test the combined proof of storage value against the state root in V1 and V2
+//! 23. [batch_proof] test one proof for many account fields and storage keys, including absent keys, in V1 and V2
+//! 24. [absence_proof] test proving and verifying the absence of account fields and storage keys in V1 and V2
+//! 25. [range_proof] test proving and verifying the completeness of ranges of storage keys and account addresses in V1 and V2
+//! 26. [account_proof_bundle] test exporting and verifying the proofs of all account fields and some storage keys of an account in V1 and V2
+//! 27. [verify_module] test verifying account fields and storage values by the `no_std` verify module in V1 and V2
+//! 28. [witness] test executing a block again on the recorded witness without the database in V1 and V2
+//! 29. [trie_diff] test the changes of account fields and storage key-value pairs between two roots in V1 and V2
+//! 30. [state_diff] test the values before and after of account fields and the storage changes of a WorldState session in V1 and V2
+//! 31. [checkpoint] test reverting and discarding nested checkpoints of account fields and storage in V1 and V2
+//! 32. [intermediate_state_root] test the state root after storage changes without closing the WorldState in V1 and V2
+//! 33. [block_overlay] test opening WorldStates on speculative blocks of different branches, reading the nearest block first, and committing one branch, in V1 and V2
+//! 34. [merge_changes] test applying the merged changes of consecutive blocks at once in V1 and V2
+//! 35. [serialize_changes] test the deterministic serialization and content hash of WorldStateChanges
+//! 36. [golden_state_root] test the state roots of the same accounts and storage in V1 and V2 are kept by the trie layout
+//! 37. [test_network_account] test the current epoch, pools, delegated stakes and deposits of NetworkAccount
+//! 38. [test_network_account_validator_set] test the previous, current and next validator pools of NetworkAccount
 
-use pchain_types::cryptography::PublicAddress;
+use pchain_types::cryptography::{PublicAddress, Sha256Hash};
 use pchain_world_state::*;
-use std::collections::{HashMap, HashSet};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 pub type Key = Vec<u8>;
 pub type Value = Vec<u8>;
 
-#[derive(Debug, Clone)]
-struct DummyStorage(HashMap<Key, Value>);
-impl DB for DummyStorage {
-    fn get(&self, key: &[u8]) -> Option<Value> {
-        match self.0.get(key) {
-            Some(value) => Some(value.to_owned()),
-            None => None,
-        }
-    }
-}
-
-impl DummyStorage {
-    fn apply_changes(&mut self, inserts: HashMap<Vec<u8>, Vec<u8>>, deletes: HashSet<Vec<u8>>) {
-        for (key, value) in inserts.into_iter() {
-            self.0.insert(key, value);
-        }
-        for key in deletes.into_iter() {
-            self.0.remove(&key);
-        }
-    }
-}
-
 #[derive(Debug, Clone)]
 struct TestEnv {
-    db: DummyStorage,
+    db: MemoryDB,
     address: PublicAddress,
 }
 impl Default for TestEnv {
     fn default() -> Self {
-        let db = DummyStorage(HashMap::new());
+        let db = MemoryDB::new();
         const PUBLIC_KEY: &str = "ipy_VXNiwHNP9mx6-nKxht_ZJNfYoMAcCnLykpq4x_k";
         let address = base64url::decode(PUBLIC_KEY).unwrap().try_into().unwrap();
         Self { db, address }
     }
 }
 
+impl TestEnv {
+    /// `commit_genesis` commits the accounts shared by the proof and session tests into the db, and returns the state root,
+    /// the addresses of the users ordered by address, and the storage of the contract ordered by key.
+    ///
+    /// The contract at `address` has all account fields and 51 storage key-value pairs, including "apple". The 10 users have
+    /// the balances 0, 100, .., 900 only.
+    fn commit_genesis<V: VersionProvider + Send + Sync + Clone>(
+        &self,
+    ) -> (Sha256Hash, Vec<PublicAddress>, Vec<(Key, Value)>) {
+        let addresses: Vec<PublicAddress> = (1..=10_u8).map(|i| [i * 16; 32]).collect();
+        let storage: Vec<(Key, Value)> = (0..50_u8)
+            .map(|i| (vec![i, i % 7, i % 3], vec![i; 8]))
+            .chain([(b"apple".to_vec(), b"1234".to_vec())])
+            .collect();
+        let mut ws = WorldState::<MemoryDB, V>::new(&self.db);
+        for (i, address) in addresses.iter().enumerate() {
+            ws.account_trie_mut()
+                .set_balance(address, i as u64 * 100)
+                .unwrap();
+        }
+        let account_trie = ws.account_trie_mut();
+        account_trie.set_nonce(&self.address, 0).unwrap();
+        account_trie.set_balance(&self.address, 500).unwrap();
+        account_trie
+            .set_code(&self.address, vec![1, 2, 3, 4])
+            .unwrap();
+        account_trie.set_cbi_version(&self.address, 2).unwrap();
+        let storage_trie = ws.storage_trie_mut(&self.address).unwrap();
+        for (key, value) in storage.iter() {
+            storage_trie.set(key, value.clone()).unwrap();
+        }
+        (ws.commit().unwrap(), addresses, storage)
+    }
+}
+
 #[derive(Debug, Clone)]
 struct TestEnvWithSeveralAccounts {
-    db: DummyStorage,
+    db: MemoryDB,
     addresses: Vec<PublicAddress>,
 }
 
 impl Default for TestEnvWithSeveralAccounts {
     fn default() -> Self {
-        let db = DummyStorage(HashMap::new());
+        let db = MemoryDB::new();
         const PUBLIC_KEY_ONE: &str = "ipy_VXNiwHNP9mx6-nKxht_ZJNfYoMAcCnLykpq4x_k";
         const PUBLIC_KEY_TWO: &str = "l1RjOEHtM-RvUh7BxmCBgu33Pw1vqb8AgKJLMLqz3js";
         let address_one = base64url::decode(PUBLIC_KEY_ONE)
@@ -92,8 +137,8 @@ impl Default for TestEnvWithSeveralAccounts {
 fn diff_version() {
     let env_1 = TestEnv::default();
     let env_2 = TestEnv::default();
-    let mut ws_1 = WorldState::<DummyStorage, V1>::new(&env_1.db);
-    let mut ws_2 = WorldState::<DummyStorage, V2>::new(&env_2.db);
+    let mut ws_1 = WorldState::<MemoryDB, V1>::new(&env_1.db);
+    let mut ws_2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
     let ws_change_1 = ws_1.close().unwrap();
     let ws_change_2 = ws_2.close().unwrap();
     let key_1 = ws_change_1.inserts.into_iter().next().unwrap().0;
@@ -104,8 +149,8 @@ fn diff_version() {
 #[test]
 fn update_nonce() {
     // ================ Version1 ================
-    let mut env_1 = TestEnv::default();
-    let mut genesis_ws_v1 = WorldState::<DummyStorage, V1>::new(&env_1.db);
+    let env_1 = TestEnv::default();
+    let mut genesis_ws_v1 = WorldState::<MemoryDB, V1>::new(&env_1.db);
     assert_eq!(
         genesis_ws_v1.account_trie().nonce(&env_1.address).unwrap(),
         0_u64
@@ -115,10 +160,8 @@ fn update_nonce() {
         .set_nonce(&(env_1.address.clone()), 1_u64)
         .unwrap();
     let ws_changes_v1 = genesis_ws_v1.close().unwrap();
-    env_1
-        .db
-        .apply_changes(ws_changes_v1.inserts, ws_changes_v1.deletes);
-    let new_ws_v1 = WorldState::<DummyStorage, V1>::open(&env_1.db, ws_changes_v1.new_root_hash);
+    env_1.db.apply_changes(ws_changes_v1.clone());
+    let new_ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, ws_changes_v1.new_root_hash);
     assert!(new_ws_v1
         .account_trie()
         .contains_nonce(&env_1.address)
@@ -128,8 +171,8 @@ fn update_nonce() {
         1_u64
     );
     // ================ Version2 ================
-    let mut env_2 = TestEnv::default();
-    let mut genesis_ws_v2 = WorldState::<DummyStorage, V2>::new(&env_2.db);
+    let env_2 = TestEnv::default();
+    let mut genesis_ws_v2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
     assert_eq!(
         genesis_ws_v2.account_trie().nonce(&env_2.address).unwrap(),
         0_u64
@@ -139,10 +182,8 @@ fn update_nonce() {
         .set_nonce(&env_2.address, 1_u64)
         .unwrap();
     let ws_changes_v2 = genesis_ws_v2.close().unwrap();
-    env_2
-        .db
-        .apply_changes(ws_changes_v2.inserts, ws_changes_v2.deletes);
-    let new_ws_v2 = WorldState::<DummyStorage, V2>::open(&env_2.db, ws_changes_v2.new_root_hash);
+    env_2.db.apply_changes(ws_changes_v2.clone());
+    let new_ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, ws_changes_v2.new_root_hash);
     assert!(new_ws_v2
         .account_trie()
         .contains_nonce(&env_2.address)
@@ -156,8 +197,8 @@ fn update_nonce() {
 #[test]
 fn update_balance() {
     //================ Version1 ================
-    let mut env_1 = TestEnv::default();
-    let mut genesis_ws_1 = WorldState::<DummyStorage, V1>::new(&env_1.db);
+    let env_1 = TestEnv::default();
+    let mut genesis_ws_1 = WorldState::<MemoryDB, V1>::new(&env_1.db);
     assert_eq!(
         genesis_ws_1.account_trie().balance(&env_1.address).unwrap(),
         0_u64
@@ -167,17 +208,15 @@ fn update_balance() {
         .set_balance(&env_1.address, 100_000_u64)
         .unwrap();
     let ws_change_v1 = genesis_ws_1.close().unwrap();
-    env_1
-        .db
-        .apply_changes(ws_change_v1.inserts, ws_change_v1.deletes);
-    let new_ws_v1 = WorldState::<DummyStorage, V1>::open(&env_1.db, ws_change_v1.new_root_hash);
+    env_1.db.apply_changes(ws_change_v1.clone());
+    let new_ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, ws_change_v1.new_root_hash);
     assert_eq!(
         new_ws_v1.account_trie().balance(&env_1.address).unwrap(),
         100_000_u64
     );
     //================ Version2 ================
-    let mut env_2 = TestEnv::default();
-    let mut genesis_ws_2 = WorldState::<DummyStorage, V2>::new(&env_2.db);
+    let env_2 = TestEnv::default();
+    let mut genesis_ws_2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
     assert_eq!(
         genesis_ws_2.account_trie().balance(&env_2.address).unwrap(),
         0_u64
@@ -187,10 +226,8 @@ fn update_balance() {
         .set_balance(&env_2.address, 100_000_u64)
         .unwrap();
     let ws_change_v2 = genesis_ws_2.close().unwrap();
-    env_2
-        .db
-        .apply_changes(ws_change_v2.inserts, ws_change_v2.deletes);
-    let new_ws_v2 = WorldState::<DummyStorage, V2>::open(&env_2.db, ws_change_v2.new_root_hash);
+    env_2.db.apply_changes(ws_change_v2.clone());
+    let new_ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, ws_change_v2.new_root_hash);
     assert_eq!(
         new_ws_v2.account_trie().balance(&env_2.address).unwrap(),
         100_000_u64
@@ -202,8 +239,8 @@ fn update_code() {
     //================ Version1 ================
     let code_str = "Hello world";
     let code_vec = code_str.as_bytes().to_vec();
-    let mut env_1 = TestEnv::default();
-    let mut genesis_ws_v1 = WorldState::<DummyStorage, V1>::new(&env_1.db);
+    let env_1 = TestEnv::default();
+    let mut genesis_ws_v1 = WorldState::<MemoryDB, V1>::new(&env_1.db);
     assert!(genesis_ws_v1
         .account_trie()
         .code(&env_1.address)
@@ -215,10 +252,8 @@ fn update_code() {
         .set_code(&env_1.address, code_vec.clone())
         .unwrap();
     let ws_change_v1 = genesis_ws_v1.close().unwrap();
-    env_1
-        .db
-        .apply_changes(ws_change_v1.inserts, ws_change_v1.deletes);
-    let new_ws_v1 = WorldState::<DummyStorage, V1>::open(&env_1.db, ws_change_v1.new_root_hash);
+    env_1.db.apply_changes(ws_change_v1.clone());
+    let new_ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, ws_change_v1.new_root_hash);
     assert_eq!(
         std::str::from_utf8(
             &new_ws_v1
@@ -231,8 +266,8 @@ fn update_code() {
         "Hello world"
     );
     //================ Version2 ================
-    let mut env_2 = TestEnv::default();
-    let mut genesis_ws_v2 = WorldState::<DummyStorage, V2>::new(&env_2.db);
+    let env_2 = TestEnv::default();
+    let mut genesis_ws_v2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
     assert!(genesis_ws_v2
         .account_trie()
         .code(&env_2.address)
@@ -244,10 +279,8 @@ fn update_code() {
         .set_code(&env_2.address, code_vec.clone())
         .unwrap();
     let ws_change_v2 = genesis_ws_v2.close().unwrap();
-    env_2
-        .db
-        .apply_changes(ws_change_v2.inserts, ws_change_v2.deletes);
-    let new_ws_v2 = WorldState::<DummyStorage, V2>::open(&env_2.db, ws_change_v2.new_root_hash);
+    env_2.db.apply_changes(ws_change_v2.clone());
+    let new_ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, ws_change_v2.new_root_hash);
     assert_eq!(
         std::str::from_utf8(
             &new_ws_v2
@@ -264,8 +297,8 @@ fn update_code() {
 #[test]
 pub fn update_cbi_version() {
     //================ Version1 ================
-    let mut env_1 = TestEnv::default();
-    let mut genesis_ws_v1 = WorldState::<DummyStorage, V1>::new(&env_1.db);
+    let env_1 = TestEnv::default();
+    let mut genesis_ws_v1 = WorldState::<MemoryDB, V1>::new(&env_1.db);
     assert_eq!(
         genesis_ws_v1
             .account_trie()
@@ -278,10 +311,8 @@ pub fn update_cbi_version() {
         .set_cbi_version(&env_1.address, 1_u32)
         .unwrap();
     let ws_changes_v1 = genesis_ws_v1.close().unwrap();
-    env_1
-        .db
-        .apply_changes(ws_changes_v1.inserts, ws_changes_v1.deletes);
-    let new_ws_v1 = WorldState::<DummyStorage, V1>::open(&env_1.db, ws_changes_v1.new_root_hash);
+    env_1.db.apply_changes(ws_changes_v1.clone());
+    let new_ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, ws_changes_v1.new_root_hash);
     assert_eq!(
         new_ws_v1
             .account_trie()
@@ -290,8 +321,8 @@ pub fn update_cbi_version() {
         Some(1_u32)
     );
     //================ Version2 ================
-    let mut env_2 = TestEnv::default();
-    let mut genesis_ws_v2 = WorldState::<DummyStorage, V2>::new(&env_2.db);
+    let env_2 = TestEnv::default();
+    let mut genesis_ws_v2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
     assert_eq!(
         genesis_ws_v2
             .account_trie()
@@ -304,10 +335,8 @@ pub fn update_cbi_version() {
         .set_cbi_version(&env_2.address, 1_u32)
         .unwrap();
     let ws_changes_v2 = genesis_ws_v2.close().unwrap();
-    env_2
-        .db
-        .apply_changes(ws_changes_v2.inserts, ws_changes_v2.deletes);
-    let new_ws_v2 = WorldState::<DummyStorage, V2>::open(&env_2.db, ws_changes_v2.new_root_hash);
+    env_2.db.apply_changes(ws_changes_v2.clone());
+    let new_ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, ws_changes_v2.new_root_hash);
     assert_eq!(
         new_ws_v2
             .account_trie()
@@ -329,8 +358,8 @@ pub fn update_storage() {
     let value_apple: Value = b"1234".to_vec();
     let key_banana: Key = b"banana".to_vec();
     let value_banana: Value = b"12345".to_vec();
-    let mut env_1 = TestEnv::default();
-    let mut genesis_ws_v1 = WorldState::<DummyStorage, V1>::new(&env_1.db);
+    let env_1 = TestEnv::default();
+    let mut genesis_ws_v1 = WorldState::<MemoryDB, V1>::new(&env_1.db);
     let storage_trie_unmut_ref = genesis_ws_v1.storage_trie(&env_1.address).unwrap();
     assert!(storage_trie_unmut_ref.get(&key_apple).unwrap().is_none());
     assert!(storage_trie_unmut_ref.get(&key_banana).unwrap().is_none());
@@ -342,10 +371,8 @@ pub fn update_storage() {
         .set(&key_apple, value_apple.clone())
         .unwrap();
     let ws_change_v1 = genesis_ws_v1.close().unwrap();
-    env_1
-        .db
-        .apply_changes(ws_change_v1.inserts, ws_change_v1.deletes);
-    let mut new_ws_v1 = WorldState::<DummyStorage, V1>::open(&env_1.db, ws_change_v1.new_root_hash);
+    env_1.db.apply_changes(ws_change_v1.clone());
+    let mut new_ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, ws_change_v1.new_root_hash);
     let storage_trie_unmut_ref = new_ws_v1.storage_trie(&env_1.address).unwrap();
     assert!(storage_trie_unmut_ref.contains(&key_apple).unwrap());
     assert!(storage_trie_unmut_ref.contains(&key_banana).unwrap());
@@ -358,8 +385,8 @@ pub fn update_storage() {
         value_banana
     );
     //================ Version2 ================
-    let mut env_2 = TestEnv::default();
-    let mut genesis_ws_v2 = WorldState::<DummyStorage, V2>::new(&env_2.db);
+    let env_2 = TestEnv::default();
+    let mut genesis_ws_v2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
     let storage_trie_unmut_ref = genesis_ws_v2.storage_trie(&env_2.address).unwrap();
     assert!(storage_trie_unmut_ref.get(&key_apple).unwrap().is_none());
     assert!(storage_trie_unmut_ref.get(&key_banana).unwrap().is_none());
@@ -374,10 +401,8 @@ pub fn update_storage() {
         .set(&key_banana, value_banana.clone())
         .unwrap();
     let ws_change_v2 = genesis_ws_v2.close().unwrap();
-    env_2
-        .db
-        .apply_changes(ws_change_v2.inserts, ws_change_v2.deletes);
-    let mut new_ws_v2 = WorldState::<DummyStorage, V2>::open(&env_2.db, ws_change_v2.new_root_hash);
+    env_2.db.apply_changes(ws_change_v2.clone());
+    let mut new_ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, ws_change_v2.new_root_hash);
     let storage_trie_unmut_ref = new_ws_v2.storage_trie(&env_2.address).unwrap();
     assert!(storage_trie_unmut_ref.contains(&key_apple).unwrap());
     assert!(storage_trie_unmut_ref.contains(&key_banana).unwrap());
@@ -408,8 +433,8 @@ pub fn iter() {
 
     //================ Version1 ================
     // init trie
-    let mut env_1 = TestEnvWithSeveralAccounts::default();
-    let mut genesis_ws_v1 = WorldState::<DummyStorage, V1>::new(&env_1.db);
+    let env_1 = TestEnvWithSeveralAccounts::default();
+    let mut genesis_ws_v1 = WorldState::<MemoryDB, V1>::new(&env_1.db);
     // update account info
     let account_trie_mut_ref = genesis_ws_v1.account_trie_mut();
     account_trie_mut_ref
@@ -448,12 +473,9 @@ pub fn iter() {
         .unwrap();
     // close change
     let ws_changes_v1 = genesis_ws_v1.close().unwrap();
-    env_1
-        .db
-        .apply_changes(ws_changes_v1.inserts, ws_changes_v1.deletes);
+    env_1.db.apply_changes(ws_changes_v1.clone());
     // check
-    let mut new_ws_v1 =
-        WorldState::<DummyStorage, V1>::open(&env_1.db, ws_changes_v1.new_root_hash);
+    let mut new_ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, ws_changes_v1.new_root_hash);
     // iter account trie
     let account_iter = new_ws_v1.account_trie().all().unwrap();
     account_iter.into_iter().for_each(|(key, value)| {
@@ -489,8 +511,8 @@ pub fn iter() {
 
     //================ Version2 ================
     // init trie
-    let mut env_2 = TestEnvWithSeveralAccounts::default();
-    let mut genesis_ws_v2 = WorldState::<DummyStorage, V2>::new(&env_2.db);
+    let env_2 = TestEnvWithSeveralAccounts::default();
+    let mut genesis_ws_v2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
     // update account info
     let account_trie_mut_ref = genesis_ws_v2.account_trie_mut();
     account_trie_mut_ref
@@ -529,12 +551,9 @@ pub fn iter() {
         .unwrap();
     // close change
     let ws_changes_v2 = genesis_ws_v2.close().unwrap();
-    env_2
-        .db
-        .apply_changes(ws_changes_v2.inserts, ws_changes_v2.deletes);
+    env_2.db.apply_changes(ws_changes_v2.clone());
     // check
-    let mut new_ws_v2 =
-        WorldState::<DummyStorage, V2>::open(&env_2.db, ws_changes_v2.new_root_hash);
+    let mut new_ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, ws_changes_v2.new_root_hash);
     // iter account trie
     let account_iter = new_ws_v2.account_trie().all();
     account_iter.unwrap().into_iter().for_each(|(key, value)| {
@@ -573,8 +592,8 @@ pub fn search_with_proof() {
     let code_str = "Hello world";
     let code_vec = code_str.as_bytes().to_vec();
     //================ Version1 ================
-    let mut env_1 = TestEnv::default();
-    let mut genesis_ws_v1 = WorldState::<DummyStorage, V1>::new(&env_1.db);
+    let env_1 = TestEnv::default();
+    let mut genesis_ws_v1 = WorldState::<MemoryDB, V1>::new(&env_1.db);
     let account_trie_mut_ref = genesis_ws_v1.account_trie_mut();
     account_trie_mut_ref.set_nonce(&env_1.address, 1).unwrap();
     account_trie_mut_ref
@@ -591,10 +610,8 @@ pub fn search_with_proof() {
         .set(&key_apple, value_apple.clone())
         .unwrap();
     let ws_change_v1 = genesis_ws_v1.close().unwrap();
-    env_1
-        .db
-        .apply_changes(ws_change_v1.inserts, ws_change_v1.deletes);
-    let mut new_ws_v1 = WorldState::<DummyStorage, V1>::open(&env_1.db, ws_change_v1.new_root_hash);
+    env_1.db.apply_changes(ws_change_v1.clone());
+    let mut new_ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, ws_change_v1.new_root_hash);
     let account_trie_unmut_ref = new_ws_v1.account_trie();
     println!(
         "proof: {:?}",
@@ -657,8 +674,8 @@ pub fn search_with_proof() {
         value_apple
     );
     //================ Version2 ================.
-    let mut env_2 = TestEnv::default();
-    let mut genesis_ws_v2 = WorldState::<DummyStorage, V2>::new(&env_2.db);
+    let env_2 = TestEnv::default();
+    let mut genesis_ws_v2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
     let account_trie_mut_ref = genesis_ws_v2.account_trie_mut();
     account_trie_mut_ref.set_nonce(&env_2.address, 1).unwrap();
     account_trie_mut_ref
@@ -675,10 +692,8 @@ pub fn search_with_proof() {
         .set(&key_apple, value_apple.clone())
         .unwrap();
     let ws_change_v2 = genesis_ws_v2.close().unwrap();
-    env_2
-        .db
-        .apply_changes(ws_change_v2.inserts, ws_change_v2.deletes);
-    let mut new_ws_v2 = WorldState::<DummyStorage, V2>::open(&env_2.db, ws_change_v2.new_root_hash);
+    env_2.db.apply_changes(ws_change_v2.clone());
+    let mut new_ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, ws_change_v2.new_root_hash);
     let account_trie_unmut_ref = new_ws_v2.account_trie();
     println!(
         "proof: {:?}",
@@ -764,8 +779,8 @@ pub fn upgrade() {
     let value_banana: Value = b"12345".to_vec();
 
     // init account trie with 2 accounts
-    let mut env = TestEnvWithSeveralAccounts::default();
-    let mut ws_1 = WorldState::<DummyStorage, V1>::new(&env.db);
+    let env = TestEnvWithSeveralAccounts::default();
+    let mut ws_1 = WorldState::<MemoryDB, V1>::new(&env.db);
     // account 1
     let account_trie_mut_ref = ws_1.account_trie_mut();
     account_trie_mut_ref
@@ -805,10 +820,9 @@ pub fn upgrade() {
         .unwrap();
     // close changes
     let ws_change_1 = ws_1.close().unwrap();
-    env.db
-        .apply_changes(ws_change_1.inserts, ws_change_1.deletes);
+    env.db.apply_changes(ws_change_1.clone());
     // iter the AccountTrie and StorageTrie
-    let mut new_ws_1 = WorldState::<DummyStorage, V1>::open(&env.db, ws_change_1.new_root_hash);
+    let mut new_ws_1 = WorldState::<MemoryDB, V1>::open(&env.db, ws_change_1.new_root_hash);
     let accounts_trie_ref = new_ws_1.account_trie();
     println!("=======================iter account trie after insertion==========================");
     accounts_trie_ref
@@ -837,10 +851,9 @@ pub fn upgrade() {
     let mut ws_2 = new_ws_1.upgrade().unwrap();
 
     let ws_2_changes = ws_2.close().unwrap();
-    env.db
-        .apply_changes(ws_2_changes.inserts, ws_2_changes.deletes);
+    env.db.apply_changes(ws_2_changes.clone());
     // open ws_2
-    let mut ws_2 = WorldState::<DummyStorage, V2>::open(&env.db, ws_2_changes.new_root_hash);
+    let mut ws_2 = WorldState::<MemoryDB, V2>::open(&env.db, ws_2_changes.new_root_hash);
     let accounts_trie_ref = ws_2.account_trie();
     println!("=======================iter account trie after upgrade==========================");
     accounts_trie_ref
@@ -874,8 +887,8 @@ pub fn remove_storage_info() {
 
     //================ Version1 ================
     // init trie
-    let mut env_1 = TestEnv::default();
-    let mut genesis_ws_v1 = WorldState::<DummyStorage, V1>::new(&env_1.db);
+    let env_1 = TestEnv::default();
+    let mut genesis_ws_v1 = WorldState::<MemoryDB, V1>::new(&env_1.db);
     let storage_trie_mut_ref = genesis_ws_v1.storage_trie_mut(&env_1.address).unwrap();
     storage_trie_mut_ref
         .set(&key_apple, value_apple.clone())
@@ -885,12 +898,9 @@ pub fn remove_storage_info() {
         .unwrap();
     // close change
     let ws_changes_v1 = genesis_ws_v1.close().unwrap();
-    env_1
-        .db
-        .apply_changes(ws_changes_v1.inserts, ws_changes_v1.deletes);
+    env_1.db.apply_changes(ws_changes_v1.clone());
     // check
-    let mut new_ws_v1 =
-        WorldState::<DummyStorage, V1>::open(&env_1.db, ws_changes_v1.new_root_hash);
+    let mut new_ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, ws_changes_v1.new_root_hash);
     println!("======Account info=======");
     new_ws_v1
         .account_trie()
@@ -917,12 +927,10 @@ pub fn remove_storage_info() {
         .remove_trie()
         .unwrap();
     let ws_changes_v1 = new_ws_v1.close().unwrap();
-    env_1
-        .db
-        .apply_changes(ws_changes_v1.inserts, ws_changes_v1.deletes);
+    env_1.db.apply_changes(ws_changes_v1.clone());
     // check
     let mut ws_after_delete_v1 =
-        WorldState::<DummyStorage, V1>::open(&env_1.db, ws_changes_v1.new_root_hash);
+        WorldState::<MemoryDB, V1>::open(&env_1.db, ws_changes_v1.new_root_hash);
     println!("======Account info=======");
     ws_after_delete_v1
         .account_trie()
@@ -936,8 +944,8 @@ pub fn remove_storage_info() {
     assert!(!storage_trie_ref.contains(&key_banana).unwrap());
     //================ Version2 ================
     // init trie
-    let mut env_2 = TestEnv::default();
-    let mut genesis_ws_v2 = WorldState::<DummyStorage, V2>::new(&env_2.db);
+    let env_2 = TestEnv::default();
+    let mut genesis_ws_v2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
     let storage_trie_mut_ref = genesis_ws_v2.storage_trie_mut(&env_2.address).unwrap();
     storage_trie_mut_ref
         .set(&key_apple, value_apple.clone())
@@ -947,12 +955,9 @@ pub fn remove_storage_info() {
         .unwrap();
     // close change
     let ws_changes_v2 = genesis_ws_v2.close().unwrap();
-    env_2
-        .db
-        .apply_changes(ws_changes_v2.inserts, ws_changes_v2.deletes);
+    env_2.db.apply_changes(ws_changes_v2.clone());
     // check
-    let mut new_ws_v2 =
-        WorldState::<DummyStorage, V2>::open(&env_2.db, ws_changes_v2.new_root_hash);
+    let mut new_ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, ws_changes_v2.new_root_hash);
     println!("======Account info=======");
     new_ws_v2
         .account_trie()
@@ -976,12 +981,10 @@ pub fn remove_storage_info() {
         .remove_trie()
         .unwrap();
     let ws_changes_v2 = new_ws_v2.close().unwrap();
-    env_2
-        .db
-        .apply_changes(ws_changes_v2.inserts, ws_changes_v2.deletes);
+    env_2.db.apply_changes(ws_changes_v2.clone());
     // check
     let mut ws_after_delete_v2 =
-        WorldState::<DummyStorage, V2>::open(&env_2.db, ws_changes_v2.new_root_hash);
+        WorldState::<MemoryDB, V2>::open(&env_2.db, ws_changes_v2.new_root_hash);
     println!("======Account info=======");
     ws_after_delete_v2
         .account_trie()
@@ -995,6 +998,3283 @@ pub fn remove_storage_info() {
     assert!(!storage_trie_ref.contains(&key_banana).unwrap());
 }
 
+#[test]
+pub fn memory_db() {
+    let env = TestEnv::default();
+    let mut ws = WorldState::<MemoryDB, V2>::new(&env.db);
+    ws.account_trie_mut().set_nonce(&env.address, 1).unwrap();
+    let ws_changes = ws.close().unwrap();
+    assert!(env.db.is_empty());
+    // clone shares the underlying storage
+    let db_handle = env.db.clone();
+    env.db.apply_changes(ws_changes.clone());
+    assert_eq!(db_handle.len(), ws_changes.inserts.len());
+    let stats = env.db.stats();
+    assert_eq!(stats.num_keys, ws_changes.inserts.len());
+    assert_eq!(
+        stats.total_bytes(),
+        ws_changes
+            .inserts
+            .iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum::<usize>()
+    );
+    // snapshot is not affected by later changes
+    let snapshot = env.db.snapshot();
+    let mut ws = WorldState::<MemoryDB, V2>::open(&env.db, ws_changes.new_root_hash);
+    ws.account_trie_mut().set_nonce(&env.address, 2).unwrap();
+    let ws_changes_2 = ws.close().unwrap();
+    env.db.apply_changes(ws_changes_2.clone());
+    for key in ws_changes_2.deletes.iter() {
+        assert_eq!(env.db.get(key), None);
+    }
+    let ws_snapshot = WorldState::<MemoryDB, V2>::open(&snapshot, ws_changes.new_root_hash);
+    assert_eq!(ws_snapshot.account_trie().nonce(&env.address).unwrap(), 1);
+    assert_eq!(snapshot.len(), ws_changes.inserts.len());
+    let ws_new = WorldState::<MemoryDB, V2>::open(&db_handle, ws_changes_2.new_root_hash);
+    assert_eq!(ws_new.account_trie().nonce(&env.address).unwrap(), 2);
+}
+
+#[test]
+pub fn commit() {
+    let key_apple: Key = b"apple".to_vec();
+    let value_apple: Value = b"1234".to_vec();
+    // close and apply changes by caller
+    let env_1 = TestEnv::default();
+    let mut ws_1 = WorldState::<MemoryDB, V2>::new(&env_1.db);
+    ws_1.account_trie_mut()
+        .set_nonce(&env_1.address, 1)
+        .unwrap();
+    ws_1.storage_trie_mut(&env_1.address)
+        .unwrap()
+        .set(&key_apple, value_apple.clone())
+        .unwrap();
+    let ws_changes = ws_1.close().unwrap();
+    env_1.db.apply_changes(ws_changes.clone());
+    // commit by WorldState
+    let env_2 = TestEnv::default();
+    let mut ws_2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
+    ws_2.account_trie_mut()
+        .set_nonce(&env_2.address, 1)
+        .unwrap();
+    ws_2.storage_trie_mut(&env_2.address)
+        .unwrap()
+        .set(&key_apple, value_apple.clone())
+        .unwrap();
+    let new_root_hash = ws_2.commit().unwrap();
+    assert_eq!(new_root_hash, ws_changes.new_root_hash);
+    assert_eq!(env_2.db.len(), env_1.db.len());
+    // WorldState keeps working on the committed state
+    assert_eq!(ws_2.close().unwrap().new_root_hash, new_root_hash);
+    let mut ws = WorldState::<MemoryDB, V2>::open(&env_2.db, new_root_hash);
+    assert_eq!(ws.account_trie().nonce(&env_2.address).unwrap(), 1);
+    assert_eq!(
+        ws.storage_trie(&env_2.address)
+            .unwrap()
+            .get(&key_apple)
+            .unwrap(),
+        Some(value_apple)
+    );
+}
+
+/// `UnreadableDB` fails every read after `fail` is set, to simulate I/O error of the database
+#[derive(Debug, Clone)]
+struct UnreadableDB {
+    db: MemoryDB,
+    fail: Arc<AtomicBool>,
+}
+
+impl DB for UnreadableDB {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.db.get(key)
+    }
+
+    fn try_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DbError> {
+        if self.fail.load(Ordering::SeqCst) {
+            return Err(DbError::ReadError("disk failure".to_string()));
+        }
+        Ok(self.db.get(key))
+    }
+}
+
+impl DBWrite for UnreadableDB {
+    fn write(&self, batch: WriteBatch) -> Result<(), DbError> {
+        self.db.write(batch)
+    }
+}
+
+#[test]
+pub fn read_error() {
+    let env = TestEnv::default();
+    let mut ws = WorldState::<MemoryDB, V2>::new(&env.db);
+    ws.account_trie_mut().set_nonce(&env.address, 1).unwrap();
+    ws.storage_trie_mut(&env.address)
+        .unwrap()
+        .set(&b"apple".to_vec(), b"1234".to_vec())
+        .unwrap();
+    let new_root_hash = ws.commit().unwrap();
+
+    let db = UnreadableDB {
+        db: env.db.clone(),
+        fail: Arc::new(AtomicBool::new(false)),
+    };
+    let mut ws = WorldState::<UnreadableDB, V2>::open(&db, new_root_hash);
+    assert_eq!(ws.account_trie().nonce(&env.address).unwrap(), 1);
+    db.fail.store(true, Ordering::SeqCst);
+    let expected_error = MptError::DbError(DbError::ReadError("disk failure".to_string()));
+    assert_eq!(
+        ws.account_trie().nonce(&env.address).unwrap_err(),
+        expected_error
+    );
+    assert_eq!(
+        ws.account_trie_mut()
+            .set_balance(&env.address, 100)
+            .unwrap_err(),
+        expected_error
+    );
+    assert!(matches!(
+        ws.storage_trie(&env.address),
+        Err(MptError::DbError(DbError::ReadError(_)))
+    ));
+    // a missing trie node is still reported as incomplete database
+    db.fail.store(false, Ordering::SeqCst);
+    let ws = WorldState::<UnreadableDB, V2>::open(&db, [1u8; 32]);
+    assert_eq!(
+        ws.account_trie().nonce(&env.address).unwrap_err(),
+        MptError::InvalidStateRoot
+    );
+
+    // error from DB::try_get during upgrade
+    let env = TestEnv::default();
+    let mut ws = WorldState::<MemoryDB, V1>::new(&env.db);
+    ws.account_trie_mut()
+        .set_balance(&env.address, 100)
+        .unwrap();
+    let new_root_hash = ws.commit().unwrap();
+    let db = UnreadableDB {
+        db: env.db.clone(),
+        fail: Arc::new(AtomicBool::new(true)),
+    };
+    let ws = WorldState::<UnreadableDB, V1>::open(&db, new_root_hash);
+    assert!(matches!(
+        ws.upgrade(),
+        Err(WorldStateError::MptError(MptError::DbError(
+            DbError::ReadError(_)
+        )))
+    ));
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+pub fn rocks_db() {
+    let key_apple: Key = b"apple".to_vec();
+    let value_apple: Value = b"1234".to_vec();
+    let address = TestEnv::default().address;
+    let db_dir = temp_dir::TempDir::new().unwrap();
+    let path = db_dir.child("rocks_db");
+    let new_root_hash = {
+        let db = RocksDB::open_with_column_families(&path).unwrap();
+        let mut ws = WorldState::<RocksDB, V2>::new(&db);
+        ws.account_trie_mut().set_balance(&address, 100).unwrap();
+        ws.storage_trie_mut(&address)
+            .unwrap()
+            .set(&key_apple, value_apple.clone())
+            .unwrap();
+        let ws_changes = ws.close().unwrap();
+        db.apply_changes(ws_changes.clone()).unwrap();
+        // keys are stored in the column family of their keyspace
+        for key in ws_changes.inserts.keys() {
+            let cf_name = match key[0] {
+                0 => ACCOUNTS_COLUMN_FAMILY,
+                _ => STORAGE_COLUMN_FAMILY,
+            };
+            let cf = db.inner().cf_handle(cf_name).unwrap();
+            assert!(db.inner().get_cf(&cf, key).unwrap().is_some());
+            assert!(db.inner().get(key).unwrap().is_none());
+        }
+        ws_changes.new_root_hash
+    };
+    // reopen the database
+    let db = RocksDB::open_with_column_families(&path).unwrap();
+    let mut ws = WorldState::<RocksDB, V2>::open(&db, new_root_hash);
+    assert_eq!(ws.account_trie().balance(&address).unwrap(), 100);
+    let account = ws.account_trie().account(&address).unwrap().unwrap();
+    assert_eq!(account.balance, 100);
+    assert!(account.storage_hash().is_some());
+    assert_eq!(
+        ws.storage_trie(&address).unwrap().get(&key_apple).unwrap(),
+        Some(value_apple)
+    );
+    ws.account_trie_mut().set_balance(&address, 200).unwrap();
+    let new_root_hash = ws.commit().unwrap();
+    let ws = WorldState::<RocksDB, V2>::open(&db, new_root_hash);
+    assert_eq!(ws.account_trie().balance(&address).unwrap(), 200);
+}
+
+#[test]
+pub fn batch_read() {
+    let env = TestEnvWithSeveralAccounts::default();
+    let keys: Vec<Key> = (0..10_u8).map(|i| vec![i; i as usize + 1]).collect();
+    let mut ws = WorldState::<MemoryDB, V1>::new(&env.db);
+    ws.account_trie_mut()
+        .set_nonce(&env.addresses[0], 1)
+        .unwrap();
+    ws.account_trie_mut()
+        .set_balance(&env.addresses[0], 100)
+        .unwrap();
+    ws.account_trie_mut()
+        .set_code(&env.addresses[0], b"code".to_vec())
+        .unwrap();
+    ws.account_trie_mut()
+        .set_cbi_version(&env.addresses[0], 2)
+        .unwrap();
+    let storage_trie = ws.storage_trie_mut(&env.addresses[0]).unwrap();
+    for key in keys.iter().step_by(2) {
+        storage_trie.set(key, key.repeat(2)).unwrap();
+    }
+    let new_root_hash = ws.commit().unwrap();
+
+    let mut ws = WorldState::<MemoryDB, V1>::open(&env.db, new_root_hash);
+    let account = ws
+        .account_trie()
+        .account(&env.addresses[0])
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.nonce, 1);
+    assert_eq!(account.balance, 100);
+    assert_eq!(account.code, b"code".to_vec());
+    assert_eq!(account.cbi_version, Some(2));
+    assert_eq!(
+        account.storage_hash(),
+        ws.account_trie().storage_hash(&env.addresses[0]).unwrap()
+    );
+    assert!(ws
+        .account_trie()
+        .account(&env.addresses[1])
+        .unwrap()
+        .is_none());
+
+    let values = ws
+        .storage_trie(&env.addresses[0])
+        .unwrap()
+        .batch_get(&keys)
+        .unwrap();
+    for (i, (key, value)) in keys.iter().zip(values).enumerate() {
+        match i % 2 {
+            0 => assert_eq!(value, Some(key.repeat(2))),
+            _ => assert_eq!(value, None),
+        }
+    }
+}
+
+#[test]
+pub fn node_cache() {
+    let env = TestEnv::default();
+    let key_apple: Key = b"apple".to_vec();
+    let value_apple: Value = b"1234".to_vec();
+    let mut ws = WorldState::<MemoryDB, V2>::new(&env.db);
+    ws.account_trie_mut()
+        .set_balance(&env.address, 100)
+        .unwrap();
+    ws.storage_trie_mut(&env.address)
+        .unwrap()
+        .set(&key_apple, value_apple.clone())
+        .unwrap();
+    let root_hash = ws.commit().unwrap();
+
+    let db = UnreadableDB {
+        db: env.db.clone(),
+        fail: Arc::new(AtomicBool::new(false)),
+    };
+    let node_cache = NodeCache::new(1024 * 1024);
+    let mut ws = WorldState::<UnreadableDB, V2>::open(&db, root_hash);
+    ws.set_node_cache(node_cache.clone());
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 100);
+    assert_eq!(
+        ws.storage_trie(&env.address)
+            .unwrap()
+            .get(&key_apple)
+            .unwrap(),
+        Some(value_apple.clone())
+    );
+    let stats = node_cache.stats();
+    assert!(stats.misses > 0);
+    assert_eq!(stats.num_nodes as u64, stats.misses);
+
+    // nodes are read from the cache by another WorldState sharing the cache
+    db.fail.store(true, Ordering::SeqCst);
+    let mut ws = WorldState::<UnreadableDB, V2>::open(&db, root_hash);
+    ws.set_node_cache(node_cache.clone());
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 100);
+    assert_eq!(
+        ws.storage_trie(&env.address)
+            .unwrap()
+            .get(&key_apple)
+            .unwrap(),
+        Some(value_apple)
+    );
+    assert_eq!(node_cache.stats().misses, stats.misses);
+    assert!(node_cache.stats().hits > 0);
+
+    // commit invalidates the changed nodes in the cache
+    db.fail.store(false, Ordering::SeqCst);
+    ws.account_trie_mut()
+        .set_balance(&env.address, 200)
+        .unwrap();
+    let ws_changes = ws.close().unwrap();
+    let cached_keys: Vec<Vec<u8>> = ws_changes
+        .inserts
+        .keys()
+        .chain(ws_changes.deletes.iter())
+        .filter(|key| node_cache.get(key).is_some())
+        .cloned()
+        .collect();
+    assert!(!cached_keys.is_empty());
+    node_cache.apply_changes(&db, ws_changes.clone()).unwrap();
+    for key in cached_keys {
+        assert!(node_cache.get(&key).is_none());
+    }
+    let mut ws = WorldState::<UnreadableDB, V2>::open(&db, ws_changes.new_root_hash);
+    ws.set_node_cache(node_cache.clone());
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 200);
+    ws.account_trie_mut()
+        .set_balance(&env.address, 300)
+        .unwrap();
+    let root_hash = ws.commit().unwrap();
+    let ws = WorldState::<UnreadableDB, V2>::open(&db, root_hash);
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 300);
+
+    // pruning invalidates the deleted trie nodes of the pruned roots
+    let db = MemoryDB::new();
+    let mut pruner = Pruner::new(&db);
+    pruner.set_node_cache(node_cache.clone());
+    let mut roots = Vec::new();
+    let mut ws = WorldState::<MemoryDB, V2>::new(&db);
+    for balance in 1..=3_u64 {
+        ws.account_trie_mut()
+            .set_balance(&env.address, balance)
+            .unwrap();
+        let ws_changes = ws.close().unwrap();
+        roots.push(ws_changes.new_root_hash);
+        pruner.commit(ws_changes).unwrap();
+        ws = WorldState::<MemoryDB, V2>::open(&db, *roots.last().unwrap());
+    }
+    let mut ws = WorldState::<MemoryDB, V2>::open(&db, roots[0]);
+    ws.set_node_cache(node_cache.clone());
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 1);
+    assert_eq!(pruner.prune(1).unwrap().pruned_roots, 2);
+    let mut ws = WorldState::<MemoryDB, V2>::open(&db, roots[0]);
+    ws.set_node_cache(node_cache.clone());
+    assert!(ws.account_trie().balance(&env.address).is_err());
+    let mut ws = WorldState::<MemoryDB, V2>::open(&db, roots[2]);
+    ws.set_node_cache(node_cache);
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 3);
+}
+
+#[test]
+pub fn pruning() {
+    let env = TestEnv::default();
+    let key_apple: Key = b"apple".to_vec();
+    let pruner = Pruner::new(&env.db);
+    // ================ Version1 ================
+    // the state of era 2 is the same as era 0, so its trie nodes are inserted again after being removed by era 1
+    let balances = [1_u64, 2, 1, 3];
+    let mut roots = Vec::new();
+    let mut ws = WorldState::<MemoryDB, V1>::new(&env.db);
+    for balance in balances {
+        ws.account_trie_mut()
+            .set_balance(&env.address, balance)
+            .unwrap();
+        ws.storage_trie_mut(&env.address)
+            .unwrap()
+            .set(&key_apple, balance.to_le_bytes().to_vec())
+            .unwrap();
+        let ws_changes = ws.close().unwrap();
+        roots.push(ws_changes.new_root_hash);
+        pruner.commit(ws_changes).unwrap();
+        ws = WorldState::<MemoryDB, V1>::open(&env.db, *roots.last().unwrap());
+    }
+    assert_eq!(roots[0], roots[2]);
+    assert_eq!(pruner.roots().unwrap(), roots);
+
+    let num_keys = env.db.len();
+    let stats = pruner.prune(2).unwrap();
+    assert_eq!(stats.pruned_roots, 2);
+    assert!(stats.deleted_nodes > 0);
+    assert!(env.db.len() < num_keys);
+    assert_eq!(pruner.roots().unwrap(), roots[2..].to_vec());
+    // pruning again without new roots changes nothing
+    assert_eq!(pruner.prune(2).unwrap(), PruningStats::default());
+
+    for (root_hash, balance) in roots[2..].iter().zip(&balances[2..]) {
+        let mut ws = WorldState::<MemoryDB, V1>::open(&env.db, *root_hash);
+        assert_eq!(ws.account_trie().balance(&env.address).unwrap(), *balance);
+        assert_eq!(
+            ws.storage_trie(&env.address)
+                .unwrap()
+                .get(&key_apple)
+                .unwrap(),
+            Some(balance.to_le_bytes().to_vec())
+        );
+    }
+    let ws = WorldState::<MemoryDB, V1>::open(&env.db, roots[1]);
+    assert!(ws.account_trie().balance(&env.address).is_err());
+
+    // ================ Version2 ================
+    let ws = WorldState::<MemoryDB, V1>::open(&env.db, roots[3]);
+    let mut ws = ws.upgrade().unwrap();
+    ws.account_trie_mut().set_balance(&env.address, 4).unwrap();
+    let ws_changes = ws.close().unwrap();
+    let root_hash_v2 = ws_changes.new_root_hash;
+    pruner.commit(ws_changes).unwrap();
+    let stats = pruner.prune(1).unwrap();
+    assert_eq!(stats.pruned_roots, 2);
+    assert_eq!(pruner.roots().unwrap(), vec![root_hash_v2]);
+
+    let mut ws = WorldState::<MemoryDB, V2>::open(&env.db, root_hash_v2);
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 4);
+    assert_eq!(
+        ws.storage_trie(&env.address)
+            .unwrap()
+            .get(&key_apple)
+            .unwrap(),
+        Some(3_u64.to_le_bytes().to_vec())
+    );
+    let ws = WorldState::<MemoryDB, V1>::open(&env.db, roots[3]);
+    assert!(ws.account_trie().balance(&env.address).is_err());
+}
+
+#[test]
+pub fn state_root_registry() {
+    let env = TestEnv::default();
+    let registry = StateRootRegistry::new(&env.db);
+    assert!(registry.latest().unwrap().is_none());
+    assert!(registry.range(..).unwrap().next().is_none());
+    // archive style commit which keeps the trie nodes of previous state roots
+    let archive = |ws_changes: WorldStateChanges, height: u64, version: Version| {
+        let mut batch = WriteBatch::new();
+        batch.inserts = ws_changes.inserts;
+        env.db.write(batch).unwrap();
+        registry
+            .record(StateRootEntry {
+                height,
+                root_hash: ws_changes.new_root_hash,
+                version,
+            })
+            .unwrap();
+        ws_changes.new_root_hash
+    };
+
+    let mut ws = WorldState::<MemoryDB, V1>::new(&env.db);
+    ws.account_trie_mut()
+        .set_balance(&env.address, 100)
+        .unwrap();
+    let root_hash_1 = ws.commit_at_height(&registry, 1).unwrap();
+    ws.account_trie_mut()
+        .set_balance(&env.address, 200)
+        .unwrap();
+    let root_hash_2 = archive(ws.close().unwrap(), 2, Version::V1);
+    // the upgrade deletes the V1 trie nodes, which are kept by commit_at_height
+    let mut ws = WorldState::<MemoryDB, V1>::open(&env.db, root_hash_2)
+        .upgrade()
+        .unwrap();
+    let root_hash_3 = ws.commit_at_height(&registry, 3).unwrap();
+    let mut ws = WorldState::<MemoryDB, V2>::open(&env.db, root_hash_3);
+    ws.account_trie_mut()
+        .set_balance(&env.address, 300)
+        .unwrap();
+    ws.commit_at_height(&registry, 5).unwrap();
+    ws.account_trie_mut()
+        .set_balance(&env.address, 400)
+        .unwrap();
+    ws.commit_at_height(&registry, 6).unwrap();
+
+    assert_eq!(
+        registry.get(1).unwrap(),
+        Some(StateRootEntry {
+            height: 1,
+            root_hash: root_hash_1,
+            version: Version::V1
+        })
+    );
+    assert!(registry.get(4).unwrap().is_none());
+    assert_eq!(registry.latest().unwrap().unwrap().height, 6);
+    let heights = |range: StateRootRange<MemoryDB>| -> Vec<u64> {
+        range.map(|entry| entry.unwrap().height).collect()
+    };
+    assert_eq!(heights(registry.range(..).unwrap()), vec![1, 2, 3, 5, 6]);
+    assert_eq!(heights(registry.range(2..).unwrap()), vec![2, 3, 5, 6]);
+    assert_eq!(heights(registry.range(..=2).unwrap()), vec![1, 2]);
+    assert_eq!(heights(registry.range(3..5).unwrap()), vec![3]);
+    assert!(registry.range(4..5).unwrap().next().is_none());
+    assert!(registry.range(..0).unwrap().next().is_none());
+
+    let ws = registry.open_at_height::<V1>(1).unwrap();
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 100);
+    let ws = registry.open_at_height::<V1>(2).unwrap();
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 200);
+    let ws = registry.open_at_height::<V2>(3).unwrap();
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 200);
+    let ws = registry.open_at_height::<V2>(5).unwrap();
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 300);
+    let ws = registry.open_at_height::<V2>(6).unwrap();
+    assert_eq!(ws.account_trie().balance(&env.address).unwrap(), 400);
+    assert!(matches!(
+        registry.open_at_height::<V2>(2),
+        Err(WorldStateError::StateRootRegistryError(
+            StateRootRegistryError::VersionMismatch
+        ))
+    ));
+    assert!(matches!(
+        registry.open_at_height::<V2>(4),
+        Err(WorldStateError::StateRootRegistryError(
+            StateRootRegistryError::UnknownHeight(4)
+        ))
+    ));
+
+    // the block heights are read lazily in chunks across a gap of several chunks
+    let height = 6 + 3 * RANGE_CHUNK_SIZE + 1;
+    registry
+        .record(StateRootEntry {
+            height,
+            root_hash: root_hash_1,
+            version: Version::V1,
+        })
+        .unwrap();
+    assert_eq!(heights(registry.range(5..).unwrap()), vec![5, 6, height]);
+    assert_eq!(
+        heights(registry.range(7..height).unwrap()),
+        Vec::<u64>::new()
+    );
+    let mut range = registry.range(..).unwrap();
+    assert_eq!(range.next().unwrap().unwrap().height, 1);
+    assert_eq!(heights(range), vec![2, 3, 5, 6, height]);
+}
+
+#[test]
+pub fn lazy_iter() {
+    let env = TestEnvWithSeveralAccounts::default();
+    let keys: Vec<Key> = [b"a".as_slice(), b"ab", b"abc", b"b", b"ba", b"c"]
+        .iter()
+        .map(|key| key.to_vec())
+        .collect();
+    let mut addresses = env.addresses.clone();
+    addresses.sort();
+
+    // ================ Version1 ================
+    let mut ws = WorldState::<MemoryDB, V1>::new(&env.db);
+    for (i, address) in addresses.iter().enumerate() {
+        ws.account_trie_mut()
+            .set_balance(address, i as u64 + 1)
+            .unwrap();
+        ws.account_trie_mut().set_nonce(address, 1).unwrap();
+    }
+    let storage_trie = ws.storage_trie_mut(&addresses[0]).unwrap();
+    for key in keys.iter() {
+        storage_trie.set(key, key.repeat(2)).unwrap();
+    }
+    let root_hash = ws.commit().unwrap();
+    let mut ws = WorldState::<MemoryDB, V1>::open(&env.db, root_hash);
+    let storage_keys = |iter: StorageTrieIterator<MemoryDB, V1>| -> Vec<Key> {
+        iter.map(|item| item.unwrap().0).collect()
+    };
+    let storage_trie = ws.storage_trie(&addresses[0]).unwrap();
+    assert_eq!(storage_keys(storage_trie.iter()), keys);
+    assert_eq!(
+        storage_trie.iter().next().unwrap().unwrap(),
+        (b"a".to_vec(), b"aa".to_vec())
+    );
+    assert_eq!(storage_keys(storage_trie.iter().seek(b"aa")), keys[1..]);
+    assert_eq!(storage_keys(storage_trie.iter().prefix(b"a")), keys[..3]);
+    assert_eq!(
+        storage_keys(storage_trie.iter().prefix(b"b").seek(b"b")),
+        keys[3..5]
+    );
+
+    // paginate by resuming from the cursor
+    let mut pages: Vec<Vec<Key>> = Vec::new();
+    let mut cursor: Option<TrieCursor> = None;
+    loop {
+        let mut iter = storage_trie.iter();
+        if let Some(cursor) = &cursor {
+            let cursor = TrieCursor::from_bytes(cursor.as_bytes().to_vec());
+            iter = iter.resume(&cursor);
+        }
+        let page: Vec<Key> = iter.by_ref().take(4).map(|item| item.unwrap().0).collect();
+        if page.is_empty() {
+            break;
+        }
+        cursor = iter.cursor();
+        pages.push(page);
+    }
+    assert_eq!(pages, vec![keys[..4].to_vec(), keys[4..].to_vec()]);
+
+    let fields: Vec<(PublicAddress, AccountField)> = ws
+        .account_trie()
+        .iter()
+        .map(|item| {
+            let (address, field, _) = item.unwrap();
+            (address, field)
+        })
+        .filter(|(_, field)| *field != AccountField::StorageHash)
+        .collect();
+    assert_eq!(
+        fields,
+        vec![
+            (addresses[0], AccountField::Nonce),
+            (addresses[0], AccountField::Balance),
+            (addresses[1], AccountField::Nonce),
+            (addresses[1], AccountField::Balance),
+        ]
+    );
+
+    // ================ Version2 ================
+    let ws = WorldState::<MemoryDB, V1>::open(&env.db, root_hash);
+    let mut ws = ws.upgrade().unwrap();
+    let storage_trie = ws.storage_trie(&addresses[0]).unwrap();
+    let items: Vec<(Key, Value)> = storage_trie
+        .iter()
+        .prefix(b"ab")
+        .map(|item| item.unwrap())
+        .collect();
+    assert_eq!(
+        items,
+        vec![
+            (b"ab".to_vec(), b"abab".to_vec()),
+            (b"abc".to_vec(), b"abcabc".to_vec())
+        ]
+    );
+    let mut iter = ws.account_trie().iter().seek(&addresses[1]);
+    let (address, field, value) = iter.next().unwrap().unwrap();
+    assert_eq!(address, addresses[1]);
+    assert_eq!(field, AccountField::Nonce);
+    assert_eq!(value, 1_u64.to_le_bytes().to_vec());
+    let balances: Vec<u64> = ws
+        .account_trie()
+        .iter()
+        .resume(&iter.cursor().unwrap())
+        .filter_map(|item| match item.unwrap() {
+            (_, AccountField::Balance, value) => {
+                Some(u64::from_le_bytes(value.try_into().unwrap()))
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(balances, vec![2]);
+    let num_fields = ws.account_trie().iter().prefix(&addresses[0][..1]).count();
+    assert!(num_fields >= 2);
+}
+
+#[test]
+pub fn accounts_page() {
+    let db = MemoryDB::new();
+    let addresses: Vec<PublicAddress> = (1..=5_u8).map(|i| [i; 32]).collect();
+    // write account fields directly into the trie, with a malformed balance of the third account
+    let mut mpt =
+        Mpt::<MemoryDB, V2>::unsafe_new(db::KeyInstrumentedDB::unsafe_new(&db, Vec::new()));
+    let mut data = std::collections::HashMap::new();
+    for (i, address) in addresses.iter().enumerate() {
+        let balance = match i {
+            2 => vec![1, 2, 3],
+            _ => (i as u64 * 100).to_le_bytes().to_vec(),
+        };
+        data.insert(
+            [address.as_slice(), &[0]].concat(),
+            (i as u64).to_le_bytes().to_vec(),
+        );
+        data.insert([address.as_slice(), &[1]].concat(), balance);
+    }
+    mpt.batch_set(&data).unwrap();
+    let ws_changes = WorldStateChanges::from(mpt.close());
+    db.apply_changes(ws_changes.clone());
+
+    let mut ws = WorldState::<MemoryDB, V2>::open(&db, ws_changes.new_root_hash);
+    let accounts: Vec<(PublicAddress, Result<Account, WorldStateError>)> = ws
+        .account_trie()
+        .accounts()
+        .map(|item| item.unwrap())
+        .collect();
+    assert_eq!(accounts.len(), 5);
+    for (i, (address, account)) in accounts.into_iter().enumerate() {
+        assert_eq!(address, addresses[i]);
+        match i {
+            2 => assert!(matches!(
+                account,
+                Err(WorldStateError::DecodeOrEncodeError(
+                    DecodeOrEncodeError::DecodeError
+                ))
+            )),
+            _ => assert_eq!(account.unwrap().balance, i as u64 * 100),
+        }
+    }
+    assert!(ws.account_trie().all().is_err());
+
+    let page = ws.account_trie().accounts_page(None, 2).unwrap();
+    let page_addresses: Vec<PublicAddress> =
+        page.accounts.iter().map(|(address, _)| *address).collect();
+    assert_eq!(page_addresses, addresses[..2]);
+    let cursor = page.next_cursor.unwrap();
+    assert!(ws.account_trie().accounts_page(Some(&cursor), 2).is_err());
+    let resumed: Vec<(PublicAddress, bool)> = ws
+        .account_trie()
+        .accounts()
+        .resume(&cursor)
+        .map(|item| {
+            let (address, account) = item.unwrap();
+            (address, account.is_ok())
+        })
+        .collect();
+    assert_eq!(
+        resumed,
+        vec![
+            (addresses[2], false),
+            (addresses[3], true),
+            (addresses[4], true)
+        ]
+    );
+
+    // the malformed balance is a decode error in the account proof bundle
+    assert!(matches!(
+        ws.account_proof_bundle(&addresses[2], &[]),
+        Err(WorldStateError::DecodeOrEncodeError(
+            DecodeOrEncodeError::DecodeError
+        ))
+    ));
+
+    // paginate after fixing the malformed account
+    ws.account_trie_mut()
+        .set_balance(&addresses[2], 200)
+        .unwrap();
+    let root_hash = ws.commit().unwrap();
+    let ws = WorldState::<MemoryDB, V2>::open(&db, root_hash);
+    let mut pages: Vec<Vec<u64>> = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = ws.account_trie().accounts_page(cursor.as_ref(), 2).unwrap();
+        pages.push(
+            page.accounts
+                .iter()
+                .map(|(_, account)| account.balance)
+                .collect(),
+        );
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(pages, vec![vec![0, 100], vec![200, 300], vec![400]]);
+    // a page of limit 0 is empty, and its cursor resumes from the same position
+    let page = ws.account_trie().accounts_page(None, 0).unwrap();
+    assert!(page.accounts.is_empty());
+    assert!(page.next_cursor.is_some());
+    let page = ws
+        .account_trie()
+        .accounts_page(page.next_cursor.as_ref(), 3)
+        .unwrap();
+    assert_eq!(page.accounts[0].0, addresses[0]);
+    let cursor = page.next_cursor.unwrap();
+    let page = ws.account_trie().accounts_page(Some(&cursor), 0).unwrap();
+    assert!(page.accounts.is_empty());
+    assert_eq!(page.next_cursor, Some(cursor.clone()));
+    let page = ws.account_trie().accounts_page(Some(&cursor), 2).unwrap();
+    assert_eq!(page.accounts[0].1.balance, 300);
+    // no cursor after the last account
+    let account_trie = ws.account_trie();
+    let mut iter = account_trie.accounts();
+    iter.by_ref().for_each(drop);
+    let cursor = iter.cursor().unwrap();
+    assert!(ws
+        .account_trie()
+        .accounts_page(Some(&cursor), 0)
+        .unwrap()
+        .next_cursor
+        .is_none());
+    let page = ws.account_trie().accounts_page(None, 5).unwrap();
+    assert_eq!(page.accounts.len(), 5);
+    assert!(page.next_cursor.is_none());
+    let accounts = ws.account_trie().all().unwrap();
+    assert_eq!(accounts.len(), 5);
+    assert_eq!(accounts[&addresses[4]].nonce, 4);
+}
+
+/// The following tests are for the proofs of account fields and storage values.
+///
+/// They read the accounts and storage committed by [TestEnv::commit_genesis].
+#[test]
+pub fn verify_proof() {
+    let key_apple: Key = b"apple".to_vec();
+    let value_apple: Value = b"1234".to_vec();
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (root_hash_v1, addresses, _) = env_1.commit_genesis::<V1>();
+    let (contract, user) = (env_1.address, addresses[1]);
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, root_hash_v1);
+
+    let (proof, nonce) = ws_v1.account_trie().nonce_with_proof(&contract).unwrap();
+    assert!(verify_account_field_proof::<V1>(
+        &root_hash_v1,
+        &contract,
+        AccountField::Nonce,
+        &proof,
+        Some(&nonce.to_le_bytes())
+    )
+    .is_ok());
+    assert_eq!(
+        verify_account_field_proof::<V1>(
+            &root_hash_v1,
+            &contract,
+            AccountField::Nonce,
+            &proof,
+            Some(&2_u64.to_le_bytes())
+        ),
+        Err(ProofError::RootMismatch)
+    );
+    assert_eq!(
+        verify_account_field_proof::<V1>(
+            &[1; 32],
+            &contract,
+            AccountField::Nonce,
+            &proof,
+            Some(&nonce.to_le_bytes())
+        ),
+        Err(ProofError::RootMismatch)
+    );
+    let (proof, balance) = ws_v1.account_trie().balance_with_proof(&contract).unwrap();
+    assert!(verify_account_field_proof::<V1>(
+        &root_hash_v1,
+        &contract,
+        AccountField::Balance,
+        &proof,
+        Some(&balance.to_le_bytes())
+    )
+    .is_ok());
+    let (proof, code) = ws_v1.account_trie().code_with_proof(&contract).unwrap();
+    assert!(verify_account_field_proof::<V1>(
+        &root_hash_v1,
+        &contract,
+        AccountField::ContractCode,
+        &proof,
+        code.as_deref()
+    )
+    .is_ok());
+    // absent field is verified by None
+    let (proof, cbi_version) = ws_v1.account_trie().cbi_version_with_proof(&user).unwrap();
+    assert_eq!(cbi_version, None);
+    assert!(verify_account_field_proof::<V1>(
+        &root_hash_v1,
+        &user,
+        AccountField::CbiVersion,
+        &proof,
+        None
+    )
+    .is_ok());
+    assert_eq!(
+        verify_account_field_proof::<V1>(
+            &root_hash_v1,
+            &user,
+            AccountField::CbiVersion,
+            &proof,
+            Some(&1_u32.to_le_bytes())
+        ),
+        Err(ProofError::ValueMismatch)
+    );
+
+    let storage_hash = ws_v1
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    let (proof, value) = ws_v1
+        .storage_trie(&contract)
+        .unwrap()
+        .get_with_proof(&key_apple)
+        .unwrap();
+    assert_eq!(value, Some(value_apple.clone()));
+    assert!(
+        verify_storage_proof::<V1>(&storage_hash, &key_apple, &proof, value.as_deref()).is_ok()
+    );
+    assert_eq!(
+        verify_storage_proof::<V1>(&storage_hash, &key_apple, &proof, Some(b"4321")),
+        Err(ProofError::RootMismatch)
+    );
+    // storage proof is not a proof of account field
+    assert_eq!(
+        verify_account_field_proof::<V1>(
+            &storage_hash,
+            &contract,
+            AccountField::Nonce,
+            &proof,
+            None
+        ),
+        Err(ProofError::InvalidProofLevel)
+    );
+    let (proof, value) = ws_v1
+        .storage_trie(&contract)
+        .unwrap()
+        .get_with_proof(&b"banana".to_vec())
+        .unwrap();
+    assert_eq!(value, None);
+    assert!(verify_storage_proof::<V1>(&storage_hash, b"banana", &proof, None).is_ok());
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (root_hash_v2, addresses, _) = env_2.commit_genesis::<V2>();
+    let (contract, user) = (env_2.address, addresses[1]);
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, root_hash_v2);
+
+    let (proof, nonce) = ws_v2.account_trie().nonce_with_proof(&contract).unwrap();
+    assert!(verify_account_field_proof::<V2>(
+        &root_hash_v2,
+        &contract,
+        AccountField::Nonce,
+        &proof,
+        Some(&nonce.to_le_bytes())
+    )
+    .is_ok());
+    assert_eq!(
+        verify_account_field_proof::<V2>(
+            &root_hash_v2,
+            &contract,
+            AccountField::Nonce,
+            &proof,
+            Some(&2_u64.to_le_bytes())
+        ),
+        Err(ProofError::RootMismatch)
+    );
+    assert_eq!(
+        verify_account_field_proof::<V2>(
+            &[1; 32],
+            &contract,
+            AccountField::Nonce,
+            &proof,
+            Some(&nonce.to_le_bytes())
+        ),
+        Err(ProofError::RootMismatch)
+    );
+    let (proof, balance) = ws_v2.account_trie().balance_with_proof(&contract).unwrap();
+    assert!(verify_account_field_proof::<V2>(
+        &root_hash_v2,
+        &contract,
+        AccountField::Balance,
+        &proof,
+        Some(&balance.to_le_bytes())
+    )
+    .is_ok());
+    let (proof, code) = ws_v2.account_trie().code_with_proof(&contract).unwrap();
+    assert!(verify_account_field_proof::<V2>(
+        &root_hash_v2,
+        &contract,
+        AccountField::ContractCode,
+        &proof,
+        code.as_deref()
+    )
+    .is_ok());
+    // absent field is verified by None
+    let (proof, cbi_version) = ws_v2.account_trie().cbi_version_with_proof(&user).unwrap();
+    assert_eq!(cbi_version, None);
+    assert!(verify_account_field_proof::<V2>(
+        &root_hash_v2,
+        &user,
+        AccountField::CbiVersion,
+        &proof,
+        None
+    )
+    .is_ok());
+    assert_eq!(
+        verify_account_field_proof::<V2>(
+            &root_hash_v2,
+            &user,
+            AccountField::CbiVersion,
+            &proof,
+            Some(&1_u32.to_le_bytes())
+        ),
+        Err(ProofError::ValueMismatch)
+    );
+
+    let storage_hash = ws_v2
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    let (proof, value) = ws_v2
+        .storage_trie(&contract)
+        .unwrap()
+        .get_with_proof(&key_apple)
+        .unwrap();
+    assert_eq!(value, Some(value_apple.clone()));
+    assert!(
+        verify_storage_proof::<V2>(&storage_hash, &key_apple, &proof, value.as_deref()).is_ok()
+    );
+    assert_eq!(
+        verify_storage_proof::<V2>(&storage_hash, &key_apple, &proof, Some(b"4321")),
+        Err(ProofError::RootMismatch)
+    );
+    // storage proof is not a proof of account field
+    assert_eq!(
+        verify_account_field_proof::<V2>(
+            &storage_hash,
+            &contract,
+            AccountField::Nonce,
+            &proof,
+            None
+        ),
+        Err(ProofError::InvalidProofLevel)
+    );
+    let (proof, value) = ws_v2
+        .storage_trie(&contract)
+        .unwrap()
+        .get_with_proof(&b"banana".to_vec())
+        .unwrap();
+    assert_eq!(value, None);
+    assert!(verify_storage_proof::<V2>(&storage_hash, b"banana", &proof, None).is_ok());
+}
+
+#[test]
+pub fn world_state_proof() {
+    let key_apple: Key = b"apple".to_vec();
+    let value_apple: Value = b"1234".to_vec();
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (root_hash_v1, addresses, _) = env_1.commit_genesis::<V1>();
+    let (contract, user) = (env_1.address, addresses[1]);
+    let ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, root_hash_v1);
+
+    let (proof, value) = ws_v1.storage_with_proof(&contract, &key_apple).unwrap();
+    assert_eq!(value, Some(value_apple.clone()));
+    assert!(proof.storage_hash.is_some());
+    assert!(verify_world_state_proof::<V1>(
+        &root_hash_v1,
+        &contract,
+        &key_apple,
+        &proof,
+        value.as_deref()
+    )
+    .is_ok());
+    assert_eq!(
+        verify_world_state_proof::<V1>(&root_hash_v1, &contract, &key_apple, &proof, Some(b"4321")),
+        Err(ProofError::RootMismatch)
+    );
+    assert_eq!(
+        verify_world_state_proof::<V1>(&[1; 32], &contract, &key_apple, &proof, value.as_deref()),
+        Err(ProofError::RootMismatch)
+    );
+    // the storage hash carried by the proof is verified against the state root
+    let mut forged_proof = proof.clone();
+    forged_proof.storage_hash = Some([1; 32]);
+    assert_eq!(
+        verify_world_state_proof::<V1>(
+            &root_hash_v1,
+            &contract,
+            &key_apple,
+            &forged_proof,
+            value.as_deref()
+        ),
+        Err(ProofError::RootMismatch)
+    );
+    // absent key in the storage
+    let (proof, value) = ws_v1
+        .storage_with_proof(&contract, &b"banana".to_vec())
+        .unwrap();
+    assert_eq!(value, None);
+    assert!(
+        verify_world_state_proof::<V1>(&root_hash_v1, &contract, b"banana", &proof, None).is_ok()
+    );
+    // account without storage
+    let (proof, value) = ws_v1.storage_with_proof(&user, &key_apple).unwrap();
+    assert_eq!(value, None);
+    assert_eq!(proof.storage_hash, None);
+    assert!(verify_world_state_proof::<V1>(&root_hash_v1, &user, &key_apple, &proof, None).is_ok());
+    assert_eq!(
+        verify_world_state_proof::<V1>(&root_hash_v1, &user, &key_apple, &proof, Some(b"1234")),
+        Err(ProofError::ValueMismatch)
+    );
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (root_hash_v2, addresses, _) = env_2.commit_genesis::<V2>();
+    let (contract, user) = (env_2.address, addresses[1]);
+    let ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, root_hash_v2);
+
+    let (proof, value) = ws_v2.storage_with_proof(&contract, &key_apple).unwrap();
+    assert_eq!(value, Some(value_apple.clone()));
+    assert!(proof.storage_hash.is_some());
+    assert!(verify_world_state_proof::<V2>(
+        &root_hash_v2,
+        &contract,
+        &key_apple,
+        &proof,
+        value.as_deref()
+    )
+    .is_ok());
+    assert_eq!(
+        verify_world_state_proof::<V2>(&root_hash_v2, &contract, &key_apple, &proof, Some(b"4321")),
+        Err(ProofError::RootMismatch)
+    );
+    assert_eq!(
+        verify_world_state_proof::<V2>(&[1; 32], &contract, &key_apple, &proof, value.as_deref()),
+        Err(ProofError::RootMismatch)
+    );
+    // the storage hash carried by the proof is verified against the state root
+    let mut forged_proof = proof.clone();
+    forged_proof.storage_hash = Some([1; 32]);
+    assert_eq!(
+        verify_world_state_proof::<V2>(
+            &root_hash_v2,
+            &contract,
+            &key_apple,
+            &forged_proof,
+            value.as_deref()
+        ),
+        Err(ProofError::RootMismatch)
+    );
+    // absent key in the storage
+    let (proof, value) = ws_v2
+        .storage_with_proof(&contract, &b"banana".to_vec())
+        .unwrap();
+    assert_eq!(value, None);
+    assert!(
+        verify_world_state_proof::<V2>(&root_hash_v2, &contract, b"banana", &proof, None).is_ok()
+    );
+    // account without storage
+    let (proof, value) = ws_v2.storage_with_proof(&user, &key_apple).unwrap();
+    assert_eq!(value, None);
+    assert_eq!(proof.storage_hash, None);
+    assert!(verify_world_state_proof::<V2>(&root_hash_v2, &user, &key_apple, &proof, None).is_ok());
+    assert_eq!(
+        verify_world_state_proof::<V2>(&root_hash_v2, &user, &key_apple, &proof, Some(b"1234")),
+        Err(ProofError::ValueMismatch)
+    );
+}
+
+#[test]
+pub fn batch_proof() {
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (root_hash_v1, addresses, storage) = env_1.commit_genesis::<V1>();
+    let contract = env_1.address;
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, root_hash_v1);
+
+    // balances of the accounts, and the nonce not set
+    let mut fields: Vec<(PublicAddress, AccountField)> = addresses
+        .iter()
+        .map(|address| (*address, AccountField::Balance))
+        .collect();
+    fields.push((addresses[1], AccountField::Nonce));
+    let (proof, values) = ws_v1.account_trie().fields_with_proof(&fields).unwrap();
+    assert_eq!(values.len(), fields.len());
+    assert_eq!(values[3], Some(300_u64.to_le_bytes().to_vec()));
+    assert_eq!(values[10], None);
+    let single_proofs_size: usize = addresses
+        .iter()
+        .map(|address| {
+            let (proof, _) = ws_v1.account_trie().balance_with_proof(address).unwrap();
+            proof.iter().map(Vec::len).sum::<usize>()
+        })
+        .sum();
+    assert!(proof.iter().map(Vec::len).sum::<usize>() < single_proofs_size);
+    let mut expected: Vec<(PublicAddress, AccountField, Option<&[u8]>)> = fields
+        .iter()
+        .zip(values.iter())
+        .map(|((address, field), value)| (*address, *field, value.as_deref()))
+        .collect();
+    expected.reverse();
+    assert!(verify_account_fields_proof::<V1>(&root_hash_v1, &proof, &expected).is_ok());
+    let wrong_balance = 1_u64.to_le_bytes();
+    let mut wrong_expected = expected.clone();
+    wrong_expected[5].2 = Some(&wrong_balance);
+    assert_eq!(
+        verify_account_fields_proof::<V1>(&root_hash_v1, &proof, &wrong_expected),
+        Err(ProofError::RootMismatch)
+    );
+    // the values of all proved fields are needed to verify the proof
+    assert!(verify_account_fields_proof::<V1>(
+        &root_hash_v1,
+        &proof,
+        &expected[..expected.len() - 1]
+    )
+    .is_err());
+
+    // the first 10 storage keys, and a key not set
+    let storage_hash = ws_v1
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    let mut keys: Vec<Key> = storage[..10].iter().map(|(key, _)| key.clone()).collect();
+    keys.push(b"absent".to_vec());
+    let (proof, values) = ws_v1
+        .storage_trie(&contract)
+        .unwrap()
+        .batch_get_with_proof(&keys)
+        .unwrap();
+    assert_eq!(values[4], Some(storage[4].1.clone()));
+    assert_eq!(values[10], None);
+    let expected: Vec<(&[u8], Option<&[u8]>)> = keys
+        .iter()
+        .zip(values.iter())
+        .map(|(key, value)| (key.as_slice(), value.as_deref()))
+        .collect();
+    assert!(verify_storage_batch_proof::<V1>(&storage_hash, &proof, &expected).is_ok());
+    let mut wrong_expected = expected.clone();
+    wrong_expected[10].1 = Some(b"present");
+    assert!(verify_storage_batch_proof::<V1>(&storage_hash, &proof, &wrong_expected).is_err());
+    assert_eq!(
+        verify_storage_batch_proof::<V1>(&root_hash_v1, &proof, &expected),
+        Err(ProofError::RootMismatch)
+    );
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (root_hash_v2, addresses, storage) = env_2.commit_genesis::<V2>();
+    let contract = env_2.address;
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, root_hash_v2);
+
+    // balances of the accounts, and the nonce not set
+    let mut fields: Vec<(PublicAddress, AccountField)> = addresses
+        .iter()
+        .map(|address| (*address, AccountField::Balance))
+        .collect();
+    fields.push((addresses[1], AccountField::Nonce));
+    let (proof, values) = ws_v2.account_trie().fields_with_proof(&fields).unwrap();
+    assert_eq!(values.len(), fields.len());
+    assert_eq!(values[3], Some(300_u64.to_le_bytes().to_vec()));
+    assert_eq!(values[10], None);
+    let single_proofs_size: usize = addresses
+        .iter()
+        .map(|address| {
+            let (proof, _) = ws_v2.account_trie().balance_with_proof(address).unwrap();
+            proof.iter().map(Vec::len).sum::<usize>()
+        })
+        .sum();
+    assert!(proof.iter().map(Vec::len).sum::<usize>() < single_proofs_size);
+    let mut expected: Vec<(PublicAddress, AccountField, Option<&[u8]>)> = fields
+        .iter()
+        .zip(values.iter())
+        .map(|((address, field), value)| (*address, *field, value.as_deref()))
+        .collect();
+    expected.reverse();
+    assert!(verify_account_fields_proof::<V2>(&root_hash_v2, &proof, &expected).is_ok());
+    let wrong_balance = 1_u64.to_le_bytes();
+    let mut wrong_expected = expected.clone();
+    wrong_expected[5].2 = Some(&wrong_balance);
+    assert_eq!(
+        verify_account_fields_proof::<V2>(&root_hash_v2, &proof, &wrong_expected),
+        Err(ProofError::RootMismatch)
+    );
+    // the values of all proved fields are needed to verify the proof
+    assert!(verify_account_fields_proof::<V2>(
+        &root_hash_v2,
+        &proof,
+        &expected[..expected.len() - 1]
+    )
+    .is_err());
+
+    // the first 10 storage keys, and a key not set
+    let storage_hash = ws_v2
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    let mut keys: Vec<Key> = storage[..10].iter().map(|(key, _)| key.clone()).collect();
+    keys.push(b"absent".to_vec());
+    let (proof, values) = ws_v2
+        .storage_trie(&contract)
+        .unwrap()
+        .batch_get_with_proof(&keys)
+        .unwrap();
+    assert_eq!(values[4], Some(storage[4].1.clone()));
+    assert_eq!(values[10], None);
+    let expected: Vec<(&[u8], Option<&[u8]>)> = keys
+        .iter()
+        .zip(values.iter())
+        .map(|(key, value)| (key.as_slice(), value.as_deref()))
+        .collect();
+    assert!(verify_storage_batch_proof::<V2>(&storage_hash, &proof, &expected).is_ok());
+    let mut wrong_expected = expected.clone();
+    wrong_expected[10].1 = Some(b"present");
+    assert!(verify_storage_batch_proof::<V2>(&storage_hash, &proof, &wrong_expected).is_err());
+    assert_eq!(
+        verify_storage_batch_proof::<V2>(&root_hash_v2, &proof, &expected),
+        Err(ProofError::RootMismatch)
+    );
+}
+
+#[test]
+pub fn absence_proof() {
+    let key_apple: Key = b"apple".to_vec();
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (root_hash_v1, addresses, _) = env_1.commit_genesis::<V1>();
+    let (contract, user) = (env_1.address, addresses[1]);
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, root_hash_v1);
+
+    // nonce set to 0 is not absent
+    assert_eq!(
+        ws_v1.account_trie().nonce_with_proof(&contract).unwrap().1,
+        0
+    );
+    assert!(ws_v1
+        .account_trie()
+        .absence_proof(&contract, AccountField::Nonce)
+        .unwrap()
+        .is_none());
+    // the user has no contract code
+    let proof = ws_v1
+        .account_trie()
+        .absence_proof(&user, AccountField::ContractCode)
+        .unwrap()
+        .unwrap();
+    assert!(verify_account_field_absence::<V1>(
+        &root_hash_v1,
+        &user,
+        AccountField::ContractCode,
+        &proof
+    )
+    .is_ok());
+    assert_eq!(
+        verify_account_field_absence::<V1>(&[1; 32], &user, AccountField::ContractCode, &proof),
+        Err(ProofError::RootMismatch)
+    );
+    // absence of a field cannot be verified by the proof of its value
+    let (proof, _) = ws_v1.account_trie().balance_with_proof(&contract).unwrap();
+    assert!(verify_account_field_absence::<V1>(
+        &root_hash_v1,
+        &contract,
+        AccountField::Balance,
+        &proof
+    )
+    .is_err());
+
+    // the storage slot is unset
+    let storage_hash = ws_v1
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    let storage_trie = ws_v1.storage_trie(&contract).unwrap();
+    assert!(storage_trie.absence_proof(&key_apple).unwrap().is_none());
+    let proof = storage_trie
+        .absence_proof(&b"banana".to_vec())
+        .unwrap()
+        .unwrap();
+    assert!(verify_storage_absence::<V1>(&storage_hash, b"banana", &proof).is_ok());
+    assert!(verify_storage_absence::<V1>(&storage_hash, b"banana", &Vec::new()).is_err());
+    let (proof, _) = storage_trie.get_with_proof(&key_apple).unwrap();
+    assert!(verify_storage_absence::<V1>(&storage_hash, &key_apple, &proof).is_err());
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (root_hash_v2, addresses, _) = env_2.commit_genesis::<V2>();
+    let (contract, user) = (env_2.address, addresses[1]);
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, root_hash_v2);
+
+    // nonce set to 0 is not absent
+    assert_eq!(
+        ws_v2.account_trie().nonce_with_proof(&contract).unwrap().1,
+        0
+    );
+    assert!(ws_v2
+        .account_trie()
+        .absence_proof(&contract, AccountField::Nonce)
+        .unwrap()
+        .is_none());
+    // the user has no contract code
+    let proof = ws_v2
+        .account_trie()
+        .absence_proof(&user, AccountField::ContractCode)
+        .unwrap()
+        .unwrap();
+    assert!(verify_account_field_absence::<V2>(
+        &root_hash_v2,
+        &user,
+        AccountField::ContractCode,
+        &proof
+    )
+    .is_ok());
+    assert_eq!(
+        verify_account_field_absence::<V2>(&[1; 32], &user, AccountField::ContractCode, &proof),
+        Err(ProofError::RootMismatch)
+    );
+    // absence of a field cannot be verified by the proof of its value
+    let (proof, _) = ws_v2.account_trie().balance_with_proof(&contract).unwrap();
+    assert!(verify_account_field_absence::<V2>(
+        &root_hash_v2,
+        &contract,
+        AccountField::Balance,
+        &proof
+    )
+    .is_err());
+
+    // the storage slot is unset
+    let storage_hash = ws_v2
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    let storage_trie = ws_v2.storage_trie(&contract).unwrap();
+    assert!(storage_trie.absence_proof(&key_apple).unwrap().is_none());
+    let proof = storage_trie
+        .absence_proof(&b"banana".to_vec())
+        .unwrap()
+        .unwrap();
+    assert!(verify_storage_absence::<V2>(&storage_hash, b"banana", &proof).is_ok());
+    assert!(verify_storage_absence::<V2>(&storage_hash, b"banana", &Vec::new()).is_err());
+    let (proof, _) = storage_trie.get_with_proof(&key_apple).unwrap();
+    assert!(verify_storage_absence::<V2>(&storage_hash, &key_apple, &proof).is_err());
+}
+
+#[test]
+pub fn range_proof() {
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (root_hash_v1, addresses, storage) = env_1.commit_genesis::<V1>();
+    let contract = env_1.address;
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, root_hash_v1);
+
+    // storage keys in [10, 0, 0]..[20, 0, 0]
+    let storage_hash = ws_v1
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    let (start, end) = (vec![10, 0, 0], vec![20, 0, 0]);
+    let (proof, items) = ws_v1
+        .storage_trie(&contract)
+        .unwrap()
+        .range_with_proof(&start, Some(&end))
+        .unwrap();
+    assert_eq!(items, storage[10..20].to_vec());
+    assert!(
+        verify_storage_range_proof::<V1>(&storage_hash, &start, Some(&end), &proof, &items).is_ok()
+    );
+    // omitted key-value pair
+    let mut omitted = items.clone();
+    omitted.remove(4);
+    assert_eq!(
+        verify_storage_range_proof::<V1>(&storage_hash, &start, Some(&end), &proof, &omitted),
+        Err(ProofError::RangeMismatch)
+    );
+    // the proof does not cover a larger range. The small leaves are inlined in their branch nodes, so the proof
+    // covers the keys up to [31, ..] in the same branch, but not the keys from [32, ..] in the next one.
+    assert!(verify_storage_range_proof::<V1>(
+        &storage_hash,
+        &start,
+        Some(&[40, 0, 0]),
+        &proof,
+        &storage[10..40]
+    )
+    .is_err());
+    assert_eq!(
+        verify_storage_range_proof::<V1>(&root_hash_v1, &start, Some(&end), &proof, &items),
+        Err(ProofError::RootMismatch)
+    );
+    // the range is not bounded above
+    let (proof, items) = ws_v1
+        .storage_trie(&contract)
+        .unwrap()
+        .range_with_proof(&[45], None)
+        .unwrap();
+    assert_eq!(items, storage[45..].to_vec());
+    assert!(verify_storage_range_proof::<V1>(&storage_hash, &[45], None, &proof, &items).is_ok());
+    // empty range
+    let (proof, items) = ws_v1
+        .storage_trie(&contract)
+        .unwrap()
+        .range_with_proof(&[100], Some(&[200]))
+        .unwrap();
+    assert!(items.is_empty());
+    assert!(
+        verify_storage_range_proof::<V1>(&storage_hash, &[100], Some(&[200]), &proof, &[]).is_ok()
+    );
+
+    // account addresses in addresses[7]..addresses[8], which include all fields of the contract
+    let (proof, items) = ws_v1
+        .account_trie()
+        .range_with_proof(&addresses[7], Some(&addresses[8]))
+        .unwrap();
+    let range_addresses: Vec<PublicAddress> =
+        items.iter().map(|(address, _, _)| *address).collect();
+    assert_eq!(
+        range_addresses,
+        vec![
+            addresses[7],
+            contract,
+            contract,
+            contract,
+            contract,
+            contract
+        ]
+    );
+    assert_eq!(
+        items[2],
+        (
+            contract,
+            AccountField::Balance,
+            500_u64.to_le_bytes().to_vec()
+        )
+    );
+    assert!(verify_accounts_range_proof::<V1>(
+        &root_hash_v1,
+        &addresses[7],
+        Some(&addresses[8]),
+        &proof,
+        &items
+    )
+    .is_ok());
+    assert_eq!(
+        verify_accounts_range_proof::<V1>(
+            &root_hash_v1,
+            &addresses[7],
+            Some(&addresses[8]),
+            &proof,
+            &items[..4]
+        ),
+        Err(ProofError::RangeMismatch)
+    );
+    let mut truncated_proof = proof.clone();
+    truncated_proof.pop();
+    assert!(verify_accounts_range_proof::<V1>(
+        &root_hash_v1,
+        &addresses[7],
+        Some(&addresses[8]),
+        &truncated_proof,
+        &items
+    )
+    .is_err());
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (root_hash_v2, addresses, storage) = env_2.commit_genesis::<V2>();
+    let contract = env_2.address;
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, root_hash_v2);
+
+    // storage keys in [10, 0, 0]..[20, 0, 0]
+    let storage_hash = ws_v2
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    let (start, end) = (vec![10, 0, 0], vec![20, 0, 0]);
+    let (proof, items) = ws_v2
+        .storage_trie(&contract)
+        .unwrap()
+        .range_with_proof(&start, Some(&end))
+        .unwrap();
+    assert_eq!(items, storage[10..20].to_vec());
+    assert!(
+        verify_storage_range_proof::<V2>(&storage_hash, &start, Some(&end), &proof, &items).is_ok()
+    );
+    // omitted key-value pair
+    let mut omitted = items.clone();
+    omitted.remove(4);
+    assert_eq!(
+        verify_storage_range_proof::<V2>(&storage_hash, &start, Some(&end), &proof, &omitted),
+        Err(ProofError::RangeMismatch)
+    );
+    // the proof does not cover a larger range. The small leaves are inlined in their branch nodes, so the proof
+    // covers the keys up to [31, ..] in the same branch, but not the keys from [32, ..] in the next one.
+    assert!(verify_storage_range_proof::<V2>(
+        &storage_hash,
+        &start,
+        Some(&[40, 0, 0]),
+        &proof,
+        &storage[10..40]
+    )
+    .is_err());
+    assert_eq!(
+        verify_storage_range_proof::<V2>(&root_hash_v2, &start, Some(&end), &proof, &items),
+        Err(ProofError::RootMismatch)
+    );
+    // the range is not bounded above
+    let (proof, items) = ws_v2
+        .storage_trie(&contract)
+        .unwrap()
+        .range_with_proof(&[45], None)
+        .unwrap();
+    assert_eq!(items, storage[45..].to_vec());
+    assert!(verify_storage_range_proof::<V2>(&storage_hash, &[45], None, &proof, &items).is_ok());
+    // empty range
+    let (proof, items) = ws_v2
+        .storage_trie(&contract)
+        .unwrap()
+        .range_with_proof(&[100], Some(&[200]))
+        .unwrap();
+    assert!(items.is_empty());
+    assert!(
+        verify_storage_range_proof::<V2>(&storage_hash, &[100], Some(&[200]), &proof, &[]).is_ok()
+    );
+
+    // account addresses in addresses[7]..addresses[8], which include all fields of the contract
+    let (proof, items) = ws_v2
+        .account_trie()
+        .range_with_proof(&addresses[7], Some(&addresses[8]))
+        .unwrap();
+    let range_addresses: Vec<PublicAddress> =
+        items.iter().map(|(address, _, _)| *address).collect();
+    assert_eq!(
+        range_addresses,
+        vec![
+            addresses[7],
+            contract,
+            contract,
+            contract,
+            contract,
+            contract
+        ]
+    );
+    assert_eq!(
+        items[2],
+        (
+            contract,
+            AccountField::Balance,
+            500_u64.to_le_bytes().to_vec()
+        )
+    );
+    assert!(verify_accounts_range_proof::<V2>(
+        &root_hash_v2,
+        &addresses[7],
+        Some(&addresses[8]),
+        &proof,
+        &items
+    )
+    .is_ok());
+    assert_eq!(
+        verify_accounts_range_proof::<V2>(
+            &root_hash_v2,
+            &addresses[7],
+            Some(&addresses[8]),
+            &proof,
+            &items[..4]
+        ),
+        Err(ProofError::RangeMismatch)
+    );
+    let mut truncated_proof = proof.clone();
+    truncated_proof.pop();
+    assert!(verify_accounts_range_proof::<V2>(
+        &root_hash_v2,
+        &addresses[7],
+        Some(&addresses[8]),
+        &truncated_proof,
+        &items
+    )
+    .is_err());
+}
+
+#[test]
+pub fn account_proof_bundle() {
+    let code: Vec<u8> = vec![1, 2, 3, 4];
+    let key_apple: Key = b"apple".to_vec();
+    let value_apple: Value = b"1234".to_vec();
+    let key_banana: Key = b"banana".to_vec();
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (root_hash_v1, addresses, _) = env_1.commit_genesis::<V1>();
+    let (contract, user) = (env_1.address, addresses[1]);
+    let ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, root_hash_v1);
+
+    let bundle = ws_v1
+        .account_proof_bundle(&contract, &[key_apple.clone(), key_banana.clone()])
+        .unwrap();
+    assert_eq!(bundle.nonce, Some(0));
+    assert_eq!(bundle.balance, Some(500));
+    assert_eq!(bundle.code_hash, Some(AccountProofBundle::hash_code(&code)));
+    assert_eq!(bundle.cbi_version, Some(2));
+    assert_eq!(
+        bundle.storage_hash,
+        ws_v1.account_trie().storage_hash(&contract).unwrap()
+    );
+    assert!(bundle.storage_hash.is_some());
+    assert_eq!(bundle.storage_proofs[0].value, Some(value_apple.clone()));
+    assert_eq!(bundle.storage_proofs[1].key, key_banana);
+    assert_eq!(bundle.storage_proofs[1].value, None);
+    assert!(verify_account_proof_bundle::<V1>(&root_hash_v1, &bundle, Some(&code)).is_ok());
+    // the bundle is serializable
+    let serialized = borsh::BorshSerialize::try_to_vec(&bundle).unwrap();
+    let deserialized: AccountProofBundle =
+        borsh::BorshDeserialize::try_from_slice(&serialized).unwrap();
+    assert_eq!(deserialized, bundle);
+    // the code must match the code hash
+    assert_eq!(
+        verify_account_proof_bundle::<V1>(&root_hash_v1, &bundle, Some(&[5, 6])),
+        Err(ProofError::ValueMismatch)
+    );
+    let mut forged_bundle = bundle.clone();
+    forged_bundle.balance = Some(1000);
+    assert!(verify_account_proof_bundle::<V1>(&root_hash_v1, &forged_bundle, Some(&code)).is_err());
+    let mut forged_bundle = bundle.clone();
+    forged_bundle.storage_proofs[1].value = Some(b"5678".to_vec());
+    assert!(verify_account_proof_bundle::<V1>(&root_hash_v1, &forged_bundle, Some(&code)).is_err());
+
+    // account without code and storage
+    let bundle = ws_v1
+        .account_proof_bundle(&user, std::slice::from_ref(&key_apple))
+        .unwrap();
+    assert_eq!(bundle.nonce, None);
+    assert_eq!(bundle.balance, Some(100));
+    assert_eq!(bundle.code_hash, None);
+    assert_eq!(bundle.storage_hash, None);
+    assert!(bundle.storage_proofs[0].proof.is_empty());
+    assert!(verify_account_proof_bundle::<V1>(&root_hash_v1, &bundle, None).is_ok());
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (root_hash_v2, addresses, _) = env_2.commit_genesis::<V2>();
+    let (contract, user) = (env_2.address, addresses[1]);
+    let ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, root_hash_v2);
+
+    let bundle = ws_v2
+        .account_proof_bundle(&contract, &[key_apple.clone(), key_banana.clone()])
+        .unwrap();
+    assert_eq!(bundle.nonce, Some(0));
+    assert_eq!(bundle.balance, Some(500));
+    assert_eq!(bundle.code_hash, Some(AccountProofBundle::hash_code(&code)));
+    assert_eq!(bundle.cbi_version, Some(2));
+    assert_eq!(
+        bundle.storage_hash,
+        ws_v2.account_trie().storage_hash(&contract).unwrap()
+    );
+    assert!(bundle.storage_hash.is_some());
+    assert_eq!(bundle.storage_proofs[0].value, Some(value_apple.clone()));
+    assert_eq!(bundle.storage_proofs[1].key, key_banana);
+    assert_eq!(bundle.storage_proofs[1].value, None);
+    assert!(verify_account_proof_bundle::<V2>(&root_hash_v2, &bundle, Some(&code)).is_ok());
+    // the bundle is serializable
+    let serialized = borsh::BorshSerialize::try_to_vec(&bundle).unwrap();
+    let deserialized: AccountProofBundle =
+        borsh::BorshDeserialize::try_from_slice(&serialized).unwrap();
+    assert_eq!(deserialized, bundle);
+    // the code must match the code hash
+    assert_eq!(
+        verify_account_proof_bundle::<V2>(&root_hash_v2, &bundle, Some(&[5, 6])),
+        Err(ProofError::ValueMismatch)
+    );
+    let mut forged_bundle = bundle.clone();
+    forged_bundle.balance = Some(1000);
+    assert!(verify_account_proof_bundle::<V2>(&root_hash_v2, &forged_bundle, Some(&code)).is_err());
+    let mut forged_bundle = bundle.clone();
+    forged_bundle.storage_proofs[1].value = Some(b"5678".to_vec());
+    assert!(verify_account_proof_bundle::<V2>(&root_hash_v2, &forged_bundle, Some(&code)).is_err());
+
+    // account without code and storage
+    let bundle = ws_v2
+        .account_proof_bundle(&user, std::slice::from_ref(&key_apple))
+        .unwrap();
+    assert_eq!(bundle.nonce, None);
+    assert_eq!(bundle.balance, Some(100));
+    assert_eq!(bundle.code_hash, None);
+    assert_eq!(bundle.storage_hash, None);
+    assert!(bundle.storage_proofs[0].proof.is_empty());
+    assert!(verify_account_proof_bundle::<V2>(&root_hash_v2, &bundle, None).is_ok());
+}
+
+#[test]
+pub fn verify_module() {
+    let key_apple: Key = b"apple".to_vec();
+    let value_apple: Value = b"1234".to_vec();
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (root_hash_v1, addresses, _) = env_1.commit_genesis::<V1>();
+    let (contract, user) = (env_1.address, addresses[1]);
+    let ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, root_hash_v1);
+
+    let (proof, balance) = ws_v1.account_trie().balance_with_proof(&user).unwrap();
+    assert!(verify::verify_account_field::<V1>(
+        &root_hash_v1,
+        &user,
+        AccountField::Balance,
+        &proof,
+        Some(&balance.to_le_bytes())
+    )
+    .is_ok());
+    assert_eq!(
+        verify::verify_account_field::<V1>(
+            &root_hash_v1,
+            &user,
+            AccountField::Balance,
+            &proof,
+            Some(&200_u64.to_le_bytes())
+        ),
+        Err(ProofError::RootMismatch)
+    );
+    let (proof, value) = ws_v1.storage_with_proof(&contract, &key_apple).unwrap();
+    let storage_hash = proof.storage_hash.unwrap();
+    assert!(verify::verify_world_state::<V1>(
+        &root_hash_v1,
+        &contract,
+        &key_apple,
+        Some(&storage_hash),
+        &proof.proof,
+        value.as_deref()
+    )
+    .is_ok());
+    // the storage part of the proof alone is verified against the storage hash
+    let storage_proof: Vec<Vec<u8>> = proof
+        .proof
+        .iter()
+        .filter(|node| node.first() == Some(&1))
+        .cloned()
+        .collect();
+    assert!(verify::verify_storage::<V1>(
+        &storage_hash,
+        &key_apple,
+        &storage_proof,
+        Some(&value_apple)
+    )
+    .is_ok());
+    assert_eq!(
+        verify::verify_storage::<V1>(&storage_hash, &key_apple, &proof.proof, Some(&value_apple)),
+        Err(ProofError::InvalidProofLevel)
+    );
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (root_hash_v2, addresses, _) = env_2.commit_genesis::<V2>();
+    let (contract, user) = (env_2.address, addresses[1]);
+    let ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, root_hash_v2);
+
+    let (proof, balance) = ws_v2.account_trie().balance_with_proof(&user).unwrap();
+    assert!(verify::verify_account_field::<V2>(
+        &root_hash_v2,
+        &user,
+        AccountField::Balance,
+        &proof,
+        Some(&balance.to_le_bytes())
+    )
+    .is_ok());
+    assert_eq!(
+        verify::verify_account_field::<V2>(
+            &root_hash_v2,
+            &user,
+            AccountField::Balance,
+            &proof,
+            Some(&200_u64.to_le_bytes())
+        ),
+        Err(ProofError::RootMismatch)
+    );
+    let (proof, value) = ws_v2.storage_with_proof(&contract, &key_apple).unwrap();
+    let storage_hash = proof.storage_hash.unwrap();
+    assert!(verify::verify_world_state::<V2>(
+        &root_hash_v2,
+        &contract,
+        &key_apple,
+        Some(&storage_hash),
+        &proof.proof,
+        value.as_deref()
+    )
+    .is_ok());
+    // the storage part of the proof alone is verified against the storage hash
+    let storage_proof: Vec<Vec<u8>> = proof
+        .proof
+        .iter()
+        .filter(|node| node.first() == Some(&1))
+        .cloned()
+        .collect();
+    assert!(verify::verify_storage::<V2>(
+        &storage_hash,
+        &key_apple,
+        &storage_proof,
+        Some(&value_apple)
+    )
+    .is_ok());
+    assert_eq!(
+        verify::verify_storage::<V2>(&storage_hash, &key_apple, &proof.proof, Some(&value_apple)),
+        Err(ProofError::InvalidProofLevel)
+    );
+}
+
+/// `execute_transfer` executes a block increasing the balance of the user and moving a storage value of the contract,
+/// and returns the state root
+fn execute_transfer<S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>(
+    ws: &mut WorldState<S, V>,
+    contract: &PublicAddress,
+    user: &PublicAddress,
+    storage: &[(Key, Value)],
+) -> Sha256Hash {
+    let balance = ws.account_trie().balance(user).unwrap();
+    ws.account_trie_mut()
+        .set_balance(user, balance + 1)
+        .unwrap();
+    ws.account_trie_mut().set_nonce(user, 1).unwrap();
+    let storage_trie = ws.storage_trie_mut(contract).unwrap();
+    let value = storage_trie.get(&storage[5].0).unwrap().unwrap();
+    storage_trie.set(&vec![100], value).unwrap();
+    storage_trie.remove(&storage[6].0).unwrap();
+    ws.close().unwrap().new_root_hash
+}
+
+/// The following tests are for the witness of a block and the diffs of state roots and WorldState sessions.
+///
+/// They start from the accounts and storage committed by [TestEnv::commit_genesis].
+#[test]
+pub fn witness() {
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (pre_state_root_v1, addresses, storage) = env_1.commit_genesis::<V1>();
+    let (contract, user) = (env_1.address, addresses[1]);
+
+    // execute the block on the database, recording the trie nodes read
+    let recorder = WitnessRecorder::new();
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, pre_state_root_v1);
+    ws_v1.set_witness_recorder(recorder.clone());
+    let post_state_root_v1 = execute_transfer(&mut ws_v1, &contract, &user, &storage);
+    let witness = recorder.witness();
+    assert!(!witness.is_empty());
+    assert!(witness.len() < env_1.db.len());
+    // execute the block again on the witness only
+    let mut ws_v1 = WorldState::<Witness, V1>::open(&witness, pre_state_root_v1);
+    assert_eq!(
+        execute_transfer(&mut ws_v1, &contract, &user, &storage),
+        post_state_root_v1
+    );
+    // the witness is serializable
+    let serialized = borsh::BorshSerialize::try_to_vec(&witness).unwrap();
+    let deserialized: Witness = borsh::BorshDeserialize::try_from_slice(&serialized).unwrap();
+    assert_eq!(deserialized, witness);
+    // trie nodes not read by the block are not in the witness
+    let ws_v1 = WorldState::<Witness, V1>::open(&witness, pre_state_root_v1);
+    assert_eq!(
+        ws_v1.account_trie().balance(&addresses[9]),
+        Err(MptError::IncompleteDatabase)
+    );
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (pre_state_root_v2, addresses, storage) = env_2.commit_genesis::<V2>();
+    let (contract, user) = (env_2.address, addresses[1]);
+
+    // execute the block on the database, recording the trie nodes read
+    let recorder = WitnessRecorder::new();
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, pre_state_root_v2);
+    ws_v2.set_witness_recorder(recorder.clone());
+    let post_state_root_v2 = execute_transfer(&mut ws_v2, &contract, &user, &storage);
+    let witness = recorder.witness();
+    assert!(!witness.is_empty());
+    assert!(witness.len() < env_2.db.len());
+    // execute the block again on the witness only
+    let mut ws_v2 = WorldState::<Witness, V2>::open(&witness, pre_state_root_v2);
+    assert_eq!(
+        execute_transfer(&mut ws_v2, &contract, &user, &storage),
+        post_state_root_v2
+    );
+    // the witness is serializable
+    let serialized = borsh::BorshSerialize::try_to_vec(&witness).unwrap();
+    let deserialized: Witness = borsh::BorshDeserialize::try_from_slice(&serialized).unwrap();
+    assert_eq!(deserialized, witness);
+    // trie nodes not read by the block are not in the witness
+    let ws_v2 = WorldState::<Witness, V2>::open(&witness, pre_state_root_v2);
+    assert_eq!(
+        ws_v2.account_trie().balance(&addresses[9]),
+        Err(MptError::IncompleteDatabase)
+    );
+}
+
+#[test]
+pub fn trie_diff() {
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (old_state_root_v1, addresses, storage) = env_1.commit_genesis::<V1>();
+    let (contract, user, new_user) = (env_1.address, addresses[1], [255; 32]);
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, old_state_root_v1);
+    let old_storage_hash = ws_v1
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    ws_v1.account_trie_mut().set_balance(&user, 200).unwrap();
+    ws_v1.account_trie_mut().set_nonce(&new_user, 1).unwrap();
+    let storage_trie = ws_v1.storage_trie_mut(&contract).unwrap();
+    storage_trie.set(&storage[3].0, vec![33]).unwrap();
+    storage_trie.remove(&storage[4].0).unwrap();
+    storage_trie.set(&vec![100], vec![100]).unwrap();
+    // keep the trie nodes of the old state root
+    let new_state_root_v1 = ws_v1
+        .commit_at_height(&StateRootRegistry::new(&env_1.db), 1)
+        .unwrap();
+    let new_storage_hash = ws_v1
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+
+    let ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, old_state_root_v1);
+    assert_eq!(
+        ws_v1.account_trie().diff(new_state_root_v1).unwrap(),
+        vec![
+            (
+                user,
+                AccountField::Balance,
+                ValueChange::Changed {
+                    old: 100_u64.to_le_bytes().to_vec(),
+                    new: 200_u64.to_le_bytes().to_vec()
+                }
+            ),
+            (
+                contract,
+                AccountField::StorageHash,
+                ValueChange::Changed {
+                    old: old_storage_hash.to_vec(),
+                    new: new_storage_hash.to_vec()
+                }
+            ),
+            (
+                new_user,
+                AccountField::Nonce,
+                ValueChange::Added(1_u64.to_le_bytes().to_vec())
+            ),
+        ]
+    );
+    // no change between the same roots
+    assert!(ws_v1
+        .account_trie()
+        .diff(old_state_root_v1)
+        .unwrap()
+        .is_empty());
+
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, old_state_root_v1);
+    assert_eq!(
+        ws_v1
+            .storage_trie(&contract)
+            .unwrap()
+            .diff(new_storage_hash)
+            .unwrap(),
+        vec![
+            (
+                storage[3].0.clone(),
+                ValueChange::Changed {
+                    old: storage[3].1.clone(),
+                    new: vec![33]
+                }
+            ),
+            (
+                storage[4].0.clone(),
+                ValueChange::Removed(storage[4].1.clone())
+            ),
+            (vec![100], ValueChange::Added(vec![100])),
+        ]
+    );
+    // the changes in the other direction
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, new_state_root_v1);
+    let storage_changes = ws_v1
+        .storage_trie(&contract)
+        .unwrap()
+        .diff(old_storage_hash)
+        .unwrap();
+    assert_eq!(
+        storage_changes[1],
+        (
+            storage[4].0.clone(),
+            ValueChange::Added(storage[4].1.clone())
+        )
+    );
+    assert_eq!(
+        storage_changes[2],
+        (vec![100], ValueChange::Removed(vec![100]))
+    );
+    assert!(ws_v1.account_trie().diff([1; 32]).is_err());
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (old_state_root_v2, addresses, storage) = env_2.commit_genesis::<V2>();
+    let (contract, user, new_user) = (env_2.address, addresses[1], [255; 32]);
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, old_state_root_v2);
+    let old_storage_hash = ws_v2
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    ws_v2.account_trie_mut().set_balance(&user, 200).unwrap();
+    ws_v2.account_trie_mut().set_nonce(&new_user, 1).unwrap();
+    let storage_trie = ws_v2.storage_trie_mut(&contract).unwrap();
+    storage_trie.set(&storage[3].0, vec![33]).unwrap();
+    storage_trie.remove(&storage[4].0).unwrap();
+    storage_trie.set(&vec![100], vec![100]).unwrap();
+    // keep the trie nodes of the old state root
+    let new_state_root_v2 = ws_v2
+        .commit_at_height(&StateRootRegistry::new(&env_2.db), 1)
+        .unwrap();
+    let new_storage_hash = ws_v2
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+
+    let ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, old_state_root_v2);
+    assert_eq!(
+        ws_v2.account_trie().diff(new_state_root_v2).unwrap(),
+        vec![
+            (
+                user,
+                AccountField::Balance,
+                ValueChange::Changed {
+                    old: 100_u64.to_le_bytes().to_vec(),
+                    new: 200_u64.to_le_bytes().to_vec()
+                }
+            ),
+            (
+                contract,
+                AccountField::StorageHash,
+                ValueChange::Changed {
+                    old: old_storage_hash.to_vec(),
+                    new: new_storage_hash.to_vec()
+                }
+            ),
+            (
+                new_user,
+                AccountField::Nonce,
+                ValueChange::Added(1_u64.to_le_bytes().to_vec())
+            ),
+        ]
+    );
+    // no change between the same roots
+    assert!(ws_v2
+        .account_trie()
+        .diff(old_state_root_v2)
+        .unwrap()
+        .is_empty());
+
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, old_state_root_v2);
+    assert_eq!(
+        ws_v2
+            .storage_trie(&contract)
+            .unwrap()
+            .diff(new_storage_hash)
+            .unwrap(),
+        vec![
+            (
+                storage[3].0.clone(),
+                ValueChange::Changed {
+                    old: storage[3].1.clone(),
+                    new: vec![33]
+                }
+            ),
+            (
+                storage[4].0.clone(),
+                ValueChange::Removed(storage[4].1.clone())
+            ),
+            (vec![100], ValueChange::Added(vec![100])),
+        ]
+    );
+    // the changes in the other direction
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, new_state_root_v2);
+    let storage_changes = ws_v2
+        .storage_trie(&contract)
+        .unwrap()
+        .diff(old_storage_hash)
+        .unwrap();
+    assert_eq!(
+        storage_changes[1],
+        (
+            storage[4].0.clone(),
+            ValueChange::Added(storage[4].1.clone())
+        )
+    );
+    assert_eq!(
+        storage_changes[2],
+        (vec![100], ValueChange::Removed(vec![100]))
+    );
+    assert!(ws_v2.account_trie().diff([1; 32]).is_err());
+}
+
+#[test]
+pub fn state_diff() {
+    let balance = |value: u64| Some(value.to_le_bytes().to_vec());
+    let field_values = |account: &AccountDiff| {
+        account
+            .fields
+            .iter()
+            .map(|field| (field.field, field.before.clone(), field.after.clone()))
+            .collect::<Vec<_>>()
+    };
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (state_root_v1, addresses, storage) = env_1.commit_genesis::<V1>();
+    let (contract, user, unchanged, new_user) =
+        (env_1.address, addresses[1], addresses[2], [255; 32]);
+    let ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, state_root_v1);
+    let old_storage_hash = ws_v1
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+
+    // no state diff without recording
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, state_root_v1);
+    ws_v1.account_trie_mut().set_balance(&user, 1).unwrap();
+    let (_, diff) = ws_v1.close_with_state_diff().unwrap();
+    assert!(diff.accounts.is_empty());
+
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, state_root_v1);
+    ws_v1.set_state_diff_recording(true);
+    ws_v1.account_trie_mut().set_balance(&user, 1).unwrap();
+    ws_v1.account_trie_mut().set_balance(&user, 200).unwrap();
+    ws_v1
+        .account_trie_mut()
+        .set_balance(&unchanged, 50)
+        .unwrap();
+    ws_v1
+        .account_trie_mut()
+        .set_balance(&unchanged, 200)
+        .unwrap();
+    ws_v1.account_trie_mut().set_nonce(&new_user, 1).unwrap();
+    let storage_trie = ws_v1.storage_trie_mut(&contract).unwrap();
+    storage_trie.set(&storage[1].0, vec![10]).unwrap();
+    storage_trie.remove(&storage[2].0).unwrap();
+    storage_trie.set(&vec![3], vec![3]).unwrap();
+    let (mut changes, diff) = ws_v1.close_with_state_diff().unwrap();
+    // keep the trie nodes of the old state root
+    changes.deletes.clear();
+    env_1.db.apply_changes(changes.clone());
+    let ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, changes.new_root_hash);
+    let new_storage_hash = ws_v1
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        ws_v1.account_trie().diff(state_root_v1).unwrap().len(),
+        3,
+        "balance of user, storage hash of contract and nonce of new_user"
+    );
+    assert_eq!(
+        diff.accounts
+            .iter()
+            .map(|account| account.address)
+            .collect::<Vec<PublicAddress>>(),
+        vec![user, contract, new_user]
+    );
+    let user_diff = &diff.accounts[0];
+    assert_eq!(
+        user_diff
+            .fields
+            .iter()
+            .filter(|field| field.is_changed())
+            .map(|field| (field.field, field.before.clone(), field.after.clone()))
+            .collect::<Vec<_>>(),
+        vec![(AccountField::Balance, balance(100), balance(200))]
+    );
+    assert!(user_diff.storage.is_empty());
+    let contract_diff = &diff.accounts[1];
+    assert_eq!(
+        field_values(contract_diff),
+        vec![
+            (AccountField::Nonce, balance(0), balance(0)),
+            (AccountField::Balance, balance(500), balance(500)),
+            (
+                AccountField::ContractCode,
+                Some(vec![1, 2, 3, 4]),
+                Some(vec![1, 2, 3, 4])
+            ),
+            (
+                AccountField::CbiVersion,
+                Some(2_u32.to_le_bytes().to_vec()),
+                Some(2_u32.to_le_bytes().to_vec())
+            ),
+            (
+                AccountField::StorageHash,
+                Some(old_storage_hash.to_vec()),
+                Some(new_storage_hash.to_vec())
+            ),
+        ]
+    );
+    assert_eq!(
+        contract_diff.storage,
+        vec![
+            (
+                storage[1].0.clone(),
+                ValueChange::Changed {
+                    old: storage[1].1.clone(),
+                    new: vec![10]
+                }
+            ),
+            (
+                storage[2].0.clone(),
+                ValueChange::Removed(storage[2].1.clone())
+            ),
+            (vec![3], ValueChange::Added(vec![3])),
+        ]
+    );
+    let new_user_diff = &diff.accounts[2];
+    assert_eq!(
+        field_values(new_user_diff)[0],
+        (AccountField::Nonce, None, balance(1))
+    );
+    assert!(new_user_diff.fields[1..]
+        .iter()
+        .all(|field| field.before.is_none() && field.after.is_none()));
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (state_root_v2, addresses, storage) = env_2.commit_genesis::<V2>();
+    let (contract, user, unchanged, new_user) =
+        (env_2.address, addresses[1], addresses[2], [255; 32]);
+    let ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, state_root_v2);
+    let old_storage_hash = ws_v2
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+
+    // no state diff without recording
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, state_root_v2);
+    ws_v2.account_trie_mut().set_balance(&user, 1).unwrap();
+    let (_, diff) = ws_v2.close_with_state_diff().unwrap();
+    assert!(diff.accounts.is_empty());
+
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, state_root_v2);
+    ws_v2.set_state_diff_recording(true);
+    ws_v2.account_trie_mut().set_balance(&user, 1).unwrap();
+    ws_v2.account_trie_mut().set_balance(&user, 200).unwrap();
+    ws_v2
+        .account_trie_mut()
+        .set_balance(&unchanged, 50)
+        .unwrap();
+    ws_v2
+        .account_trie_mut()
+        .set_balance(&unchanged, 200)
+        .unwrap();
+    ws_v2.account_trie_mut().set_nonce(&new_user, 1).unwrap();
+    let storage_trie = ws_v2.storage_trie_mut(&contract).unwrap();
+    storage_trie.set(&storage[1].0, vec![10]).unwrap();
+    storage_trie.remove(&storage[2].0).unwrap();
+    storage_trie.set(&vec![3], vec![3]).unwrap();
+    let (mut changes, diff) = ws_v2.close_with_state_diff().unwrap();
+    // keep the trie nodes of the old state root
+    changes.deletes.clear();
+    env_2.db.apply_changes(changes.clone());
+    let ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, changes.new_root_hash);
+    let new_storage_hash = ws_v2
+        .account_trie()
+        .storage_hash(&contract)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        ws_v2.account_trie().diff(state_root_v2).unwrap().len(),
+        3,
+        "balance of user, storage hash of contract and nonce of new_user"
+    );
+    assert_eq!(
+        diff.accounts
+            .iter()
+            .map(|account| account.address)
+            .collect::<Vec<PublicAddress>>(),
+        vec![user, contract, new_user]
+    );
+    let user_diff = &diff.accounts[0];
+    assert_eq!(
+        user_diff
+            .fields
+            .iter()
+            .filter(|field| field.is_changed())
+            .map(|field| (field.field, field.before.clone(), field.after.clone()))
+            .collect::<Vec<_>>(),
+        vec![(AccountField::Balance, balance(100), balance(200))]
+    );
+    assert!(user_diff.storage.is_empty());
+    let contract_diff = &diff.accounts[1];
+    assert_eq!(
+        field_values(contract_diff),
+        vec![
+            (AccountField::Nonce, balance(0), balance(0)),
+            (AccountField::Balance, balance(500), balance(500)),
+            (
+                AccountField::ContractCode,
+                Some(vec![1, 2, 3, 4]),
+                Some(vec![1, 2, 3, 4])
+            ),
+            (
+                AccountField::CbiVersion,
+                Some(2_u32.to_le_bytes().to_vec()),
+                Some(2_u32.to_le_bytes().to_vec())
+            ),
+            (
+                AccountField::StorageHash,
+                Some(old_storage_hash.to_vec()),
+                Some(new_storage_hash.to_vec())
+            ),
+        ]
+    );
+    assert_eq!(
+        contract_diff.storage,
+        vec![
+            (
+                storage[1].0.clone(),
+                ValueChange::Changed {
+                    old: storage[1].1.clone(),
+                    new: vec![10]
+                }
+            ),
+            (
+                storage[2].0.clone(),
+                ValueChange::Removed(storage[2].1.clone())
+            ),
+            (vec![3], ValueChange::Added(vec![3])),
+        ]
+    );
+    let new_user_diff = &diff.accounts[2];
+    assert_eq!(
+        field_values(new_user_diff)[0],
+        (AccountField::Nonce, None, balance(1))
+    );
+    assert!(new_user_diff.fields[1..]
+        .iter()
+        .all(|field| field.before.is_none() && field.after.is_none()));
+}
+
+/// The following tests are for the changes of a WorldState session before closing it, and the changes of blocks
+/// before committing them.
+///
+/// They start from the accounts and storage committed by [TestEnv::commit_genesis].
+#[test]
+pub fn checkpoint() {
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (state_root_v1, addresses, storage) = env_1.commit_genesis::<V1>();
+    let (contract, new_contract, user) = (env_1.address, [255; 32], addresses[1]);
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, state_root_v1);
+    ws_v1.account_trie_mut().set_balance(&user, 1).unwrap();
+    let checkpoint1 = ws_v1.checkpoint();
+    ws_v1.account_trie_mut().set_balance(&user, 2).unwrap();
+    ws_v1
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![2], vec![2])
+        .unwrap();
+    let checkpoint2 = ws_v1.checkpoint();
+    ws_v1.account_trie_mut().set_nonce(&user, 1).unwrap();
+    ws_v1
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .remove(&storage[1].0)
+        .unwrap();
+    // StorageTrie opened after the checkpoints
+    ws_v1
+        .storage_trie_mut(&new_contract)
+        .unwrap()
+        .set(&vec![3], vec![3])
+        .unwrap();
+
+    ws_v1.revert_to(checkpoint2).unwrap();
+    assert_eq!(ws_v1.account_trie().nonce(&user).unwrap(), 0);
+    assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 2);
+    assert_eq!(
+        ws_v1.account_trie().storage_hash(&new_contract).unwrap(),
+        None
+    );
+    assert_eq!(
+        ws_v1
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&storage[1].0)
+            .unwrap(),
+        Some(storage[1].1.clone())
+    );
+    assert!(matches!(
+        ws_v1.revert_to(checkpoint2),
+        Err(WorldStateError::InvalidCheckpoint)
+    ));
+    ws_v1.revert_to(checkpoint1).unwrap();
+    assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 1);
+    assert_eq!(
+        ws_v1
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![2])
+            .unwrap(),
+        None
+    );
+    // the changes after a discarded checkpoint are kept
+    let checkpoint3 = ws_v1.checkpoint();
+    ws_v1
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![4], vec![4])
+        .unwrap();
+    let checkpoint4 = ws_v1.checkpoint();
+    ws_v1.account_trie_mut().set_nonce(&user, 4).unwrap();
+    ws_v1.discard(checkpoint4).unwrap();
+    assert!(matches!(
+        ws_v1.discard(checkpoint4),
+        Err(WorldStateError::InvalidCheckpoint)
+    ));
+    ws_v1.discard(checkpoint3).unwrap();
+    let new_state_root_v1 = ws_v1.commit().unwrap();
+    assert!(matches!(
+        ws_v1.revert_to(checkpoint1),
+        Err(WorldStateError::InvalidCheckpoint)
+    ));
+    // same state root as the WorldState with only the kept changes
+    let expected_env_1 = TestEnv::default();
+    let (state_root, _, _) = expected_env_1.commit_genesis::<V1>();
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&expected_env_1.db, state_root);
+    ws_v1.account_trie_mut().set_balance(&user, 1).unwrap();
+    ws_v1.account_trie_mut().set_nonce(&user, 4).unwrap();
+    ws_v1
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![4], vec![4])
+        .unwrap();
+    assert_eq!(ws_v1.commit().unwrap(), new_state_root_v1);
+    // the committed trie nodes are complete
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, new_state_root_v1);
+    assert_eq!(ws_v1.account_trie().nonce(&user).unwrap(), 4);
+    assert_eq!(
+        ws_v1
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![4])
+            .unwrap(),
+        Some(vec![4])
+    );
+    assert_eq!(
+        ws_v1
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&storage[1].0)
+            .unwrap(),
+        Some(storage[1].1.clone())
+    );
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (state_root_v2, addresses, storage) = env_2.commit_genesis::<V2>();
+    let (contract, new_contract, user) = (env_2.address, [255; 32], addresses[1]);
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, state_root_v2);
+    ws_v2.account_trie_mut().set_balance(&user, 1).unwrap();
+    let checkpoint1 = ws_v2.checkpoint();
+    ws_v2.account_trie_mut().set_balance(&user, 2).unwrap();
+    ws_v2
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![2], vec![2])
+        .unwrap();
+    let checkpoint2 = ws_v2.checkpoint();
+    ws_v2.account_trie_mut().set_nonce(&user, 1).unwrap();
+    ws_v2
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .remove(&storage[1].0)
+        .unwrap();
+    // StorageTrie opened after the checkpoints
+    ws_v2
+        .storage_trie_mut(&new_contract)
+        .unwrap()
+        .set(&vec![3], vec![3])
+        .unwrap();
+
+    ws_v2.revert_to(checkpoint2).unwrap();
+    assert_eq!(ws_v2.account_trie().nonce(&user).unwrap(), 0);
+    assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 2);
+    assert_eq!(
+        ws_v2.account_trie().storage_hash(&new_contract).unwrap(),
+        None
+    );
+    assert_eq!(
+        ws_v2
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&storage[1].0)
+            .unwrap(),
+        Some(storage[1].1.clone())
+    );
+    assert!(matches!(
+        ws_v2.revert_to(checkpoint2),
+        Err(WorldStateError::InvalidCheckpoint)
+    ));
+    ws_v2.revert_to(checkpoint1).unwrap();
+    assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 1);
+    assert_eq!(
+        ws_v2
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![2])
+            .unwrap(),
+        None
+    );
+    // the changes after a discarded checkpoint are kept
+    let checkpoint3 = ws_v2.checkpoint();
+    ws_v2
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![4], vec![4])
+        .unwrap();
+    let checkpoint4 = ws_v2.checkpoint();
+    ws_v2.account_trie_mut().set_nonce(&user, 4).unwrap();
+    ws_v2.discard(checkpoint4).unwrap();
+    assert!(matches!(
+        ws_v2.discard(checkpoint4),
+        Err(WorldStateError::InvalidCheckpoint)
+    ));
+    ws_v2.discard(checkpoint3).unwrap();
+    let new_state_root_v2 = ws_v2.commit().unwrap();
+    assert!(matches!(
+        ws_v2.revert_to(checkpoint1),
+        Err(WorldStateError::InvalidCheckpoint)
+    ));
+    // same state root as the WorldState with only the kept changes
+    let expected_env_2 = TestEnv::default();
+    let (state_root, _, _) = expected_env_2.commit_genesis::<V2>();
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&expected_env_2.db, state_root);
+    ws_v2.account_trie_mut().set_balance(&user, 1).unwrap();
+    ws_v2.account_trie_mut().set_nonce(&user, 4).unwrap();
+    ws_v2
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![4], vec![4])
+        .unwrap();
+    assert_eq!(ws_v2.commit().unwrap(), new_state_root_v2);
+    // the committed trie nodes are complete
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, new_state_root_v2);
+    assert_eq!(ws_v2.account_trie().nonce(&user).unwrap(), 4);
+    assert_eq!(
+        ws_v2
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![4])
+            .unwrap(),
+        Some(vec![4])
+    );
+    assert_eq!(
+        ws_v2
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&storage[1].0)
+            .unwrap(),
+        Some(storage[1].1.clone())
+    );
+}
+
+#[test]
+pub fn intermediate_state_root() {
+    // ================ Version1 ================
+    // state roots by committing the changes of each transaction
+    let expected_env_1 = TestEnv::default();
+    let (state_root, addresses, _) = expected_env_1.commit_genesis::<V1>();
+    let (contract, user) = (expected_env_1.address, addresses[1]);
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&expected_env_1.db, state_root);
+    ws_v1.account_trie_mut().set_balance(&user, 200).unwrap();
+    ws_v1
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![1], vec![1])
+        .unwrap();
+    let expected_root1 = ws_v1.commit().unwrap();
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&expected_env_1.db, expected_root1);
+    ws_v1
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![2], vec![2])
+        .unwrap();
+    let expected_root2 = ws_v1.commit().unwrap();
+
+    let env_1 = TestEnv::default();
+    let (state_root_v1, _, _) = env_1.commit_genesis::<V1>();
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, state_root_v1);
+    ws_v1.account_trie_mut().set_balance(&user, 200).unwrap();
+    ws_v1
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![1], vec![1])
+        .unwrap();
+    assert_eq!(ws_v1.state_root().unwrap(), expected_root1);
+    assert_eq!(ws_v1.state_root().unwrap(), expected_root1);
+    ws_v1
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![2], vec![2])
+        .unwrap();
+    assert_eq!(ws_v1.state_root().unwrap(), expected_root2);
+    // the changes are kept for close
+    let changes = ws_v1.close().unwrap();
+    assert_eq!(changes.new_root_hash, expected_root2);
+    env_1.db.apply_changes(changes);
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, expected_root2);
+    assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 200);
+    assert_eq!(
+        ws_v1
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![1])
+            .unwrap(),
+        Some(vec![1])
+    );
+    assert_eq!(
+        ws_v1
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![2])
+            .unwrap(),
+        Some(vec![2])
+    );
+    // ================ Version2 ================
+    // state roots by committing the changes of each transaction
+    let expected_env_2 = TestEnv::default();
+    let (state_root, addresses, _) = expected_env_2.commit_genesis::<V2>();
+    let (contract, user) = (expected_env_2.address, addresses[1]);
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&expected_env_2.db, state_root);
+    ws_v2.account_trie_mut().set_balance(&user, 200).unwrap();
+    ws_v2
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![1], vec![1])
+        .unwrap();
+    let expected_root1 = ws_v2.commit().unwrap();
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&expected_env_2.db, expected_root1);
+    ws_v2
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![2], vec![2])
+        .unwrap();
+    let expected_root2 = ws_v2.commit().unwrap();
+
+    let env_2 = TestEnv::default();
+    let (state_root_v2, _, _) = env_2.commit_genesis::<V2>();
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, state_root_v2);
+    ws_v2.account_trie_mut().set_balance(&user, 200).unwrap();
+    ws_v2
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![1], vec![1])
+        .unwrap();
+    assert_eq!(ws_v2.state_root().unwrap(), expected_root1);
+    assert_eq!(ws_v2.state_root().unwrap(), expected_root1);
+    ws_v2
+        .storage_trie_mut(&contract)
+        .unwrap()
+        .set(&vec![2], vec![2])
+        .unwrap();
+    assert_eq!(ws_v2.state_root().unwrap(), expected_root2);
+    // the changes are kept for close
+    let changes = ws_v2.close().unwrap();
+    assert_eq!(changes.new_root_hash, expected_root2);
+    env_2.db.apply_changes(changes);
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, expected_root2);
+    assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 200);
+    assert_eq!(
+        ws_v2
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![1])
+            .unwrap(),
+        Some(vec![1])
+    );
+    assert_eq!(
+        ws_v2
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![2])
+            .unwrap(),
+        Some(vec![2])
+    );
+}
+
+/// `execute_block` executes a block setting the balance of the user and a storage key of the contract
+fn execute_block<S: DB + Send + Sync + Clone, V: VersionProvider + Send + Sync + Clone>(
+    db: &S,
+    state_root: Sha256Hash,
+    contract: &PublicAddress,
+    user: &PublicAddress,
+    balance: u64,
+    key: u8,
+) -> WorldStateChanges {
+    let mut ws = WorldState::<S, V>::open(db, state_root);
+    ws.account_trie_mut().set_balance(user, balance).unwrap();
+    ws.storage_trie_mut(contract)
+        .unwrap()
+        .set(&vec![key], vec![key])
+        .unwrap();
+    ws.close().unwrap()
+}
+
+#[test]
+pub fn block_overlay() {
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (genesis_root_v1, addresses, _) = env_1.commit_genesis::<V1>();
+    let (contract, user) = (env_1.address, addresses[1]);
+    let db = &env_1.db;
+
+    // block a on top of the database, b1 and b2 on top of a, and c on top of b1
+    let mut overlay = BlockOverlay::new(db);
+    let changes = execute_block::<MemoryDB, V1>(db, genesis_root_v1, &contract, &user, 1, 1);
+    overlay.insert([0xa; 32], None, changes).unwrap();
+    for (block_hash, parent, balance, key) in [
+        ([0xb1; 32], [0xa; 32], 2, 2),
+        ([0xb2; 32], [0xa; 32], 3, 3),
+        ([0xc; 32], [0xb1; 32], 4, 4),
+    ] {
+        let changes = {
+            let view = overlay.view(&parent).unwrap();
+            let state_root = overlay.state_root(&parent).unwrap();
+            execute_block::<OverlayView<MemoryDB>, V1>(
+                &view, state_root, &contract, &user, balance, key,
+            )
+        };
+        overlay.insert(block_hash, Some(parent), changes).unwrap();
+    }
+    assert_eq!(overlay.len(), 4);
+    assert_eq!(
+        overlay.insert(
+            [0xc; 32],
+            Some([0xb1; 32]),
+            execute_block::<MemoryDB, V1>(db, genesis_root_v1, &contract, &user, 0, 0)
+        ),
+        Err(OverlayError::DuplicateBlock)
+    );
+    assert_eq!(
+        overlay.insert(
+            [0xd; 32],
+            Some([0xe; 32]),
+            execute_block::<MemoryDB, V1>(db, genesis_root_v1, &contract, &user, 0, 0)
+        ),
+        Err(OverlayError::UnknownBlock)
+    );
+    assert!(overlay.view(&[0xd; 32]).is_err());
+    // the database is not changed by the speculative blocks
+    let ws_v1 = WorldState::<MemoryDB, V1>::open(db, genesis_root_v1);
+    assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 100);
+    {
+        let view = overlay.view(&[0xc; 32]).unwrap();
+        let mut ws_v1 = WorldState::<OverlayView<MemoryDB>, V1>::open(
+            &view,
+            overlay.state_root(&[0xc; 32]).unwrap(),
+        );
+        assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 4);
+        let storage_trie = ws_v1.storage_trie(&contract).unwrap();
+        for key in [1, 2, 4] {
+            assert_eq!(storage_trie.get(&vec![key]).unwrap(), Some(vec![key]));
+        }
+        assert_eq!(storage_trie.get(&vec![3]).unwrap(), None);
+    }
+    {
+        let view = overlay.view(&[0xb2; 32]).unwrap();
+        let mut ws_v1 = WorldState::<OverlayView<MemoryDB>, V1>::open(
+            &view,
+            overlay.state_root(&[0xb2; 32]).unwrap(),
+        );
+        assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 3);
+        let storage_trie = ws_v1.storage_trie(&contract).unwrap();
+        assert_eq!(storage_trie.get(&vec![2]).unwrap(), None);
+        assert_eq!(storage_trie.get(&vec![3]).unwrap(), Some(vec![3]));
+    }
+    // commit the branch of b1, discarding b2
+    let b1_root = overlay.commit(&[0xb1; 32]).unwrap();
+    assert_eq!(overlay.len(), 1);
+    assert!(overlay.contains(&[0xc; 32]));
+    assert!(!overlay.contains(&[0xb2; 32]));
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(db, b1_root);
+    assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 2);
+    assert_eq!(
+        ws_v1
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![1])
+            .unwrap(),
+        Some(vec![1])
+    );
+    // c is on top of the database now
+    {
+        let view = overlay.view(&[0xc; 32]).unwrap();
+        let ws_v1 = WorldState::<OverlayView<MemoryDB>, V1>::open(
+            &view,
+            overlay.state_root(&[0xc; 32]).unwrap(),
+        );
+        assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 4);
+    }
+    let c_root = overlay.commit(&[0xc; 32]).unwrap();
+    assert!(overlay.is_empty());
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(db, c_root);
+    assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 4);
+    assert_eq!(
+        ws_v1
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![4])
+            .unwrap(),
+        Some(vec![4])
+    );
+    // a discarded branch cannot be committed
+    let changes = execute_block::<MemoryDB, V1>(db, c_root, &contract, &user, 5, 5);
+    overlay.insert([0xf; 32], None, changes).unwrap();
+    overlay.discard(&[0xf; 32]).unwrap();
+    assert!(overlay.commit(&[0xf; 32]).is_err());
+
+    // a trie node deleted by a block and inserted again by its child, by setting the balance back, is read from the child
+    let env_1 = TestEnv::default();
+    let (genesis_root_v1, _, _) = env_1.commit_genesis::<V1>();
+    let mut overlay = BlockOverlay::new(&env_1.db);
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, genesis_root_v1);
+    ws_v1.account_trie_mut().set_balance(&user, 1).unwrap();
+    overlay
+        .insert([0xa; 32], None, ws_v1.close().unwrap())
+        .unwrap();
+    let changes = {
+        let view = overlay.view(&[0xa; 32]).unwrap();
+        let mut ws_v1 = WorldState::<OverlayView<MemoryDB>, V1>::open(
+            &view,
+            overlay.state_root(&[0xa; 32]).unwrap(),
+        );
+        ws_v1.account_trie_mut().set_balance(&user, 100).unwrap();
+        ws_v1.close().unwrap()
+    };
+    assert_eq!(changes.new_root_hash, genesis_root_v1);
+    overlay.insert([0xb; 32], Some([0xa; 32]), changes).unwrap();
+    let view = overlay.view(&[0xb; 32]).unwrap();
+    let ws_v1 = WorldState::<OverlayView<MemoryDB>, V1>::open(&view, genesis_root_v1);
+    assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 100);
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (genesis_root_v2, addresses, _) = env_2.commit_genesis::<V2>();
+    let (contract, user) = (env_2.address, addresses[1]);
+    let db = &env_2.db;
+
+    // block a on top of the database, b1 and b2 on top of a, and c on top of b1
+    let mut overlay = BlockOverlay::new(db);
+    let changes = execute_block::<MemoryDB, V2>(db, genesis_root_v2, &contract, &user, 1, 1);
+    overlay.insert([0xa; 32], None, changes).unwrap();
+    for (block_hash, parent, balance, key) in [
+        ([0xb1; 32], [0xa; 32], 2, 2),
+        ([0xb2; 32], [0xa; 32], 3, 3),
+        ([0xc; 32], [0xb1; 32], 4, 4),
+    ] {
+        let changes = {
+            let view = overlay.view(&parent).unwrap();
+            let state_root = overlay.state_root(&parent).unwrap();
+            execute_block::<OverlayView<MemoryDB>, V2>(
+                &view, state_root, &contract, &user, balance, key,
+            )
+        };
+        overlay.insert(block_hash, Some(parent), changes).unwrap();
+    }
+    assert_eq!(overlay.len(), 4);
+    assert_eq!(
+        overlay.insert(
+            [0xc; 32],
+            Some([0xb1; 32]),
+            execute_block::<MemoryDB, V2>(db, genesis_root_v2, &contract, &user, 0, 0)
+        ),
+        Err(OverlayError::DuplicateBlock)
+    );
+    assert_eq!(
+        overlay.insert(
+            [0xd; 32],
+            Some([0xe; 32]),
+            execute_block::<MemoryDB, V2>(db, genesis_root_v2, &contract, &user, 0, 0)
+        ),
+        Err(OverlayError::UnknownBlock)
+    );
+    assert!(overlay.view(&[0xd; 32]).is_err());
+    // the database is not changed by the speculative blocks
+    let ws_v2 = WorldState::<MemoryDB, V2>::open(db, genesis_root_v2);
+    assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 100);
+    {
+        let view = overlay.view(&[0xc; 32]).unwrap();
+        let mut ws_v2 = WorldState::<OverlayView<MemoryDB>, V2>::open(
+            &view,
+            overlay.state_root(&[0xc; 32]).unwrap(),
+        );
+        assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 4);
+        let storage_trie = ws_v2.storage_trie(&contract).unwrap();
+        for key in [1, 2, 4] {
+            assert_eq!(storage_trie.get(&vec![key]).unwrap(), Some(vec![key]));
+        }
+        assert_eq!(storage_trie.get(&vec![3]).unwrap(), None);
+    }
+    {
+        let view = overlay.view(&[0xb2; 32]).unwrap();
+        let mut ws_v2 = WorldState::<OverlayView<MemoryDB>, V2>::open(
+            &view,
+            overlay.state_root(&[0xb2; 32]).unwrap(),
+        );
+        assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 3);
+        let storage_trie = ws_v2.storage_trie(&contract).unwrap();
+        assert_eq!(storage_trie.get(&vec![2]).unwrap(), None);
+        assert_eq!(storage_trie.get(&vec![3]).unwrap(), Some(vec![3]));
+    }
+    // commit the branch of b1, discarding b2
+    let b1_root = overlay.commit(&[0xb1; 32]).unwrap();
+    assert_eq!(overlay.len(), 1);
+    assert!(overlay.contains(&[0xc; 32]));
+    assert!(!overlay.contains(&[0xb2; 32]));
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(db, b1_root);
+    assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 2);
+    assert_eq!(
+        ws_v2
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![1])
+            .unwrap(),
+        Some(vec![1])
+    );
+    // c is on top of the database now
+    {
+        let view = overlay.view(&[0xc; 32]).unwrap();
+        let ws_v2 = WorldState::<OverlayView<MemoryDB>, V2>::open(
+            &view,
+            overlay.state_root(&[0xc; 32]).unwrap(),
+        );
+        assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 4);
+    }
+    let c_root = overlay.commit(&[0xc; 32]).unwrap();
+    assert!(overlay.is_empty());
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(db, c_root);
+    assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 4);
+    assert_eq!(
+        ws_v2
+            .storage_trie(&contract)
+            .unwrap()
+            .get(&vec![4])
+            .unwrap(),
+        Some(vec![4])
+    );
+    // a discarded branch cannot be committed
+    let changes = execute_block::<MemoryDB, V2>(db, c_root, &contract, &user, 5, 5);
+    overlay.insert([0xf; 32], None, changes).unwrap();
+    overlay.discard(&[0xf; 32]).unwrap();
+    assert!(overlay.commit(&[0xf; 32]).is_err());
+
+    // a trie node deleted by a block and inserted again by its child, by setting the balance back, is read from the child
+    let env_2 = TestEnv::default();
+    let (genesis_root_v2, _, _) = env_2.commit_genesis::<V2>();
+    let mut overlay = BlockOverlay::new(&env_2.db);
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, genesis_root_v2);
+    ws_v2.account_trie_mut().set_balance(&user, 1).unwrap();
+    overlay
+        .insert([0xa; 32], None, ws_v2.close().unwrap())
+        .unwrap();
+    let changes = {
+        let view = overlay.view(&[0xa; 32]).unwrap();
+        let mut ws_v2 = WorldState::<OverlayView<MemoryDB>, V2>::open(
+            &view,
+            overlay.state_root(&[0xa; 32]).unwrap(),
+        );
+        ws_v2.account_trie_mut().set_balance(&user, 100).unwrap();
+        ws_v2.close().unwrap()
+    };
+    assert_eq!(changes.new_root_hash, genesis_root_v2);
+    overlay.insert([0xb; 32], Some([0xa; 32]), changes).unwrap();
+    let view = overlay.view(&[0xb; 32]).unwrap();
+    let ws_v2 = WorldState::<OverlayView<MemoryDB>, V2>::open(&view, genesis_root_v2);
+    assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 100);
+}
+
+#[test]
+pub fn merge_changes() {
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let (genesis_root_v1, addresses, _) = env_1.commit_genesis::<V1>();
+    let (contract, user) = (env_1.address, addresses[1]);
+    let batch_db = env_1.db.snapshot();
+    // apply the changes of each block one after another
+    let mut state_root = genesis_root_v1;
+    let mut block_changes = Vec::new();
+    for i in 1..=3_u8 {
+        let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&env_1.db, state_root);
+        ws_v1
+            .account_trie_mut()
+            .set_balance(&user, i as u64)
+            .unwrap();
+        let storage_trie = ws_v1.storage_trie_mut(&contract).unwrap();
+        storage_trie.set(&vec![i], vec![i]).unwrap();
+        if i == 3 {
+            storage_trie.remove(&vec![1]).unwrap();
+        }
+        let changes = ws_v1.close().unwrap();
+        state_root = changes.new_root_hash;
+        env_1.db.apply_changes(changes.clone());
+        block_changes.push(changes);
+    }
+    // trie nodes inserted in a block and deleted in the next block are not inserted by the merged changes
+    let replaced: Vec<Vec<u8>> = block_changes[0]
+        .inserts
+        .keys()
+        .filter(|key| block_changes[1].deletes.contains(*key))
+        .cloned()
+        .collect();
+    assert!(!replaced.is_empty());
+    assert!(WorldStateChanges::merge_all(Vec::new()).is_none());
+    let merged = WorldStateChanges::merge_all(block_changes).unwrap();
+    assert_eq!(merged.new_root_hash, state_root);
+    assert!(merged
+        .inserts
+        .keys()
+        .all(|key| !merged.deletes.contains(key)));
+    for key in replaced.iter() {
+        assert!(!merged.inserts.contains_key(key));
+    }
+    batch_db.apply_changes(merged);
+    assert_eq!(batch_db.stats(), env_1.db.stats());
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::open(&batch_db, state_root);
+    assert_eq!(ws_v1.account_trie().balance(&user).unwrap(), 3);
+    let storage_trie = ws_v1.storage_trie(&contract).unwrap();
+    assert_eq!(storage_trie.get(&vec![1]).unwrap(), None);
+    assert_eq!(storage_trie.get(&vec![2]).unwrap(), Some(vec![2]));
+    assert_eq!(storage_trie.get(&vec![3]).unwrap(), Some(vec![3]));
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let (genesis_root_v2, addresses, _) = env_2.commit_genesis::<V2>();
+    let (contract, user) = (env_2.address, addresses[1]);
+    let batch_db = env_2.db.snapshot();
+    // apply the changes of each block one after another
+    let mut state_root = genesis_root_v2;
+    let mut block_changes = Vec::new();
+    for i in 1..=3_u8 {
+        let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&env_2.db, state_root);
+        ws_v2
+            .account_trie_mut()
+            .set_balance(&user, i as u64)
+            .unwrap();
+        let storage_trie = ws_v2.storage_trie_mut(&contract).unwrap();
+        storage_trie.set(&vec![i], vec![i]).unwrap();
+        if i == 3 {
+            storage_trie.remove(&vec![1]).unwrap();
+        }
+        let changes = ws_v2.close().unwrap();
+        state_root = changes.new_root_hash;
+        env_2.db.apply_changes(changes.clone());
+        block_changes.push(changes);
+    }
+    // trie nodes inserted in a block and deleted in the next block are not inserted by the merged changes
+    let replaced: Vec<Vec<u8>> = block_changes[0]
+        .inserts
+        .keys()
+        .filter(|key| block_changes[1].deletes.contains(*key))
+        .cloned()
+        .collect();
+    assert!(!replaced.is_empty());
+    assert!(WorldStateChanges::merge_all(Vec::new()).is_none());
+    let merged = WorldStateChanges::merge_all(block_changes).unwrap();
+    assert_eq!(merged.new_root_hash, state_root);
+    assert!(merged
+        .inserts
+        .keys()
+        .all(|key| !merged.deletes.contains(key)));
+    for key in replaced.iter() {
+        assert!(!merged.inserts.contains_key(key));
+    }
+    batch_db.apply_changes(merged);
+    assert_eq!(batch_db.stats(), env_2.db.stats());
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::open(&batch_db, state_root);
+    assert_eq!(ws_v2.account_trie().balance(&user).unwrap(), 3);
+    let storage_trie = ws_v2.storage_trie(&contract).unwrap();
+    assert_eq!(storage_trie.get(&vec![1]).unwrap(), None);
+    assert_eq!(storage_trie.get(&vec![2]).unwrap(), Some(vec![2]));
+    assert_eq!(storage_trie.get(&vec![3]).unwrap(), Some(vec![3]));
+}
+
+#[test]
+pub fn serialize_changes() {
+    let db = MemoryDB::new();
+    let mut ws = WorldState::<MemoryDB, V2>::new(&db);
+    for i in 1..=10_u8 {
+        ws.account_trie_mut()
+            .set_balance(&[i; 32], i as u64)
+            .unwrap();
+    }
+    let genesis_changes = ws.close().unwrap();
+    db.apply_changes(genesis_changes.clone());
+    let mut ws = WorldState::<MemoryDB, V2>::open(&db, genesis_changes.new_root_hash);
+    ws.account_trie_mut().set_balance(&[1; 32], 100).unwrap();
+    let changes = ws.close().unwrap();
+    assert!(!changes.inserts.is_empty() && !changes.deletes.is_empty());
+
+    // same bytes and content hash regardless of the order in the HashMap and HashSet
+    let mut inserts: Vec<(Key, Value)> = changes.inserts.clone().into_iter().collect();
+    let mut deletes: Vec<Key> = changes.deletes.iter().cloned().collect();
+    inserts.sort();
+    deletes.sort();
+    let reordered = WorldStateChanges {
+        inserts: inserts.into_iter().rev().collect(),
+        deletes: deletes.into_iter().rev().collect(),
+        new_root_hash: changes.new_root_hash,
+    };
+    let bytes = changes.to_bytes();
+    assert_eq!(bytes, reordered.to_bytes());
+    assert_eq!(changes.content_hash(), reordered.content_hash());
+    assert_ne!(changes.content_hash(), genesis_changes.content_hash());
+
+    // replay the deserialized changes
+    let decoded = WorldStateChanges::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded, changes);
+    assert_eq!(decoded.to_bytes(), bytes);
+    let replay_db = db.snapshot();
+    replay_db.apply_changes(decoded);
+    db.apply_changes(changes.clone());
+    assert_eq!(replay_db.stats(), db.stats());
+    let ws = WorldState::<MemoryDB, V2>::open(&replay_db, changes.new_root_hash);
+    assert_eq!(ws.account_trie().balance(&[1; 32]).unwrap(), 100);
+
+    // unsorted keys are rejected
+    let mut unsorted = Vec::new();
+    unsorted.extend(2_u32.to_le_bytes());
+    for key in [2_u8, 1] {
+        unsorted.extend(1_u32.to_le_bytes());
+        unsorted.push(key);
+        unsorted.extend(1_u32.to_le_bytes());
+        unsorted.push(key);
+    }
+    unsorted.extend(0_u32.to_le_bytes());
+    unsorted.extend([0; 32]);
+    assert!(WorldStateChanges::from_bytes(&unsorted).is_err());
+    // the same changes with sorted keys
+    let mut sorted = unsorted[..4].to_vec();
+    sorted.extend(&unsorted[14..24]);
+    sorted.extend(&unsorted[4..14]);
+    sorted.extend(&unsorted[24..]);
+    let decoded = WorldStateChanges::from_bytes(&sorted).unwrap();
+    assert_eq!(decoded.inserts.len(), 2);
+    assert_eq!(decoded.to_bytes(), sorted);
+}
+
+#[test]
+pub fn golden_state_root() {
+    let key_apple: Key = b"apple".to_vec();
+    let value_apple: Value = b"1234".to_vec();
+    // ================ Version1 ================
+    let env_1 = TestEnv::default();
+    let mut ws_v1 = WorldState::<MemoryDB, V1>::new(&env_1.db);
+    ws_v1
+        .account_trie_mut()
+        .set_nonce(&env_1.address, 1)
+        .unwrap();
+    ws_v1
+        .account_trie_mut()
+        .set_balance(&env_1.address, 100)
+        .unwrap();
+    ws_v1
+        .account_trie_mut()
+        .set_code(&env_1.address, vec![1; 100])
+        .unwrap();
+    let storage_trie = ws_v1.storage_trie_mut(&env_1.address).unwrap();
+    storage_trie.set(&key_apple, value_apple.clone()).unwrap();
+    storage_trie.set(&vec![2; 40], vec![2; 40]).unwrap();
+    let root_hash_v1 = ws_v1.commit().unwrap();
+    // the trie layout and hasher must not change the V1 state root
+    assert_eq!(
+        root_hash_v1,
+        [
+            25, 150, 194, 107, 206, 170, 221, 253, 28, 142, 48, 109, 195, 234, 154, 181, 89, 122,
+            0, 202, 90, 79, 148, 56, 33, 199, 37, 181, 75, 50, 61, 73
+        ]
+    );
+
+    // ================ Version2 ================
+    let env_2 = TestEnv::default();
+    let mut ws_v2 = WorldState::<MemoryDB, V2>::new(&env_2.db);
+    ws_v2
+        .account_trie_mut()
+        .set_nonce(&env_2.address, 1)
+        .unwrap();
+    ws_v2
+        .account_trie_mut()
+        .set_balance(&env_2.address, 100)
+        .unwrap();
+    ws_v2
+        .account_trie_mut()
+        .set_code(&env_2.address, vec![1; 100])
+        .unwrap();
+    let storage_trie = ws_v2.storage_trie_mut(&env_2.address).unwrap();
+    storage_trie.set(&key_apple, value_apple).unwrap();
+    storage_trie.set(&vec![2; 40], vec![2; 40]).unwrap();
+    let root_hash_v2 = ws_v2.commit().unwrap();
+    // the trie layout and hasher must not change the V2 state root
+    assert_eq!(
+        root_hash_v2,
+        [
+            218, 140, 141, 249, 226, 84, 225, 13, 62, 12, 149, 206, 253, 12, 26, 87, 158, 161, 218,
+            219, 108, 244, 212, 133, 2, 65, 130, 18, 164, 208, 81, 97
+        ]
+    );
+}
+
 /// The following tests are for network functions
 ///
 /// This part does not change during upgrading to protocal V0.5
@@ -1064,7 +4344,7 @@ where
 #[test]
 fn test_network_account() {
     let env = TestEnv::default();
-    let mut ws = StorageWorldState::<DummyStorage, V2>::initialize(&env.db);
+    let mut ws = StorageWorldState::<MemoryDB, V2>::initialize(&env.db);
 
     // No values are set at initialization
     assert_eq!(NetworkAccount::new(&mut ws).current_epoch(), 0);
@@ -1125,7 +4405,7 @@ fn test_network_account() {
 #[test]
 fn test_network_account_validator_set() {
     let env = TestEnv::default();
-    let mut ws = StorageWorldState::<DummyStorage, V1>::initialize(&env.db);
+    let mut ws = StorageWorldState::<MemoryDB, V1>::initialize(&env.db);
 
     let pool_1 = Pool {
         operator: [1u8; 32],